@@ -1,13 +1,29 @@
-use crate::value::{Value, ValueContainer};
+use std::{cell::RefCell, mem, rc::Rc};
 
-const LONGEST_INSTRUCTION_LENGTH: usize = 3;
+use crate::{
+    inline_cache::InlineCache,
+    value::{Value, ValueContainer},
+};
 
-pub const JUMP_INSTRUCTION_ARGUMENT_LENGTH: usize = 2;
+const LONGEST_INSTRUCTION_LENGTH: usize = 6;
 
+/// Jump offsets are always 3 bytes long, as the length of the forward jump isn't known when the jump is emitted
+pub const JUMP_INSTRUCTION_ARGUMENT_LENGTH: usize = 3;
+/// The biggest number of bytes that can be skipped by the jump instruction
+pub const MAX_JUMP_ARGUMENT: u32 = 0x00ff_ffff;
+
+/// Byte put before the operation code, when its index operand doesn't fit in a single byte
+const WIDE_PREFIX: u8 = u8::MAX;
+/// Number of bytes used by the index operand of the wide instruction
+const WIDE_INDEX_ARGUMENT_LENGTH: usize = 3;
+/// The biggest index (of constant, local variable, upvalue, etc.) that can be encoded in the instruction
+pub const MAX_INDEX_ARGUMENT: u32 = 0x00ff_ffff;
+
+#[derive(Clone, Copy)]
 pub enum OperationCode {
     Return,
     /// Load constant operation, arguments: (constant index in `ValueContainer`)
-    Constant(u8),
+    Constant(u32),
     Nil,
     True,
     False,
@@ -17,106 +33,253 @@ pub enum OperationCode {
     Substract,
     Multiply,
     Divide,
+    Modulo,
+    Power,
+    IntegerDivide,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    BitwiseNot,
+    ShiftLeft,
+    ShiftRight,
     Equal,
     Greater,
     Less,
     Print,
     PopStack,
     /// Define global variable, arguments: (variable index in `ValueContainer`)
-    DefineGlobal(u8),
+    DefineGlobal(u32),
     /// Get global variable, arguments: (variable index in `ValueContainer`)
-    GetGlobal(u8),
+    GetGlobal(u32),
     /// Set global variable, arguments: (variable index in `ValueContainer`)
-    SetGlobal(u8),
+    SetGlobal(u32),
     /// Get local variable, arguments: (variable index in `ValueContainer`)
-    GetLocal(u8),
+    GetLocal(u32),
     /// Set local variable, arguments: (variable index in `ValueContainer`)
-    SetLocal(u8),
+    SetLocal(u32),
     /// Jump if stack top is false, arguments: (number of bytes to skip)
-    JumpIfFalse(u16),
+    JumpIfFalse(u32),
     /// Jump unconditionally, arguments: (number of bytes to skip)
-    Jump(u16),
+    Jump(u32),
     /// Jump if stack top is true, arguments: (number of bytes to skip)
-    JumpIfTrue(u16),
+    JumpIfTrue(u32),
     /// Jump back unconditionally, arguments: (number of bytes to skip)
-    JumpBack(u16),
+    JumpBack(u32),
     /// Call function/method, arguments: (number of call arguments)
     Call(u8),
     /// Create closure from function, arguments: (function index in `ValueContainer`)
-    Closure(u8),
+    Closure(u32),
     /// Create local upvalue (can only be used right after closure or other local/nonlocal upvalue), arguments: (upvalue index)
-    LocalUpvalue(u8),
+    LocalUpvalue(u32),
     /// Create non local upvalue (can only be used right after closure or other local/nonlocal upvalue), arguments: (upvalue index)
-    NonLocalUpvalue(u8),
+    NonLocalUpvalue(u32),
     // Get upvalue, arguments: (upvalue index)
-    GetUpvalue(u8),
+    GetUpvalue(u32),
     // Set upvalue, arguments: (upvalue index)
-    SetUpvalue(u8),
+    SetUpvalue(u32),
     CloseUpvalue,
     /// Create class with given name, arguments: (class name index in `ValueContainer`)
-    Class(u8),
+    Class(u32),
     // Get value of class property, arguments: (property name index in `ValueContainer`)
-    GetProperty(u8),
+    GetProperty(u32),
     // Set value of class property, arguments: (property name index in `ValueContainer`)
-    SetProperty(u8),
+    SetProperty(u32),
     // Bind method to class, arguments: (method name index in `ValueContainer`)
-    Method(u8),
+    Method(u32),
     // Operation for getting and calling property, arguments: (property name index in `ValueContainer`, number of call arguments)
     // This operation doesn't provide new functionality, but rather optimize existing ones, chaining together `GetProperty` and `Call``
-    InvokeProperty(u8, u8),
+    InvokeProperty(u32, u8),
     Inherit,
     // Get method from super class, arguments: (method name index in `ValueContainer`)
-    GetSuper(u8),
+    GetSuper(u32),
     // Operation for getting and calling super methpd, arguments: (super method name index in `ValueContainer`, number of call arguments)
     // This operation doesn't provide new functionality, but rather optimize existing ones, chaining together `GetSuper` and `Call``
-    InvokeSuperMethod(u8, u8),
+    InvokeSuperMethod(u32, u8),
+    /// Create list from values on the stack top, arguments: (number of list items)
+    BuildList(u32),
+    /// Get list item or map value, list/map and index/key are taken from the stack top
+    GetIndex,
+    /// Set list item or map value, list/map, index/key and new value are taken from the stack top
+    SetIndex,
+    /// Create map from keys and values on the stack top (stored one after another), arguments: (number of map entries)
+    BuildMap(u32),
+    /// Convert value on the stack top to string (used by string interpolation)
+    Stringify,
+    /// Register exception handler of the current function, arguments: (number of bytes to skip to get to the handler)
+    PushHandler(u32),
+    /// Remove the most recently registered exception handler of the current function
+    PopHandler,
+    /// Throw value from the stack top
+    Throw,
+    /// Define global variable that can't be assigned later, arguments: (variable index in `ValueContainer`)
+    DefineConstantGlobal(u32),
+    /// Import module (executing it if it wasn't imported yet), arguments: (module path index in `ValueContainer`)
+    Import(u32),
+    /// Define static field (or static method) of the class that is right below it on the stack,
+    /// arguments: (field name index in `ValueContainer`)
+    StaticField(u32),
+    /// Define getter of the class property, arguments: (property name index in `ValueContainer`)
+    Getter(u32),
+    /// Define setter of the class property, arguments: (property name index in `ValueContainer`)
+    Setter(u32),
+    /// Create trait with given name, arguments: (trait name index in `ValueContainer`)
+    Trait(u32),
+    /// Copy methods of the trait from the stack top into the class right below it
+    Mixin,
+    /// Suspend the running coroutine, the value from the stack top is the result of the `resume` that resumed it
+    Yield,
+    /// Resume the coroutine right below the stack top, the value from the stack top is the result of its `yield`
+    Resume,
 }
 
 impl OperationCode {
     pub fn get_instruction_bytes_length(code: &Self) -> usize {
         match code {
-            OperationCode::Return => 1,
-            OperationCode::Constant(_) => 2,
-            OperationCode::Negate => 1,
-            OperationCode::Add => 1,
-            OperationCode::Substract => 1,
-            OperationCode::Multiply => 1,
-            OperationCode::Divide => 1,
-            OperationCode::Nil => 1,
-            OperationCode::True => 1,
-            OperationCode::False => 1,
-            OperationCode::Not => 1,
-            OperationCode::Equal => 1,
-            OperationCode::Greater => 1,
-            OperationCode::Less => 1,
-            OperationCode::Print => 1,
-            OperationCode::PopStack => 1,
-            OperationCode::DefineGlobal(_) => 2,
-            OperationCode::GetGlobal(_) => 2,
-            OperationCode::SetGlobal(_) => 2,
-            OperationCode::GetLocal(_) => 2,
-            OperationCode::SetLocal(_) => 2,
-            OperationCode::JumpIfFalse(_) => 3,
-            OperationCode::Jump(_) => 3,
-            OperationCode::JumpIfTrue(_) => 3,
-            OperationCode::JumpBack(_) => 3,
+            OperationCode::Return
+            | OperationCode::Negate
+            | OperationCode::Add
+            | OperationCode::Substract
+            | OperationCode::Multiply
+            | OperationCode::Divide
+            | OperationCode::Modulo
+            | OperationCode::Power
+            | OperationCode::IntegerDivide
+            | OperationCode::BitwiseAnd
+            | OperationCode::BitwiseOr
+            | OperationCode::BitwiseXor
+            | OperationCode::BitwiseNot
+            | OperationCode::ShiftLeft
+            | OperationCode::ShiftRight
+            | OperationCode::Nil
+            | OperationCode::True
+            | OperationCode::False
+            | OperationCode::Not
+            | OperationCode::Equal
+            | OperationCode::Greater
+            | OperationCode::Less
+            | OperationCode::Print
+            | OperationCode::PopStack
+            | OperationCode::CloseUpvalue
+            | OperationCode::Inherit
+            | OperationCode::GetIndex
+            | OperationCode::SetIndex
+            | OperationCode::Stringify
+            | OperationCode::PopHandler
+            | OperationCode::Throw
+            | OperationCode::Mixin
+            | OperationCode::Yield
+            | OperationCode::Resume => 1,
+            OperationCode::Constant(index)
+            | OperationCode::DefineGlobal(index)
+            | OperationCode::GetGlobal(index)
+            | OperationCode::SetGlobal(index)
+            | OperationCode::GetLocal(index)
+            | OperationCode::SetLocal(index)
+            | OperationCode::Closure(index)
+            | OperationCode::LocalUpvalue(index)
+            | OperationCode::NonLocalUpvalue(index)
+            | OperationCode::GetUpvalue(index)
+            | OperationCode::SetUpvalue(index)
+            | OperationCode::Class(index)
+            | OperationCode::GetProperty(index)
+            | OperationCode::SetProperty(index)
+            | OperationCode::Method(index)
+            | OperationCode::GetSuper(index)
+            | OperationCode::BuildList(index)
+            | OperationCode::BuildMap(index)
+            | OperationCode::DefineConstantGlobal(index)
+            | OperationCode::Import(index)
+            | OperationCode::StaticField(index)
+            | OperationCode::Getter(index)
+            | OperationCode::Setter(index)
+            | OperationCode::Trait(index) => 1 + Self::get_index_argument_length(*index),
+            OperationCode::InvokeProperty(index, _)
+            | OperationCode::InvokeSuperMethod(index, _) => {
+                2 + Self::get_index_argument_length(*index)
+            }
+            OperationCode::JumpIfFalse(_)
+            | OperationCode::Jump(_)
+            | OperationCode::JumpIfTrue(_)
+            | OperationCode::JumpBack(_)
+            | OperationCode::PushHandler(_) => 1 + JUMP_INSTRUCTION_ARGUMENT_LENGTH,
             OperationCode::Call(_) => 2,
-            OperationCode::Closure(_) => 2,
-            OperationCode::LocalUpvalue(_) => 2,
-            OperationCode::NonLocalUpvalue(_) => 2,
-            OperationCode::GetUpvalue(_) => 2,
-            OperationCode::SetUpvalue(_) => 2,
-            OperationCode::CloseUpvalue => 1,
-            OperationCode::Class(_) => 2,
-            OperationCode::GetProperty(_) => 2,
-            OperationCode::SetProperty(_) => 2,
-            OperationCode::Method(_) => 2,
-            OperationCode::InvokeProperty(_, _) => 3,
-            OperationCode::Inherit => 1,
-            OperationCode::GetSuper(_) => 2,
-            OperationCode::InvokeSuperMethod(_, _) => 3,
         }
     }
+
+    /// Length of the index argument, including wide prefix if it's needed
+    fn get_index_argument_length(index: u32) -> usize {
+        if index > u8::MAX as u32 {
+            return WIDE_INDEX_ARGUMENT_LENGTH + 1;
+        }
+        1
+    }
+
+    fn encode_with_index(operation_code: u8, index: u32) -> Vec<u8> {
+        if index > u8::MAX as u32 {
+            let index_in_bytes = index.to_le_bytes();
+            return vec![
+                WIDE_PREFIX,
+                operation_code,
+                index_in_bytes[0],
+                index_in_bytes[1],
+                index_in_bytes[2],
+            ];
+        }
+        vec![operation_code, index as u8]
+    }
+
+    // All the decoding functions below expect `bytes` to start at the operation code (after the wide prefix)
+    fn decode_without_arguments(
+        code: OperationCode,
+        is_wide: bool,
+    ) -> Result<OperationCode, OperationCodeConversionError> {
+        if is_wide {
+            return Err(OperationCodeConversionError::InvalidFormat);
+        }
+        Ok(code)
+    }
+
+    fn decode_index(bytes: &[u8], is_wide: bool) -> Result<u32, OperationCodeConversionError> {
+        if !is_wide {
+            return Ok(Self::decode_byte(bytes, 1)? as u32);
+        }
+        if bytes.len() < 1 + WIDE_INDEX_ARGUMENT_LENGTH {
+            return Err(OperationCodeConversionError::InvalidFormat);
+        }
+        Ok(u32::from_le_bytes([bytes[1], bytes[2], bytes[3], 0]))
+    }
+
+    fn decode_index_and_byte(
+        bytes: &[u8],
+        is_wide: bool,
+    ) -> Result<(u32, u8), OperationCodeConversionError> {
+        let index = Self::decode_index(bytes, is_wide)?;
+        let index_length = if is_wide {
+            WIDE_INDEX_ARGUMENT_LENGTH
+        } else {
+            1
+        };
+        Ok((index, Self::decode_byte(bytes, 1 + index_length)?))
+    }
+
+    fn decode_byte(bytes: &[u8], position: usize) -> Result<u8, OperationCodeConversionError> {
+        bytes
+            .get(position)
+            .copied()
+            .ok_or(OperationCodeConversionError::InvalidFormat)
+    }
+
+    fn decode_jump(bytes: &[u8], is_wide: bool) -> Result<u32, OperationCodeConversionError> {
+        if is_wide || bytes.len() < 1 + JUMP_INSTRUCTION_ARGUMENT_LENGTH {
+            return Err(OperationCodeConversionError::InvalidFormat);
+        }
+        Ok(u32::from_le_bytes([bytes[1], bytes[2], bytes[3], 0]))
+    }
+
+    fn encode_jump_argument(bytes_to_skip: u32) -> [u8; JUMP_INSTRUCTION_ARGUMENT_LENGTH] {
+        let number_in_bytes = bytes_to_skip.to_le_bytes();
+        [number_in_bytes[0], number_in_bytes[1], number_in_bytes[2]]
+    }
 }
 
 impl From<OperationCode> for u8 {
@@ -162,155 +325,116 @@ impl From<OperationCode> for u8 {
             OperationCode::Inherit => 37,
             OperationCode::GetSuper(_) => 38,
             OperationCode::InvokeSuperMethod(_, _) => 39,
+            OperationCode::BuildList(_) => 40,
+            OperationCode::GetIndex => 41,
+            OperationCode::SetIndex => 42,
+            OperationCode::BuildMap(_) => 43,
+            OperationCode::Stringify => 44,
+            OperationCode::Modulo => 45,
+            OperationCode::Power => 46,
+            OperationCode::IntegerDivide => 47,
+            OperationCode::BitwiseAnd => 48,
+            OperationCode::BitwiseOr => 49,
+            OperationCode::BitwiseXor => 50,
+            OperationCode::BitwiseNot => 51,
+            OperationCode::ShiftLeft => 52,
+            OperationCode::ShiftRight => 53,
+            OperationCode::PushHandler(_) => 54,
+            OperationCode::PopHandler => 55,
+            OperationCode::Throw => 56,
+            OperationCode::DefineConstantGlobal(_) => 57,
+            OperationCode::Import(_) => 58,
+            OperationCode::StaticField(_) => 59,
+            OperationCode::Getter(_) => 60,
+            OperationCode::Setter(_) => 61,
+            OperationCode::Trait(_) => 62,
+            OperationCode::Mixin => 63,
+            OperationCode::Yield => 64,
+            OperationCode::Resume => 65,
         }
     }
 }
 
 impl From<OperationCode> for Vec<u8> {
     fn from(value: OperationCode) -> Self {
+        let operation_code = u8::from(value);
         match value {
-            OperationCode::Return => vec![u8::from(OperationCode::Return)],
-            OperationCode::Constant(constant) => {
-                vec![u8::from(OperationCode::Constant(constant)), constant]
-            }
-            OperationCode::Negate => vec![u8::from(OperationCode::Negate)],
-            OperationCode::Add => vec![u8::from(OperationCode::Add)],
-            OperationCode::Substract => vec![u8::from(OperationCode::Substract)],
-            OperationCode::Multiply => vec![u8::from(OperationCode::Multiply)],
-            OperationCode::Divide => vec![u8::from(OperationCode::Divide)],
-            OperationCode::Nil => vec![u8::from(OperationCode::Nil)],
-            OperationCode::True => vec![u8::from(OperationCode::True)],
-            OperationCode::False => vec![u8::from(OperationCode::False)],
-            OperationCode::Not => vec![u8::from(OperationCode::Not)],
-            OperationCode::Equal => vec![u8::from(OperationCode::Equal)],
-            OperationCode::Greater => vec![u8::from(OperationCode::Greater)],
-            OperationCode::Less => vec![u8::from(OperationCode::Less)],
-            OperationCode::Print => vec![u8::from(OperationCode::Print)],
-            OperationCode::PopStack => vec![u8::from(OperationCode::PopStack)],
-            OperationCode::DefineGlobal(global_var) => {
-                vec![
-                    u8::from(OperationCode::DefineGlobal(global_var)),
-                    global_var,
-                ]
-            }
-            OperationCode::GetGlobal(global_var) => {
-                vec![u8::from(OperationCode::GetGlobal(global_var)), global_var]
-            }
-            OperationCode::SetGlobal(global_var) => {
-                vec![u8::from(OperationCode::SetGlobal(global_var)), global_var]
-            }
-            OperationCode::GetLocal(local_var) => {
-                vec![u8::from(OperationCode::GetLocal(local_var)), local_var]
-            }
-            OperationCode::SetLocal(local_var) => {
-                vec![u8::from(OperationCode::SetLocal(local_var)), local_var]
-            }
-            OperationCode::JumpIfFalse(bytes_to_skip) => {
-                let number_in_bytes = bytes_to_skip.to_ne_bytes();
-                vec![
-                    u8::from(OperationCode::JumpIfFalse(bytes_to_skip)),
-                    number_in_bytes[0],
-                    number_in_bytes[1],
-                ]
-            }
-            OperationCode::Jump(bytes_to_skip) => {
-                let number_in_bytes = bytes_to_skip.to_ne_bytes();
-                vec![
-                    u8::from(OperationCode::Jump(bytes_to_skip)),
-                    number_in_bytes[0],
-                    number_in_bytes[1],
-                ]
-            }
-            OperationCode::JumpIfTrue(bytes_to_skip) => {
-                let number_in_bytes = bytes_to_skip.to_ne_bytes();
-                vec![
-                    u8::from(OperationCode::JumpIfTrue(bytes_to_skip)),
-                    number_in_bytes[0],
-                    number_in_bytes[1],
-                ]
-            }
-            OperationCode::JumpBack(bytes_to_skip) => {
-                let number_in_bytes = bytes_to_skip.to_ne_bytes();
-                vec![
-                    u8::from(OperationCode::JumpBack(bytes_to_skip)),
-                    number_in_bytes[0],
-                    number_in_bytes[1],
-                ]
-            }
-            OperationCode::Call(arguments_count) => {
-                vec![
-                    u8::from(OperationCode::Call(arguments_count)),
-                    arguments_count,
-                ]
-            }
-            OperationCode::Closure(function_index) => {
-                vec![
-                    u8::from(OperationCode::Closure(function_index)),
-                    function_index,
-                ]
-            }
-            OperationCode::LocalUpvalue(upvalue_index) => {
-                vec![
-                    u8::from(OperationCode::LocalUpvalue(upvalue_index)),
-                    upvalue_index,
-                ]
-            }
-            OperationCode::NonLocalUpvalue(upvalue_index) => {
-                vec![
-                    u8::from(OperationCode::NonLocalUpvalue(upvalue_index)),
-                    upvalue_index,
-                ]
-            }
-            OperationCode::GetUpvalue(upvalue_index) => {
-                vec![
-                    u8::from(OperationCode::GetUpvalue(upvalue_index)),
-                    upvalue_index,
-                ]
-            }
-            OperationCode::SetUpvalue(upvalue_index) => {
-                vec![
-                    u8::from(OperationCode::SetUpvalue(upvalue_index)),
-                    upvalue_index,
-                ]
-            }
-            OperationCode::CloseUpvalue => vec![u8::from(OperationCode::CloseUpvalue)],
-            OperationCode::Class(class_name_index) => vec![
-                u8::from(OperationCode::Class(class_name_index)),
-                class_name_index,
-            ],
-            OperationCode::GetProperty(property_name_index) => vec![
-                u8::from(OperationCode::GetProperty(property_name_index)),
-                property_name_index,
-            ],
-            OperationCode::SetProperty(property_name_index) => vec![
-                u8::from(OperationCode::SetProperty(property_name_index)),
-                property_name_index,
-            ],
-            OperationCode::Method(method_name_index) => vec![
-                u8::from(OperationCode::Method(method_name_index)),
-                method_name_index,
-            ],
-            OperationCode::InvokeProperty(property_name_index, arguments_count) => vec![
-                u8::from(OperationCode::InvokeProperty(
-                    property_name_index,
-                    arguments_count,
-                )),
-                property_name_index,
-                arguments_count,
-            ],
-            OperationCode::Inherit => vec![u8::from(OperationCode::Inherit)],
-            OperationCode::GetSuper(method_name_index) => vec![
-                u8::from(OperationCode::GetSuper(method_name_index)),
-                method_name_index,
-            ],
-            OperationCode::InvokeSuperMethod(method_name_index, arguments_count) => vec![
-                u8::from(OperationCode::InvokeSuperMethod(
-                    method_name_index,
-                    arguments_count,
-                )),
-                method_name_index,
-                arguments_count,
-            ],
+            OperationCode::Return
+            | OperationCode::Negate
+            | OperationCode::Add
+            | OperationCode::Substract
+            | OperationCode::Multiply
+            | OperationCode::Divide
+            | OperationCode::Modulo
+            | OperationCode::Power
+            | OperationCode::IntegerDivide
+            | OperationCode::BitwiseAnd
+            | OperationCode::BitwiseOr
+            | OperationCode::BitwiseXor
+            | OperationCode::BitwiseNot
+            | OperationCode::ShiftLeft
+            | OperationCode::ShiftRight
+            | OperationCode::Nil
+            | OperationCode::True
+            | OperationCode::False
+            | OperationCode::Not
+            | OperationCode::Equal
+            | OperationCode::Greater
+            | OperationCode::Less
+            | OperationCode::Print
+            | OperationCode::PopStack
+            | OperationCode::CloseUpvalue
+            | OperationCode::Inherit
+            | OperationCode::GetIndex
+            | OperationCode::SetIndex
+            | OperationCode::Stringify
+            | OperationCode::PopHandler
+            | OperationCode::Throw
+            | OperationCode::Mixin
+            | OperationCode::Yield
+            | OperationCode::Resume => vec![operation_code],
+            OperationCode::Constant(index)
+            | OperationCode::DefineGlobal(index)
+            | OperationCode::GetGlobal(index)
+            | OperationCode::SetGlobal(index)
+            | OperationCode::GetLocal(index)
+            | OperationCode::SetLocal(index)
+            | OperationCode::Closure(index)
+            | OperationCode::LocalUpvalue(index)
+            | OperationCode::NonLocalUpvalue(index)
+            | OperationCode::GetUpvalue(index)
+            | OperationCode::SetUpvalue(index)
+            | OperationCode::Class(index)
+            | OperationCode::GetProperty(index)
+            | OperationCode::SetProperty(index)
+            | OperationCode::Method(index)
+            | OperationCode::GetSuper(index)
+            | OperationCode::BuildList(index)
+            | OperationCode::BuildMap(index)
+            | OperationCode::DefineConstantGlobal(index)
+            | OperationCode::Import(index)
+            | OperationCode::StaticField(index)
+            | OperationCode::Getter(index)
+            | OperationCode::Setter(index)
+            | OperationCode::Trait(index) => {
+                OperationCode::encode_with_index(operation_code, index)
+            }
+            OperationCode::InvokeProperty(index, arguments_count)
+            | OperationCode::InvokeSuperMethod(index, arguments_count) => {
+                let mut bytes = OperationCode::encode_with_index(operation_code, index);
+                bytes.push(arguments_count);
+                bytes
+            }
+            OperationCode::JumpIfFalse(bytes_to_skip)
+            | OperationCode::Jump(bytes_to_skip)
+            | OperationCode::JumpIfTrue(bytes_to_skip)
+            | OperationCode::JumpBack(bytes_to_skip)
+            | OperationCode::PushHandler(bytes_to_skip) => {
+                let mut bytes = vec![operation_code];
+                bytes.extend_from_slice(&OperationCode::encode_jump_argument(bytes_to_skip));
+                bytes
+            }
+            OperationCode::Call(arguments_count) => vec![operation_code, arguments_count],
         }
     }
 }
@@ -329,251 +453,217 @@ impl TryFrom<&[u8]> for OperationCode {
         if value.is_empty() {
             return Err(OperationCodeConversionError::EmptyInput);
         }
+        // Wide prefix only changes the length of the index argument, so after skipping it, the instruction is decoded as usual
+        let is_wide = value[0] == WIDE_PREFIX;
+        let value = if is_wide { &value[1..] } else { value };
+        if value.is_empty() {
+            return Err(OperationCodeConversionError::InvalidFormat);
+        }
         match value[0] {
-            0 => Ok(OperationCode::Return),
-            1 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::Constant(u8::MIN))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::Constant(value[1]))
-            }
-            2 => Ok(OperationCode::Negate),
-            3 => Ok(OperationCode::Add),
-            4 => Ok(OperationCode::Substract),
-            5 => Ok(OperationCode::Multiply),
-            6 => Ok(OperationCode::Divide),
-            7 => Ok(OperationCode::Nil),
-            8 => Ok(OperationCode::True),
-            9 => Ok(OperationCode::False),
-            10 => Ok(OperationCode::Not),
-            11 => Ok(OperationCode::Equal),
-            12 => Ok(OperationCode::Greater),
-            13 => Ok(OperationCode::Less),
-            14 => Ok(OperationCode::Print),
-            15 => Ok(OperationCode::PopStack),
-            16 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::DefineGlobal(
-                        u8::MIN,
-                    ))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::DefineGlobal(value[1]))
-            }
-            17 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::GetGlobal(
-                        u8::MIN,
-                    ))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::GetGlobal(value[1]))
-            }
-            18 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::SetGlobal(
-                        u8::MIN,
-                    ))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::SetGlobal(value[1]))
-            }
-            19 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::GetLocal(u8::MIN))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::GetLocal(value[1]))
-            }
-            20 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::SetLocal(u8::MIN))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::SetLocal(value[1]))
-            }
-            21 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::JumpIfFalse(
-                        u16::MIN,
-                    ))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                let bytes_to_skip = u16::from_ne_bytes([value[1], value[2]]);
-                Ok(OperationCode::JumpIfFalse(bytes_to_skip))
-            }
-            22 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::Jump(u16::MIN))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                let bytes_to_skip = u16::from_ne_bytes([value[1], value[2]]);
-                Ok(OperationCode::Jump(bytes_to_skip))
-            }
-            23 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::JumpIfTrue(
-                        u16::MIN,
-                    ))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                let bytes_to_skip = u16::from_ne_bytes([value[1], value[2]]);
-                Ok(OperationCode::JumpIfTrue(bytes_to_skip))
-            }
-            24 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::JumpBack(
-                        u16::MAX,
-                    ))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                let bytes_to_skip = u16::from_ne_bytes([value[1], value[2]]);
-                Ok(OperationCode::JumpBack(bytes_to_skip))
-            }
+            0 => OperationCode::decode_without_arguments(OperationCode::Return, is_wide),
+            1 => Ok(OperationCode::Constant(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            2 => OperationCode::decode_without_arguments(OperationCode::Negate, is_wide),
+            3 => OperationCode::decode_without_arguments(OperationCode::Add, is_wide),
+            4 => OperationCode::decode_without_arguments(OperationCode::Substract, is_wide),
+            5 => OperationCode::decode_without_arguments(OperationCode::Multiply, is_wide),
+            6 => OperationCode::decode_without_arguments(OperationCode::Divide, is_wide),
+            7 => OperationCode::decode_without_arguments(OperationCode::Nil, is_wide),
+            8 => OperationCode::decode_without_arguments(OperationCode::True, is_wide),
+            9 => OperationCode::decode_without_arguments(OperationCode::False, is_wide),
+            10 => OperationCode::decode_without_arguments(OperationCode::Not, is_wide),
+            11 => OperationCode::decode_without_arguments(OperationCode::Equal, is_wide),
+            12 => OperationCode::decode_without_arguments(OperationCode::Greater, is_wide),
+            13 => OperationCode::decode_without_arguments(OperationCode::Less, is_wide),
+            14 => OperationCode::decode_without_arguments(OperationCode::Print, is_wide),
+            15 => OperationCode::decode_without_arguments(OperationCode::PopStack, is_wide),
+            16 => Ok(OperationCode::DefineGlobal(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            17 => Ok(OperationCode::GetGlobal(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            18 => Ok(OperationCode::SetGlobal(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            19 => Ok(OperationCode::GetLocal(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            20 => Ok(OperationCode::SetLocal(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            21 => Ok(OperationCode::JumpIfFalse(OperationCode::decode_jump(
+                value, is_wide,
+            )?)),
+            22 => Ok(OperationCode::Jump(OperationCode::decode_jump(
+                value, is_wide,
+            )?)),
+            23 => Ok(OperationCode::JumpIfTrue(OperationCode::decode_jump(
+                value, is_wide,
+            )?)),
+            24 => Ok(OperationCode::JumpBack(OperationCode::decode_jump(
+                value, is_wide,
+            )?)),
             25 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::Call(u8::MAX))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::Call(value[1]))
-            }
-            26 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::Closure(u8::MAX))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::Closure(value[1]))
-            }
-            27 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::LocalUpvalue(
-                        u8::MAX,
-                    ))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::LocalUpvalue(value[1]))
-            }
-            28 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::NonLocalUpvalue(
-                        u8::MAX,
-                    ))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::NonLocalUpvalue(value[1]))
-            }
-            29 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::GetUpvalue(
-                        u8::MAX,
-                    ))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::GetUpvalue(value[1]))
-            }
-            30 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::SetUpvalue(
-                        u8::MAX,
-                    ))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::SetUpvalue(value[1]))
-            }
-            31 => Ok(OperationCode::CloseUpvalue),
-            32 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::Class(u8::MAX))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::Class(value[1]))
-            }
-            33 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::GetProperty(
-                        u8::MAX,
-                    ))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::GetProperty(value[1]))
-            }
-            34 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::SetProperty(
-                        u8::MAX,
-                    ))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::SetProperty(value[1]))
-            }
-            35 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::Method(u8::MAX))
-                {
+                if is_wide {
                     return Err(OperationCodeConversionError::InvalidFormat);
                 }
-                Ok(OperationCode::Method(value[1]))
-            }
+                Ok(OperationCode::Call(OperationCode::decode_byte(value, 1)?))
+            }
+            26 => Ok(OperationCode::Closure(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            27 => Ok(OperationCode::LocalUpvalue(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            28 => Ok(OperationCode::NonLocalUpvalue(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            29 => Ok(OperationCode::GetUpvalue(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            30 => Ok(OperationCode::SetUpvalue(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            31 => OperationCode::decode_without_arguments(OperationCode::CloseUpvalue, is_wide),
+            32 => Ok(OperationCode::Class(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            33 => Ok(OperationCode::GetProperty(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            34 => Ok(OperationCode::SetProperty(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            35 => Ok(OperationCode::Method(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
             36 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::InvokeProperty(
-                        u8::MAX,
-                        u8::MAX,
-                    ))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::InvokeProperty(value[1], value[2]))
-            }
-            37 => Ok(OperationCode::Inherit),
-            38 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(&OperationCode::GetSuper(u8::MAX))
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::GetSuper(value[1]))
-            }
+                let (index, arguments_count) =
+                    OperationCode::decode_index_and_byte(value, is_wide)?;
+                Ok(OperationCode::InvokeProperty(index, arguments_count))
+            }
+            37 => OperationCode::decode_without_arguments(OperationCode::Inherit, is_wide),
+            38 => Ok(OperationCode::GetSuper(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
             39 => {
-                if value.len()
-                    < OperationCode::get_instruction_bytes_length(
-                        &OperationCode::InvokeSuperMethod(u8::MAX, u8::MAX),
-                    )
-                {
-                    return Err(OperationCodeConversionError::InvalidFormat);
-                }
-                Ok(OperationCode::InvokeSuperMethod(value[1], value[2]))
-            }
+                let (index, arguments_count) =
+                    OperationCode::decode_index_and_byte(value, is_wide)?;
+                Ok(OperationCode::InvokeSuperMethod(index, arguments_count))
+            }
+            40 => Ok(OperationCode::BuildList(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            41 => OperationCode::decode_without_arguments(OperationCode::GetIndex, is_wide),
+            42 => OperationCode::decode_without_arguments(OperationCode::SetIndex, is_wide),
+            43 => Ok(OperationCode::BuildMap(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            44 => OperationCode::decode_without_arguments(OperationCode::Stringify, is_wide),
+            45 => OperationCode::decode_without_arguments(OperationCode::Modulo, is_wide),
+            46 => OperationCode::decode_without_arguments(OperationCode::Power, is_wide),
+            47 => OperationCode::decode_without_arguments(OperationCode::IntegerDivide, is_wide),
+            48 => OperationCode::decode_without_arguments(OperationCode::BitwiseAnd, is_wide),
+            49 => OperationCode::decode_without_arguments(OperationCode::BitwiseOr, is_wide),
+            50 => OperationCode::decode_without_arguments(OperationCode::BitwiseXor, is_wide),
+            51 => OperationCode::decode_without_arguments(OperationCode::BitwiseNot, is_wide),
+            52 => OperationCode::decode_without_arguments(OperationCode::ShiftLeft, is_wide),
+            53 => OperationCode::decode_without_arguments(OperationCode::ShiftRight, is_wide),
+            54 => Ok(OperationCode::PushHandler(OperationCode::decode_jump(
+                value, is_wide,
+            )?)),
+            55 => OperationCode::decode_without_arguments(OperationCode::PopHandler, is_wide),
+            56 => OperationCode::decode_without_arguments(OperationCode::Throw, is_wide),
+            57 => Ok(OperationCode::DefineConstantGlobal(
+                OperationCode::decode_index(value, is_wide)?,
+            )),
+            58 => Ok(OperationCode::Import(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            59 => Ok(OperationCode::StaticField(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            60 => Ok(OperationCode::Getter(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            61 => Ok(OperationCode::Setter(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            62 => Ok(OperationCode::Trait(OperationCode::decode_index(
+                value, is_wide,
+            )?)),
+            63 => OperationCode::decode_without_arguments(OperationCode::Mixin, is_wide),
+            64 => OperationCode::decode_without_arguments(OperationCode::Yield, is_wide),
+            65 => OperationCode::decode_without_arguments(OperationCode::Resume, is_wide),
             _ => Err(OperationCodeConversionError::InvalidValue(value[0])),
         }
     }
 }
 
+/// Place in the source code from which the instruction was compiled
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SourceSpan {
+    pub line: usize,
+    /// Column (counted from 1) on which the span starts
+    pub column: usize,
+    /// Number of characters covered by the span
+    pub length: usize,
+}
+
+/// Sequence of consecutive instruction bytes compiled from the same source span.
+/// Fields are narrower than in `SourceSpan` to keep the line table small - columns and lengths that
+/// don't fit are saturated.
+#[derive(Clone, Copy)]
+struct SpanRun {
+    /// Offset of the first instruction byte in the run
+    start_offset: u32,
+    line: u32,
+    column: u16,
+    length: u16,
+}
+
+impl SpanRun {
+    fn new(start_offset: usize, span: SourceSpan) -> Self {
+        SpanRun {
+            start_offset: start_offset as u32,
+            line: u32::try_from(span.line).unwrap_or(u32::MAX),
+            column: u16::try_from(span.column).unwrap_or(u16::MAX),
+            length: u16::try_from(span.length).unwrap_or(u16::MAX),
+        }
+    }
+
+    fn get_span(&self) -> SourceSpan {
+        SourceSpan {
+            line: self.line as usize,
+            column: self.column as usize,
+            length: self.length as usize,
+        }
+    }
+}
+
+/// Instruction decoded once, when it's added to the chunk.
+/// Arguments of jump instructions are resolved to the index of the decoded instruction they jump to.
+#[derive(Clone, Copy)]
+pub struct DecodedInstruction {
+    pub operation_code: OperationCode,
+    /// Index of the instruction's inline cache, only instructions that look up properties have one
+    pub inline_cache_index: Option<u32>,
+}
+
 pub struct Chunk {
     /// bytecode instructions
     instructions: Vec<u8>,
-    /// for each bytecode instruction, it tells from which line in user program it is
-    lines: Vec<usize>,
+    /// already decoded bytecode instructions (one per instruction), so that VM doesn't have to decode them on every step
+    decoded_instructions: Rc<Vec<DecodedInstruction>>,
+    /// offset in `instructions` of every decoded instruction
+    instruction_offsets: Vec<u32>,
+    /// inline caches of the property instructions in this chunk
+    inline_caches: Rc<Vec<RefCell<InlineCache>>>,
+    /// run-length encoded line table - each run tells from which place in user program the instructions
+    /// starting at its offset (until the next run) are
+    spans: Vec<SpanRun>,
     /// array of constatns
     constants: ValueContainer,
 }
@@ -582,6 +672,7 @@ pub struct Chunk {
 pub enum ChunkError {
     PatchingNotJumpInstruction,
     PatchingOutsideOfbounds,
+    PatchingJumpTooFar,
 }
 
 impl Chunk {
@@ -589,26 +680,80 @@ impl Chunk {
 
     pub fn new() -> Self {
         let initial_instruction = Vec::with_capacity(Self::INITIAL_INSTRUCTIONS_SIZE);
-        let initial_lines = Vec::with_capacity(Self::INITIAL_INSTRUCTIONS_SIZE);
+        let initial_decoded_instructions = Vec::with_capacity(Self::INITIAL_INSTRUCTIONS_SIZE);
+        let initial_instruction_offsets = Vec::with_capacity(Self::INITIAL_INSTRUCTIONS_SIZE);
+        let initial_spans = Vec::with_capacity(Self::INITIAL_INSTRUCTIONS_SIZE);
         let initial_constants = ValueContainer::new();
         Chunk {
             instructions: initial_instruction,
-            lines: initial_lines,
+            decoded_instructions: Rc::new(initial_decoded_instructions),
+            instruction_offsets: initial_instruction_offsets,
+            inline_caches: Rc::new(Vec::new()),
+            spans: initial_spans,
             constants: initial_constants,
         }
     }
 
-    pub fn add_instruction(&mut self, operation_code: OperationCode, line: usize) {
+    pub fn add_instruction(&mut self, operation_code: OperationCode, span: SourceSpan) {
         let bytes: Vec<u8> = operation_code.into();
+        // New run is only needed when the instruction comes from different place than the previous one
+        let offset = self.get_instructions_length();
+        let run = SpanRun::new(offset, span);
+        if self.spans.last().map(SpanRun::get_span) != Some(run.get_span()) {
+            self.spans.push(run);
+        }
         self.instructions.extend_from_slice(&bytes);
-        self.lines.extend_from_slice(&(vec![line; bytes.len()]));
+        self.instruction_offsets.push(offset as u32);
+        let inline_cache_index = match operation_code {
+            OperationCode::GetProperty(_)
+            | OperationCode::SetProperty(_)
+            | OperationCode::InvokeProperty(_, _)
+            | OperationCode::GetSuper(_)
+            | OperationCode::InvokeSuperMethod(_, _) => {
+                let inline_caches = Rc::make_mut(&mut self.inline_caches);
+                inline_caches.push(RefCell::new(InlineCache::default()));
+                Some(inline_caches.len() as u32 - 1)
+            }
+            _ => None,
+        };
+        // Forward jumps are emitted before their target, so they are resolved when they are patched
+        let operation_code = match operation_code {
+            OperationCode::JumpBack(bytes_to_skip) => OperationCode::JumpBack(
+                self.get_instruction_index(self.instructions.len() - bytes_to_skip as usize)
+                    .unwrap_or(MAX_JUMP_ARGUMENT),
+            ),
+            _ => operation_code,
+        };
+        Rc::make_mut(&mut self.decoded_instructions).push(DecodedInstruction {
+            operation_code,
+            inline_cache_index,
+        });
+    }
+
+    /// Index of the decoded instruction starting at the offset, offset right after the last instruction
+    /// gives the index of the next instruction that will be added
+    fn get_instruction_index(&self, offset: usize) -> Option<u32> {
+        if offset == self.instructions.len() {
+            return Some(self.instruction_offsets.len() as u32);
+        }
+        self.instruction_offsets
+            .binary_search(&(offset as u32))
+            .ok()
+            .map(|index| index as u32)
+    }
+
+    /// Offset in the bytecode of the decoded instruction with provided index
+    pub fn get_instruction_offset(&self, instruction_index: usize) -> usize {
+        self.instruction_offsets
+            .get(instruction_index)
+            .map_or(self.instructions.len(), |offset| *offset as usize)
     }
 
     pub fn patch_jump_instruction(
         &mut self,
         instruction: OperationCode,
         instruction_index: usize,
-        bytes_to_skip: u16,
+        bytes_to_skip: u32,
     ) -> Result<(), ChunkError> {
         if instruction_index >= self.get_instructions_length() {
             return Err(ChunkError::PatchingOutsideOfbounds);
@@ -616,9 +761,30 @@ impl Chunk {
         if self.instructions[instruction_index] != u8::from(instruction) {
             return Err(ChunkError::PatchingNotJumpInstruction);
         }
-        let bytes = bytes_to_skip.to_ne_bytes();
-        self.instructions[instruction_index + 1] = bytes[0];
-        self.instructions[instruction_index + 2] = bytes[1];
+        if bytes_to_skip > MAX_JUMP_ARGUMENT {
+            return Err(ChunkError::PatchingJumpTooFar);
+        }
+        let decoded_index = self
+            .get_instruction_index(instruction_index)
+            .ok_or(ChunkError::PatchingNotJumpInstruction)?;
+        let target_offset = instruction_index
+            + OperationCode::get_instruction_bytes_length(&instruction)
+            + bytes_to_skip as usize;
+        let target = self
+            .get_instruction_index(target_offset)
+            .ok_or(ChunkError::PatchingOutsideOfbounds)?;
+        let patched_instruction = match instruction {
+            OperationCode::JumpIfFalse(_) => OperationCode::JumpIfFalse(target),
+            OperationCode::Jump(_) => OperationCode::Jump(target),
+            OperationCode::JumpIfTrue(_) => OperationCode::JumpIfTrue(target),
+            OperationCode::PushHandler(_) => OperationCode::PushHandler(target),
+            _ => return Err(ChunkError::PatchingNotJumpInstruction),
+        };
+        let argument_start = instruction_index + 1;
+        self.instructions[argument_start..argument_start + JUMP_INSTRUCTION_ARGUMENT_LENGTH]
+            .copy_from_slice(&OperationCode::encode_jump_argument(bytes_to_skip));
+        Rc::make_mut(&mut self.decoded_instructions)[decoded_index as usize].operation_code =
+            patched_instruction;
         Ok(())
     }
 
@@ -642,17 +808,49 @@ impl Chunk {
         OperationCode::try_from(&self.instructions[offset..finish])
     }
 
-    pub fn read_constant(&self, offset: u8) -> Value {
+    /// Decoded instructions shared with the call frames that execute this chunk
+    pub fn get_decoded_instructions(&self) -> Rc<Vec<DecodedInstruction>> {
+        self.decoded_instructions.clone()
+    }
+
+    pub fn get_inline_caches(&self) -> Rc<Vec<RefCell<InlineCache>>> {
+        self.inline_caches.clone()
+    }
+
+    pub fn read_constant(&self, offset: u32) -> Value {
         self.constants.get_value(offset as usize)
     }
 
     pub fn read_line(&self, offset: usize) -> usize {
-        self.lines[offset]
+        self.read_span(offset).line
+    }
+
+    pub fn read_span(&self, offset: usize) -> SourceSpan {
+        // Index of the last run that starts at or before the offset
+        let run_index = self
+            .spans
+            .partition_point(|run| run.start_offset as usize <= offset)
+            .saturating_sub(1);
+        self.spans[run_index].get_span()
     }
 
     pub fn get_instructions_length(&self) -> usize {
         self.instructions.len()
     }
+
+    pub fn iter_constants(&self) -> impl Iterator<Item = &Value> {
+        self.constants.iter()
+    }
+
+    /// Approximate number of bytes taken by instructions, line table and constants
+    pub fn get_allocated_bytes(&self) -> usize {
+        self.instructions.capacity()
+            + self.decoded_instructions.capacity() * mem::size_of::<DecodedInstruction>()
+            + self.instruction_offsets.capacity() * mem::size_of::<u32>()
+            + self.inline_caches.capacity() * mem::size_of::<RefCell<InlineCache>>()
+            + self.spans.capacity() * mem::size_of::<SpanRun>()
+            + self.constants.get_allocated_bytes()
+    }
 }
 
 impl Default for Chunk {
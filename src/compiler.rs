@@ -1,15 +1,22 @@
 use core::panic;
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::{
-    chunk::{self, OperationCode},
-    lexer::{Lexer, Token, TokenType},
+    chunk::{self, OperationCode, SourceSpan},
+    gc::GarbageCollector,
+    globals::GlobalSlots,
+    lexer::{self, Lexer, Token, TokenType},
     logger::Logger,
     table::Table,
     value::{FunctionObject, Value},
 };
 
 pub const INITIALIZER_NAME: &str = "init";
+/// Contextual keywords starting property accessors in class body (`get area {...}`, `set area(value) {...}`)
+const GETTER_KEYWORD: &str = "get";
+const SETTER_KEYWORD: &str = "set";
+/// Contextual keyword starting the list of traits mixed into the class (`class Foo < Bar with A, B {...}`)
+const WITH_KEYWORD: &str = "with";
 
 struct Local {
     name: Token,
@@ -18,14 +25,18 @@ struct Local {
     depth: i32,
     /// True if closure has upvalue that reference this variable
     is_captured: bool,
+    /// False for constants, which can't be assigned after their declaration
+    is_mutable: bool,
 }
 
 /// Used for variables that are use inside closure, but are defined outside of it
 #[derive(PartialEq)]
 struct Upvalue {
     /// Index on the actual vm stack of the variable Upvalue is referencing
-    index: u8,
+    index: u32,
     is_local: bool,
+    /// Same as mutability of the referenced variable
+    is_mutable: bool,
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -34,20 +45,72 @@ pub enum FunctionType {
     Script,      // Top level function - whole global scope is put in here
     Method,      // Class method
     Initializer, // Class initializer
+    Setter,      // Class property setter
 }
 
 pub struct CompilingClass {
     has_base_class: bool,
+    /// Static members don't belong to any instance, so they can't use "this" nor "super"
+    is_compiling_static_member: bool,
+    /// Traits are compiled the same way as classes, but they can't have initializers, static members nor "super"
+    is_trait: bool,
+    /// Names of methods and property accessors defined in the body
+    member_names: Vec<String>,
+}
+
+/// Loop whose body is currently being compiled
+/// Loop or switch statement whose body is currently being compiled - `break` leaves the innermost one
+struct CompilingLoop {
+    /// Index of the instruction to which `continue` jumps (condition or incrementer), none for switch statements,
+    /// which `continue` passes through to the enclosing loop
+    continue_target: Option<usize>,
+    /// Scope depth outside of the loop body, locals deeper than it are removed by `break` and `continue`
+    scope_depth: i32,
+    /// Indexes of jump instructions emitted by `break`, patched once the end of the loop is known
+    break_jumps: Vec<usize>,
+}
+
+/// Reason for entering the finally block of the try statement, kept in its hidden local
+#[derive(Clone, Copy, PartialEq)]
+enum Completion {
+    Normal,
+    Throw,
+    Return,
+    Break,
+    Continue,
+}
+
+/// Try statement whose `try` or `catch` block is currently being compiled
+struct CompilingTry {
+    /// Number of loops and switches (of the current function) around the try statement - `break` and `continue`
+    /// leave the try statement only if their target is outside of it
+    loops_count: usize,
+    /// Scope depth of the hidden locals, locals deeper than it are removed when jumping to the finally block
+    scope_depth: i32,
+    /// Hidden local holding the value that is thrown again or returned after the finally block
+    value_index: u32,
+    /// Hidden local holding `Completion`
+    completion_index: u32,
+    /// Indexes of jump instructions to the finally block, patched once its start is known
+    finally_jumps: Vec<usize>,
+    /// Exits (`return`, `break` or `continue`) that must be resumed after the finally block
+    pending_exits: Vec<Completion>,
 }
 
 pub struct Compiler<'a, 'b> {
     parser: Parser,
     lexer: Lexer<'a>,
     source: &'a str,
+    /// Index of the module the source belongs to, every compiled function uses its global variables
+    module_index: usize,
+    /// Path of the module file shown in error messages, `None` for the main script
+    module_name: Option<&'a str>,
     // We store it like this to have all functions, so that we can update upvalue of any of them
     functions: Vec<Rc<RefCell<FunctionObject>>>,
     functions_types: Vec<FunctionType>,
     intern_strings: Option<&'b mut Table>,
+    garbage_collector: Option<&'b mut GarbageCollector>,
+    global_slots: Option<&'b mut GlobalSlots>,
     // We store it like this to have all locals in every function in nested function chain
     locals: Vec<Vec<Local>>,
     // Same as before - each function has it's own upvalues
@@ -56,6 +119,15 @@ pub struct Compiler<'a, 'b> {
     // Stack of currenlty compiling classes (classes can be nesteed one in another)
     // If empty then we aren't inside any class
     compiling_classes: Vec<CompilingClass>,
+    // Each function has its own stack of loops, so that `break` and `continue` can't cross function boundary
+    compiling_loops: Vec<Vec<CompilingLoop>>,
+    // Same as with loops - each function has its own stack of try statements
+    compiling_tries: Vec<Vec<CompilingTry>>,
+    // Names of members of every trait declared in the source, keyed by the position of the trait name in its declaration,
+    // so that traits shadowed by another variable aren't confused with the ones mixed in
+    traits_members: HashMap<usize, Vec<String>>,
+    // Position of the declaration of the trait stored in the global variable with the given name
+    global_traits: HashMap<String, usize>,
 }
 
 #[derive(Debug)]
@@ -86,7 +158,12 @@ struct InfixFunctionsArguments {
 }
 
 impl<'a, 'b> Compiler<'a, 'b> {
-    pub fn new(source: &'a str, function_type: FunctionType) -> Self {
+    pub fn new(
+        source: &'a str,
+        function_type: FunctionType,
+        module_index: usize,
+        module_name: Option<&'a str>,
+    ) -> Self {
         // First local is for VM internal use - it stores function that is currently being executed in runtime
         let locals = vec![Local {
             name: Token {
@@ -94,30 +171,47 @@ impl<'a, 'b> Compiler<'a, 'b> {
                 start: 0,
                 length: 0,
                 line: 0,
+                column: 0,
             },
             depth: 0,
             is_captured: false,
+            is_mutable: true,
         }];
 
+        let script = FunctionObject::new_rc("GLOBAL_SCRIPT");
+        script.borrow_mut().module_index = module_index;
+
         Compiler {
             parser: Parser::new(),
             lexer: Lexer::new(source),
             source,
-            functions: vec![FunctionObject::new_rc("GLOBAL_SCRIPT")],
+            module_index,
+            module_name,
+            functions: vec![script],
             functions_types: vec![function_type],
             intern_strings: None,
+            garbage_collector: None,
+            global_slots: None,
             locals: vec![locals],
             upvalues: vec![vec![]],
             current_scope_depth: 0,
             compiling_classes: vec![],
+            compiling_loops: vec![vec![]],
+            compiling_tries: vec![vec![]],
+            traits_members: HashMap::new(),
+            global_traits: HashMap::new(),
         }
     }
 
     pub fn compile(
         &mut self,
         intern_strings: &'b mut Table,
+        garbage_collector: &'b mut GarbageCollector,
+        global_slots: &'b mut GlobalSlots,
     ) -> Result<Rc<RefCell<FunctionObject>>, CompilerError> {
         self.intern_strings = Some(intern_strings);
+        self.garbage_collector = Some(garbage_collector);
+        self.global_slots = Some(global_slots);
         self.advance();
 
         while !self.match_current(&TokenType::Eof) {
@@ -155,7 +249,11 @@ impl<'a, 'b> Compiler<'a, 'b> {
                         return;
                     }
                     self.parser.in_panic_state = true;
-                    eprintln!("[line {}] Error: {}", e.line, e.message);
+                    eprintln!(
+                        "[{}] Error: {}",
+                        Self::format_line(self.module_name, e.line),
+                        e.message
+                    );
                     self.parser.in_error_state = true;
                 }
             }
@@ -210,8 +308,10 @@ impl<'a, 'b> Compiler<'a, 'b> {
                 .token_type
             {
                 TokenType::Class => return,
+                TokenType::Trait => return,
                 TokenType::Fun => return,
                 TokenType::Var => return,
+                TokenType::Const => return,
                 TokenType::For => return,
                 TokenType::If => return,
                 TokenType::While => return,
@@ -231,42 +331,73 @@ impl<'a, 'b> Compiler<'a, 'b> {
         match token_type {
             TokenType::LeftParen => self.handle_grouping(),
             TokenType::RightParen => return Err(CompilerError::EmptyFunction),
-            TokenType::LeftBrace => return Err(CompilerError::EmptyFunction),
+            TokenType::LeftBrace => self.handle_map(),
             TokenType::RightBrace => return Err(CompilerError::EmptyFunction),
+            TokenType::LeftBracket => self.handle_list(),
+            TokenType::RightBracket => return Err(CompilerError::EmptyFunction),
             TokenType::Comma => return Err(CompilerError::EmptyFunction),
+            TokenType::Colon => return Err(CompilerError::EmptyFunction),
             TokenType::Dot => return Err(CompilerError::EmptyFunction),
             TokenType::Minus => self.handle_unary(),
             TokenType::Plus => return Err(CompilerError::EmptyFunction),
             TokenType::Semicolon => return Err(CompilerError::EmptyFunction),
             TokenType::Star => return Err(CompilerError::EmptyFunction),
             TokenType::Slash => return Err(CompilerError::EmptyFunction),
+            TokenType::Percent => return Err(CompilerError::EmptyFunction),
+            TokenType::Ampersand => return Err(CompilerError::EmptyFunction),
+            TokenType::Pipe => return Err(CompilerError::EmptyFunction),
+            TokenType::Caret => return Err(CompilerError::EmptyFunction),
+            TokenType::LessLess => return Err(CompilerError::EmptyFunction),
+            TokenType::GreaterGreater => return Err(CompilerError::EmptyFunction),
+            TokenType::StarStar => return Err(CompilerError::EmptyFunction),
+            TokenType::Tilde => self.handle_unary(),
+            TokenType::TildeSlash => return Err(CompilerError::EmptyFunction),
             TokenType::Bang => self.handle_unary(),
             TokenType::BangEqual => return Err(CompilerError::EmptyFunction),
             TokenType::Equal => return Err(CompilerError::EmptyFunction),
             TokenType::EqualEqual => return Err(CompilerError::EmptyFunction),
+            TokenType::EqualGreater => return Err(CompilerError::EmptyFunction),
             TokenType::Greater => return Err(CompilerError::EmptyFunction),
             TokenType::GreaterEqual => return Err(CompilerError::EmptyFunction),
             TokenType::Less => return Err(CompilerError::EmptyFunction),
             TokenType::LessEqual => return Err(CompilerError::EmptyFunction),
             TokenType::Identifier => self.handle_variable(arguments.can_assign),
             TokenType::String => self.handle_string(),
+            TokenType::RawString => self.handle_raw_string(),
+            TokenType::Interpolation => self.handle_interpolation(),
             TokenType::Number => self.handle_number(),
             TokenType::And => return Err(CompilerError::EmptyFunction),
+            TokenType::Break => return Err(CompilerError::EmptyFunction),
             TokenType::Class => return Err(CompilerError::EmptyFunction),
+            TokenType::Const => return Err(CompilerError::EmptyFunction),
+            TokenType::Static => return Err(CompilerError::EmptyFunction),
+            TokenType::Continue => return Err(CompilerError::EmptyFunction),
+            TokenType::Switch => return Err(CompilerError::EmptyFunction),
+            TokenType::Case => return Err(CompilerError::EmptyFunction),
+            TokenType::Default => return Err(CompilerError::EmptyFunction),
+            TokenType::Throw => return Err(CompilerError::EmptyFunction),
+            TokenType::Trait => return Err(CompilerError::EmptyFunction),
+            TokenType::Try => return Err(CompilerError::EmptyFunction),
+            TokenType::Catch => return Err(CompilerError::EmptyFunction),
+            TokenType::Finally => return Err(CompilerError::EmptyFunction),
             TokenType::Else => return Err(CompilerError::EmptyFunction),
+            TokenType::Export => return Err(CompilerError::EmptyFunction),
             TokenType::False => self.handle_literal(),
             TokenType::For => return Err(CompilerError::EmptyFunction),
-            TokenType::Fun => return Err(CompilerError::EmptyFunction),
+            TokenType::Fun => self.handle_lambda(),
             TokenType::If => return Err(CompilerError::EmptyFunction),
+            TokenType::Import => return Err(CompilerError::EmptyFunction),
             TokenType::Nil => self.handle_literal(),
             TokenType::Or => return Err(CompilerError::EmptyFunction),
             TokenType::Print => return Err(CompilerError::EmptyFunction),
+            TokenType::Resume => self.handle_resume(),
             TokenType::Return => return Err(CompilerError::EmptyFunction),
             TokenType::Super => self.handle_super(),
             TokenType::This => self.handle_this(),
             TokenType::True => self.handle_literal(),
             TokenType::Var => return Err(CompilerError::EmptyFunction),
             TokenType::While => return Err(CompilerError::EmptyFunction),
+            TokenType::Yield => self.handle_yield(),
             TokenType::Eof => return Err(CompilerError::EmptyFunction),
         }
         Ok(())
@@ -282,40 +413,71 @@ impl<'a, 'b> Compiler<'a, 'b> {
             TokenType::RightParen => return Err(CompilerError::EmptyFunction),
             TokenType::LeftBrace => return Err(CompilerError::EmptyFunction),
             TokenType::RightBrace => return Err(CompilerError::EmptyFunction),
+            TokenType::LeftBracket => self.handle_index(arguments.can_assign),
+            TokenType::RightBracket => return Err(CompilerError::EmptyFunction),
             TokenType::Comma => return Err(CompilerError::EmptyFunction),
+            TokenType::Colon => return Err(CompilerError::EmptyFunction),
             TokenType::Dot => self.handle_dot(arguments.can_assign),
             TokenType::Minus => self.handle_binary(),
             TokenType::Plus => self.handle_binary(),
             TokenType::Semicolon => return Err(CompilerError::EmptyFunction),
             TokenType::Star => self.handle_binary(),
             TokenType::Slash => self.handle_binary(),
+            TokenType::Percent => self.handle_binary(),
+            TokenType::Ampersand => self.handle_binary(),
+            TokenType::Pipe => self.handle_binary(),
+            TokenType::Caret => self.handle_binary(),
+            TokenType::LessLess => self.handle_binary(),
+            TokenType::GreaterGreater => self.handle_binary(),
+            TokenType::StarStar => self.handle_binary(),
+            TokenType::Tilde => return Err(CompilerError::EmptyFunction),
+            TokenType::TildeSlash => self.handle_binary(),
             TokenType::Bang => return Err(CompilerError::EmptyFunction),
             TokenType::BangEqual => self.handle_binary(),
             TokenType::Equal => return Err(CompilerError::EmptyFunction),
             TokenType::EqualEqual => self.handle_binary(),
+            TokenType::EqualGreater => return Err(CompilerError::EmptyFunction),
             TokenType::Greater => self.handle_binary(),
             TokenType::GreaterEqual => self.handle_binary(),
             TokenType::Less => self.handle_binary(),
             TokenType::LessEqual => self.handle_binary(),
             TokenType::Identifier => return Err(CompilerError::EmptyFunction),
             TokenType::String => return Err(CompilerError::EmptyFunction),
+            TokenType::RawString => return Err(CompilerError::EmptyFunction),
+            TokenType::Interpolation => return Err(CompilerError::EmptyFunction),
             TokenType::Number => return Err(CompilerError::EmptyFunction),
             TokenType::And => self.handle_and(),
+            TokenType::Break => return Err(CompilerError::EmptyFunction),
             TokenType::Class => return Err(CompilerError::EmptyFunction),
+            TokenType::Const => return Err(CompilerError::EmptyFunction),
+            TokenType::Static => return Err(CompilerError::EmptyFunction),
+            TokenType::Continue => return Err(CompilerError::EmptyFunction),
+            TokenType::Switch => return Err(CompilerError::EmptyFunction),
+            TokenType::Case => return Err(CompilerError::EmptyFunction),
+            TokenType::Default => return Err(CompilerError::EmptyFunction),
+            TokenType::Throw => return Err(CompilerError::EmptyFunction),
+            TokenType::Trait => return Err(CompilerError::EmptyFunction),
+            TokenType::Try => return Err(CompilerError::EmptyFunction),
+            TokenType::Catch => return Err(CompilerError::EmptyFunction),
+            TokenType::Finally => return Err(CompilerError::EmptyFunction),
             TokenType::Else => return Err(CompilerError::EmptyFunction),
+            TokenType::Export => return Err(CompilerError::EmptyFunction),
             TokenType::False => return Err(CompilerError::EmptyFunction),
             TokenType::For => return Err(CompilerError::EmptyFunction),
             TokenType::Fun => return Err(CompilerError::EmptyFunction),
             TokenType::If => return Err(CompilerError::EmptyFunction),
+            TokenType::Import => return Err(CompilerError::EmptyFunction),
             TokenType::Nil => return Err(CompilerError::EmptyFunction),
             TokenType::Or => self.handle_or(),
             TokenType::Print => return Err(CompilerError::EmptyFunction),
+            TokenType::Resume => return Err(CompilerError::EmptyFunction),
             TokenType::Return => return Err(CompilerError::EmptyFunction),
             TokenType::Super => return Err(CompilerError::EmptyFunction),
             TokenType::This => return Err(CompilerError::EmptyFunction),
             TokenType::True => return Err(CompilerError::EmptyFunction),
             TokenType::Var => return Err(CompilerError::EmptyFunction),
             TokenType::While => return Err(CompilerError::EmptyFunction),
+            TokenType::Yield => return Err(CompilerError::EmptyFunction),
             TokenType::Eof => return Err(CompilerError::EmptyFunction),
         }
         Ok(())
@@ -330,9 +492,14 @@ impl<'a, 'b> Compiler<'a, 'b> {
         &self.source[start_index..end_index]
     }
 
+    // Strings are surrounded by '"' or '`', but string parts around interpolations start with '}' and end with "${"
     fn get_string_content_from_token(&self, token: &Token) -> &'a str {
+        let end_length = match token.token_type {
+            TokenType::Interpolation => 2,
+            _ => 1,
+        };
         let start_index = token.start + 1;
-        let end_index = start_index + token.length - 2;
+        let end_index = (token.start + token.length).saturating_sub(end_length);
         if end_index > self.source.len() || end_index < start_index {
             panic!("String token should correctly represent string with leading and ending '\"' signs. ")
         }
@@ -349,22 +516,58 @@ impl<'a, 'b> Compiler<'a, 'b> {
         } else {
             self.get_lexeme_from_token(token)
         };
-        eprintln!("[line {}] Error at '{}': {}", token.line, location, message);
+        eprintln!(
+            "[{}] Error at '{}': {}",
+            Self::format_line(self.module_name, token.line),
+            location,
+            message
+        );
         self.parser.in_error_state = true;
     }
 
+    /// Location prefix of the error message, for modules other than the main script it includes the file path
+    fn format_line(module_name: Option<&str>, line: usize) -> String {
+        match module_name {
+            Some(module_name) => format!("{} line {}", module_name, line),
+            None => format!("line {}", line),
+        }
+    }
+
     fn emit_instruction(&mut self, instruction: OperationCode) {
-        let line = match self.parser.previous {
-            Some(token) => token.line,
+        match self.parser.previous {
+            Some(token) => self.emit_instruction_at(instruction, &token),
             // This might happen if there are no tokens
-            None => 0,
-        };
+            None => self.add_instruction(
+                instruction,
+                SourceSpan {
+                    line: 0,
+                    column: 0,
+                    length: 0,
+                },
+            ),
+        }
+    }
+
+    /// Emit instruction that should be reported as coming from provided token (e.g. binary operator),
+    /// instead of the last consumed one
+    fn emit_instruction_at(&mut self, instruction: OperationCode, token: &Token) {
+        self.add_instruction(
+            instruction,
+            SourceSpan {
+                line: token.line,
+                column: token.column,
+                length: token.length,
+            },
+        );
+    }
+
+    fn add_instruction(&mut self, instruction: OperationCode, span: SourceSpan) {
         self.functions
             .last_mut()
             .unwrap()
             .borrow_mut()
             .chunk
-            .add_instruction(instruction, line);
+            .add_instruction(instruction, span);
     }
 
     fn emit_jump_instruction(&mut self, instruction: OperationCode) -> usize {
@@ -385,7 +588,7 @@ impl<'a, 'b> Compiler<'a, 'b> {
             .get_instructions_length()
             - (instruction_index + chunk::JUMP_INSTRUCTION_ARGUMENT_LENGTH + 1);
 
-        if bytes_to_skip > u16::MAX as usize {
+        if bytes_to_skip > chunk::MAX_JUMP_ARGUMENT as usize {
             self.handle_error_at_token(
                 &self.parser.previous.unwrap(),
                 "Too much code to jump over.",
@@ -399,7 +602,7 @@ impl<'a, 'b> Compiler<'a, 'b> {
             .unwrap()
             .borrow_mut()
             .chunk
-            .patch_jump_instruction(instruction, instruction_index, bytes_to_skip as u16);
+            .patch_jump_instruction(instruction, instruction_index, bytes_to_skip as u32);
 
         match patch_jump_result {
             Ok(_) => {}
@@ -418,21 +621,26 @@ impl<'a, 'b> Compiler<'a, 'b> {
             .get_instructions_length()
             - jump_to_index
             // Because we must also jump over the jump instruction itself
-            + OperationCode::get_instruction_bytes_length(&OperationCode::JumpBack(u16::MAX));
+            + OperationCode::get_instruction_bytes_length(&OperationCode::JumpBack(chunk::MAX_JUMP_ARGUMENT));
 
-        if bytes_to_skip > u16::MAX as usize {
+        if bytes_to_skip > chunk::MAX_JUMP_ARGUMENT as usize {
             self.handle_error_at_token(
                 &self.parser.previous.unwrap(),
-                "COuldn't jump back over so many bytes.",
+                "Couldn't jump back over so many bytes.",
             );
             return;
         }
-        self.emit_instruction(OperationCode::JumpBack(bytes_to_skip as u16));
+        self.emit_instruction(OperationCode::JumpBack(bytes_to_skip as u32));
     }
 
-    fn emit_double_instruction(&mut self, first: OperationCode, second: OperationCode) {
-        self.emit_instruction(first);
-        self.emit_instruction(second);
+    fn emit_double_instruction(
+        &mut self,
+        first: OperationCode,
+        second: OperationCode,
+        token: &Token,
+    ) {
+        self.emit_instruction_at(first, token);
+        self.emit_instruction_at(second, token);
     }
 
     fn emit_return_instruction(&mut self) {
@@ -440,43 +648,37 @@ impl<'a, 'b> Compiler<'a, 'b> {
         match self.functions_types.last().unwrap() {
             // "this" is always first element in local array
             FunctionType::Initializer => self.emit_instruction(OperationCode::GetLocal(0)),
+            // Assignment to the property results in the assigned value, which is the only parameter of setter
+            FunctionType::Setter => self.emit_instruction(OperationCode::GetLocal(1)),
             _ => self.emit_constant(Value::new_nil()),
         }
+        self.emit_return();
+    }
+
+    /// Return value from the stack top, going through the finally block first if it's inside try statement
+    fn emit_return(&mut self) {
+        if self.is_leaving_try(Completion::Return) {
+            self.emit_exit_to_finally(Completion::Return);
+            return;
+        }
         self.emit_instruction(OperationCode::Return);
     }
 
     fn emit_constant(&mut self, constant: Value) {
         let index = self.make_constant(constant);
-        if index as u8 > u8::MAX {
-            // If we are handling constant then we are sure the previous must exists
-            self.handle_error_at_token(
-                &self.parser.previous.unwrap(),
-                "Too many constants in one chunk",
-            );
-            return;
-        }
-        self.emit_instruction(OperationCode::Constant(index as u8));
+        self.emit_instruction(OperationCode::Constant(index));
     }
 
-    fn make_constant(&mut self, constant: Value) -> usize {
-        self.functions
+    fn make_constant(&mut self, constant: Value) -> u32 {
+        let index = self
+            .functions
             .last_mut()
             .unwrap()
             .borrow_mut()
             .chunk
-            .add_constant(constant)
-    }
-
-    fn make_identifier_constant(&mut self, token: &Token) -> u8 {
-        let name = self.get_lexeme_from_token(token);
-        let name_string_object = Value::new_string_object(
-            name,
-            self.intern_strings
-                .as_mut()
-                .expect("during compilation intern strings should be set"),
-        );
-        let index = self.make_constant(name_string_object);
-        if index as u8 > u8::MAX {
+            .add_constant(constant);
+        // Indices that don't fit in a single byte are emitted as wide instructions
+        if index > chunk::MAX_INDEX_ARGUMENT as usize {
             // If we are handling constant then we are sure the previous must exists
             self.handle_error_at_token(
                 &self.parser.previous.unwrap(),
@@ -484,13 +686,46 @@ impl<'a, 'b> Compiler<'a, 'b> {
             );
             return 0;
         }
-        index as u8
+        index as u32
+    }
+
+    fn intern_string(&mut self, value: &str) -> Value {
+        Value::new_string_object(
+            value,
+            self.intern_strings
+                .as_mut()
+                .expect("during compilation intern strings should be set"),
+            self.garbage_collector
+                .as_mut()
+                .expect("during compilation garbage collector should be set"),
+        )
+    }
+
+    fn make_identifier_constant(&mut self, token: &Token) -> u32 {
+        let name = self.get_lexeme_from_token(token);
+        let name_string_object = self.intern_string(name);
+        self.make_constant(name_string_object)
     }
 
     fn end_compiler(&mut self) {
         self.emit_return_instruction();
     }
 
+    fn resolve_global_slot(&mut self, token: &Token) -> u32 {
+        let name = self.get_lexeme_from_token(token);
+        let name_string_object = self.intern_string(name);
+        let slot = self
+            .global_slots
+            .as_mut()
+            .expect("during compilation global slots should be set")
+            .resolve(&name_string_object.get_string_object().unwrap());
+        if slot > chunk::MAX_INDEX_ARGUMENT {
+            self.handle_error_at_token(token, "Too many global variables.");
+            return 0;
+        }
+        slot
+    }
+
     fn handle_number(&mut self) {
         // We assume the token with number was already consumed and is stored in "previous"
         let number_str = self.get_lexeme_from_token(&self.parser.previous.unwrap());
@@ -504,47 +739,69 @@ impl<'a, 'b> Compiler<'a, 'b> {
     }
 
     fn handle_grouping(&mut self) {
+        if self.is_arrow_function_ahead() {
+            self.handle_arrow_function();
+            return;
+        }
         self.compile_expression();
         self.consume(TokenType::RightParen, "Expect ')' after expression.");
     }
 
     fn handle_unary(&mut self) {
         // We asume operator was already consumed
-        let operator_type = &self.parser.previous.unwrap().token_type;
+        let operator = self.parser.previous.unwrap();
 
         // Compile operand
         self.parse_precendence(Precedence::Unary);
 
-        match operator_type {
-            TokenType::Minus => self.emit_instruction(OperationCode::Negate),
-            TokenType::Bang => self.emit_instruction(OperationCode::Not),
+        match operator.token_type {
+            TokenType::Minus => self.emit_instruction_at(OperationCode::Negate, &operator),
+            TokenType::Bang => self.emit_instruction_at(OperationCode::Not, &operator),
+            TokenType::Tilde => self.emit_instruction_at(OperationCode::BitwiseNot, &operator),
             _ => panic!("unreachable"),
         }
     }
 
     fn handle_binary(&mut self) {
         // We asume operator was already consumed
-        let operator_type = &self.parser.previous.unwrap().token_type;
-        let precedence = Precedence::from(operator_type);
-
-        self.parse_precendence(precedence.get_higher());
-
-        match operator_type {
-            TokenType::Plus => self.emit_instruction(OperationCode::Add),
-            TokenType::Minus => self.emit_instruction(OperationCode::Substract),
-            TokenType::Star => self.emit_instruction(OperationCode::Multiply),
-            TokenType::Slash => self.emit_instruction(OperationCode::Divide),
+        let operator = self.parser.previous.unwrap();
+        let precedence = Precedence::from(&operator.token_type);
+
+        // Exponentiation is right associative, so its right operand can contain another exponentiation
+        match operator.token_type {
+            TokenType::StarStar => self.parse_precendence(precedence),
+            _ => self.parse_precendence(precedence.get_higher()),
+        }
+
+        // Instructions are emitted at the operator, so that runtime errors point at it and not at the right operand
+        match operator.token_type {
+            TokenType::Plus => self.emit_instruction_at(OperationCode::Add, &operator),
+            TokenType::Minus => self.emit_instruction_at(OperationCode::Substract, &operator),
+            TokenType::Star => self.emit_instruction_at(OperationCode::Multiply, &operator),
+            TokenType::Slash => self.emit_instruction_at(OperationCode::Divide, &operator),
+            TokenType::Percent => self.emit_instruction_at(OperationCode::Modulo, &operator),
+            TokenType::StarStar => self.emit_instruction_at(OperationCode::Power, &operator),
+            TokenType::TildeSlash => {
+                self.emit_instruction_at(OperationCode::IntegerDivide, &operator)
+            }
+            TokenType::Ampersand => self.emit_instruction_at(OperationCode::BitwiseAnd, &operator),
+            TokenType::Pipe => self.emit_instruction_at(OperationCode::BitwiseOr, &operator),
+            TokenType::Caret => self.emit_instruction_at(OperationCode::BitwiseXor, &operator),
+            TokenType::LessLess => self.emit_instruction_at(OperationCode::ShiftLeft, &operator),
+            TokenType::GreaterGreater => {
+                self.emit_instruction_at(OperationCode::ShiftRight, &operator)
+            }
             TokenType::BangEqual => {
-                self.emit_double_instruction(OperationCode::Equal, OperationCode::Not)
+                self.emit_double_instruction(OperationCode::Equal, OperationCode::Not, &operator)
             }
-            TokenType::EqualEqual => self.emit_instruction(OperationCode::Equal),
-            TokenType::Greater => self.emit_instruction(OperationCode::Greater),
+            TokenType::EqualEqual => self.emit_instruction_at(OperationCode::Equal, &operator),
+            TokenType::Greater => self.emit_instruction_at(OperationCode::Greater, &operator),
             TokenType::GreaterEqual => {
-                self.emit_double_instruction(OperationCode::Less, OperationCode::Not)
+                self.emit_double_instruction(OperationCode::Less, OperationCode::Not, &operator)
             }
-            TokenType::Less => self.emit_instruction(OperationCode::Less),
+            TokenType::Less => self.emit_instruction_at(OperationCode::Less, &operator),
             TokenType::LessEqual => {
-                self.emit_double_instruction(OperationCode::Greater, OperationCode::Not)
+                self.emit_double_instruction(OperationCode::Greater, OperationCode::Not, &operator)
             }
             _ => panic!("unreachable"),
         }
@@ -561,12 +818,62 @@ impl<'a, 'b> Compiler<'a, 'b> {
 
     fn handle_string(&mut self) {
         let content = self.get_string_content_from_token(&self.parser.previous.unwrap());
-        let new_string = Value::new_string_object(
-            content,
-            self.intern_strings
-                .as_mut()
-                .expect("Intern strings should never be emtpy"),
-        );
+        self.emit_string_constant(&lexer::unescape_string(content));
+    }
+
+    fn handle_raw_string(&mut self) {
+        let content = self.get_string_content_from_token(&self.parser.previous.unwrap());
+        self.emit_string_constant(content);
+    }
+
+    // Interpolated string is compiled into concatenation of its parts, so "a${b}c" works like "a" + str(b) + "c"
+    fn handle_interpolation(&mut self) {
+        // We asume the part before the first interpolated expression was already consumed
+        let mut has_previous_part = self.emit_string_part();
+        loop {
+            self.compile_expression();
+            self.emit_instruction(OperationCode::Stringify);
+            if has_previous_part {
+                self.emit_instruction(OperationCode::Add);
+            }
+            has_previous_part = true;
+
+            let is_last_part = !self.match_current(&TokenType::Interpolation);
+            if is_last_part {
+                self.consume(
+                    TokenType::String,
+                    "Expect '}' after interpolated expression.",
+                );
+            }
+            if self.emit_string_part() {
+                self.emit_instruction(OperationCode::Add);
+            }
+            if is_last_part {
+                break;
+            }
+        }
+    }
+
+    /// Emit the string part of interpolated string (stored in the previous token), empty parts are skipped.
+    /// Returns true if the part was emitted.
+    fn emit_string_part(&mut self) -> bool {
+        let token = self.parser.previous.unwrap();
+        if !matches!(
+            token.token_type,
+            TokenType::String | TokenType::Interpolation
+        ) {
+            return false;
+        }
+        let content = self.get_string_content_from_token(&token);
+        if content.is_empty() {
+            return false;
+        }
+        self.emit_string_constant(&lexer::unescape_string(content));
+        true
+    }
+
+    fn emit_string_constant(&mut self, content: &str) {
+        let new_string = self.intern_string(content);
         self.emit_constant(new_string);
     }
 
@@ -600,35 +907,123 @@ impl<'a, 'b> Compiler<'a, 'b> {
         self.define_variable(index);
     }
 
+    fn handle_const_declaration(&mut self) {
+        let index = self.parse_variable("Expect constant name.");
+
+        // Constant can't be assigned later, so it must be initialized right away
+        self.consume(TokenType::Equal, "Expect '=' after constant name.");
+        self.compile_expression();
+
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after constant declaration.",
+        );
+
+        self.define_constant(index);
+    }
+
+    // Syntax: import "path/to/module.lox" as name;
+    fn handle_import_declaration(&mut self) {
+        let import_token = self.parser.previous.unwrap();
+        self.consume(TokenType::String, "Expect module path after 'import'.");
+        let path = lexer::unescape_string(
+            self.get_string_content_from_token(&self.parser.previous.unwrap()),
+        );
+        let path = self.intern_string(&path);
+        let path_index = self.make_constant(path);
+
+        // `as` isn't a keyword, so that it still can be used as a name
+        self.consume(TokenType::Identifier, "Expect 'as' after module path.");
+        if self.get_lexeme_from_token(&self.parser.previous.unwrap()) != "as" {
+            self.handle_error_at_token(
+                &self.parser.previous.unwrap(),
+                "Expect 'as' after module path.",
+            );
+        }
+        let index = self.parse_variable("Expect module name.");
+
+        self.emit_instruction_at(OperationCode::Import(path_index), &import_token);
+        self.consume(TokenType::Semicolon, "Expect ';' after import.");
+
+        self.define_variable(index);
+    }
+
+    // Exported declaration is compiled as usual, it's only marked so that importing modules can access it
+    fn handle_export_declaration(&mut self) {
+        if self.current_scope_depth > 0 {
+            self.handle_error_at_token(
+                &self.parser.previous.unwrap(),
+                "Can only export top-level declarations.",
+            );
+        }
+        let declaration_type = self.parser.current.unwrap().token_type;
+        if !matches!(
+            declaration_type,
+            TokenType::Class
+                | TokenType::Trait
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::Const
+        ) {
+            self.handle_error_at_token(
+                &self.parser.current.unwrap(),
+                "Expect declaration after 'export'.",
+            );
+            return;
+        }
+        self.advance();
+        // Every exportable declaration starts with the keyword followed by the name
+        let name = self.parser.current.unwrap();
+        match declaration_type {
+            TokenType::Class => self.handle_class_declaration(),
+            TokenType::Trait => self.handle_trait_declaration(),
+            TokenType::Fun => self.handle_function_declaration(),
+            TokenType::Var => self.handle_var_declaration(),
+            _ => self.handle_const_declaration(),
+        }
+        if name.token_type == TokenType::Identifier && self.current_scope_depth == 0 {
+            let slot = self.resolve_global_slot(&name);
+            self.global_slots
+                .as_mut()
+                .expect("during compilation global slots should be set")
+                .export(slot);
+        }
+    }
+
     fn handle_variable(&mut self, can_assign: bool) {
         self.handle_named_variable(&self.parser.previous.unwrap(), can_assign);
     }
 
     fn handle_named_variable(&mut self, name: &Token, can_assign: bool) {
-        let (get_operation, set_operation) =
+        // Globals are always mutable for the compiler, constant globals are checked at runtime
+        let (get_operation, set_operation, is_mutable) =
             match self.resolve_local_variable(name, self.locals.last().unwrap()) {
                 Ok(index) => (
                     OperationCode::GetLocal(index),
                     OperationCode::SetLocal(index),
+                    self.locals.last().unwrap()[index as usize].is_mutable,
                 ),
                 Err(LocalVariableError::NotFound) => {
                     if self.locals.len() == 1 {
-                        let global_index = self.make_identifier_constant(name);
+                        let global_index = self.resolve_global_slot(name);
                         (
                             OperationCode::GetGlobal(global_index),
                             OperationCode::SetGlobal(global_index),
+                            true,
                         )
                     } else {
                         match self.resolve_upvalue(name, self.locals.len() - 2) {
                             Ok(upvalue_index) => (
                                 OperationCode::GetUpvalue(upvalue_index),
                                 OperationCode::SetUpvalue(upvalue_index),
+                                self.upvalues.last().unwrap()[upvalue_index as usize].is_mutable,
                             ),
                             Err(UpvalueError::NotFound) => {
-                                let global_index = self.make_identifier_constant(name);
+                                let global_index = self.resolve_global_slot(name);
                                 (
                                     OperationCode::GetGlobal(global_index),
                                     OperationCode::SetGlobal(global_index),
+                                    true,
                                 )
                             }
                         }
@@ -645,6 +1040,9 @@ impl<'a, 'b> Compiler<'a, 'b> {
 
         if can_assign && self.match_current(&TokenType::Equal) {
             // Setter
+            if !is_mutable {
+                self.handle_error_at_token(name, "Can't assign to constant variable.");
+            }
             self.compile_expression();
             self.emit_instruction(set_operation);
         } else {
@@ -668,7 +1066,7 @@ impl<'a, 'b> Compiler<'a, 'b> {
 
         // Instruction for skipping then branch if condition is false
         let skip_then_branch_instruction_index =
-            self.emit_jump_instruction(OperationCode::JumpIfFalse(u16::MAX));
+            self.emit_jump_instruction(OperationCode::JumpIfFalse(chunk::MAX_JUMP_ARGUMENT));
 
         // Both branches must pop stack from the value - the condition that was used to determine which branch to choose
         // If else isn't used then it implicitily is just POP_STACK instruction
@@ -679,13 +1077,13 @@ impl<'a, 'b> Compiler<'a, 'b> {
 
         // Instruction for skipping else branch - part of then branch
         let skip_else_branch_instruction_index =
-            self.emit_jump_instruction(OperationCode::Jump(u16::MAX));
+            self.emit_jump_instruction(OperationCode::Jump(chunk::MAX_JUMP_ARGUMENT));
         // End of then branch
 
         // Patching here means that everything that was compiled between here and the point where jump instruction itself
         // was emitted will be skipped by jump instruction
         self.patch_jump_instruction(
-            OperationCode::JumpIfFalse(u16::MAX),
+            OperationCode::JumpIfFalse(chunk::MAX_JUMP_ARGUMENT),
             skip_then_branch_instruction_index,
         );
 
@@ -698,35 +1096,35 @@ impl<'a, 'b> Compiler<'a, 'b> {
 
         // Same as before
         self.patch_jump_instruction(
-            OperationCode::Jump(u16::MAX),
+            OperationCode::Jump(chunk::MAX_JUMP_ARGUMENT),
             skip_else_branch_instruction_index,
         );
     }
 
     fn handle_and(&mut self) {
         let skip_right_operand_instruction_index =
-            self.emit_jump_instruction(OperationCode::JumpIfFalse(u16::MAX));
+            self.emit_jump_instruction(OperationCode::JumpIfFalse(chunk::MAX_JUMP_ARGUMENT));
 
         // If left operand is truthy then we pop it from the stack and leave right operand on the stack
         self.emit_instruction(OperationCode::PopStack);
         self.parse_precendence(Precedence::And);
 
         self.patch_jump_instruction(
-            OperationCode::JumpIfFalse(u16::MAX),
+            OperationCode::JumpIfFalse(chunk::MAX_JUMP_ARGUMENT),
             skip_right_operand_instruction_index,
         );
     }
 
     fn handle_or(&mut self) {
         let skip_right_operand_instruction_index =
-            self.emit_jump_instruction(OperationCode::JumpIfTrue(u16::MAX));
+            self.emit_jump_instruction(OperationCode::JumpIfTrue(chunk::MAX_JUMP_ARGUMENT));
 
         // If left operand is falsey then we pop it from the stack and leave right operand on the stack
         self.emit_instruction(OperationCode::PopStack);
         self.parse_precendence(Precedence::Or);
 
         self.patch_jump_instruction(
-            OperationCode::JumpIfTrue(u16::MAX),
+            OperationCode::JumpIfTrue(chunk::MAX_JUMP_ARGUMENT),
             skip_right_operand_instruction_index,
         )
     }
@@ -745,18 +1143,20 @@ impl<'a, 'b> Compiler<'a, 'b> {
         self.consume(TokenType::RightParen, "Expect ')' after condition.");
 
         let skip_while_body_instruction_index =
-            self.emit_jump_instruction(OperationCode::JumpIfFalse(u16::MAX));
+            self.emit_jump_instruction(OperationCode::JumpIfFalse(chunk::MAX_JUMP_ARGUMENT));
 
         self.emit_instruction(OperationCode::PopStack);
+        self.start_loop(while_statement_start_index);
         self.compile_statement();
         self.emit_jump_back_instruction(while_statement_start_index);
         // End of while statement body
 
         self.patch_jump_instruction(
-            OperationCode::JumpIfFalse(u16::MAX),
+            OperationCode::JumpIfFalse(chunk::MAX_JUMP_ARGUMENT),
             skip_while_body_instruction_index,
         );
         self.emit_instruction(OperationCode::PopStack);
+        self.end_loop();
     }
 
     fn handle_for_statement(&mut self) {
@@ -784,14 +1184,16 @@ impl<'a, 'b> Compiler<'a, 'b> {
         if !self.match_current(&TokenType::Semicolon) {
             self.compile_expression();
             self.consume(TokenType::Semicolon, "Expect ';' after for loop condition.");
-            skip_for_body_instruction_index =
-                Some(self.emit_jump_instruction(OperationCode::JumpIfFalse(u16::MAX)));
+            skip_for_body_instruction_index = Some(
+                self.emit_jump_instruction(OperationCode::JumpIfFalse(chunk::MAX_JUMP_ARGUMENT)),
+            );
             // Remove condition result from the stack
             self.emit_instruction(OperationCode::PopStack);
         }
 
         if !self.match_current(&TokenType::RightParen) {
-            let jump_to_body = self.emit_jump_instruction(OperationCode::Jump(u16::MAX));
+            let jump_to_body =
+                self.emit_jump_instruction(OperationCode::Jump(chunk::MAX_JUMP_ARGUMENT));
             let increment_start = self
                 .functions
                 .last()
@@ -808,128 +1210,708 @@ impl<'a, 'b> Compiler<'a, 'b> {
 
             self.emit_jump_back_instruction(for_statement_start_index);
             for_statement_start_index = increment_start;
-            self.patch_jump_instruction(OperationCode::Jump(u16::MAX), jump_to_body);
+            self.patch_jump_instruction(
+                OperationCode::Jump(chunk::MAX_JUMP_ARGUMENT),
+                jump_to_body,
+            );
         }
 
+        self.start_loop(for_statement_start_index);
         self.compile_statement();
         self.emit_jump_back_instruction(for_statement_start_index);
         // End of for statement body
 
         if let Some(index) = skip_for_body_instruction_index {
-            self.patch_jump_instruction(OperationCode::JumpIfFalse(u16::MAX), index);
+            self.patch_jump_instruction(
+                OperationCode::JumpIfFalse(chunk::MAX_JUMP_ARGUMENT),
+                index,
+            );
             self.emit_instruction(OperationCode::PopStack);
         }
+        // Loop variable is still on the stack after `break`, so it's removed together with the scope
+        self.end_loop();
         self.end_scope();
     }
 
-    fn handle_return_statement(&mut self) {
-        if *self.functions_types.last().unwrap() == FunctionType::Script {
-            self.handle_error_at_token(
-                &self.parser.previous.unwrap(),
-                "Cannot return from top level script.",
-            );
-            return;
-        }
+    fn handle_switch_statement(&mut self) {
+        // Switched value is kept in the hidden local for the whole statement, so that every case can compare against it
+        self.start_scope();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'switch'.");
+        self.compile_expression();
+        self.consume(TokenType::RightParen, "Expect ')' after switch value.");
+        let switched_value_index = self.add_hidden_local(TokenType::Switch);
+        // `break` inside a case leaves the switch, removing the hidden local as well
+        self.start_switch();
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before switch cases.");
+        // Jumps at the end of every case body - there is no fallthrough, so they all skip to the end of the switch
+        let mut skip_rest_instruction_indexes = vec![];
+        let mut has_default_case = false;
+        while !self.check_current(&TokenType::RightBrace) && !self.check_current(&TokenType::Eof) {
+            if self.match_current(&TokenType::Case) {
+                if has_default_case {
+                    self.handle_error_at_token(
+                        &self.parser.previous.unwrap(),
+                        "Can't have 'case' after 'default'.",
+                    );
+                }
 
-        if self.match_current(&TokenType::Semicolon) {
-            self.emit_return_instruction();
-            return;
-        } else {
-            // If we are using "return" inside initializer, and it's followed by expression (meaning we are trying to return
-            // value from initializer), we report it as error
-            if *self.functions_types.last().unwrap() == FunctionType::Initializer {
+                // Each value is compared separately, the first one that is equal jumps straight into the body
+                let mut enter_body_instruction_indexes = vec![];
+                loop {
+                    self.emit_instruction(OperationCode::GetLocal(switched_value_index));
+                    self.compile_expression();
+                    self.emit_instruction(OperationCode::Equal);
+                    let skip_value_instruction_index = self.emit_jump_instruction(
+                        OperationCode::JumpIfFalse(chunk::MAX_JUMP_ARGUMENT),
+                    );
+                    self.emit_instruction(OperationCode::PopStack);
+                    enter_body_instruction_indexes.push(
+                        self.emit_jump_instruction(OperationCode::Jump(chunk::MAX_JUMP_ARGUMENT)),
+                    );
+                    self.patch_jump_instruction(
+                        OperationCode::JumpIfFalse(chunk::MAX_JUMP_ARGUMENT),
+                        skip_value_instruction_index,
+                    );
+                    self.emit_instruction(OperationCode::PopStack);
+
+                    if !self.match_current(&TokenType::Comma) {
+                        break;
+                    }
+                }
+                self.consume(TokenType::Colon, "Expect ':' after case value.");
+
+                // None of the values matched - go to the next case
+                let skip_body_instruction_index =
+                    self.emit_jump_instruction(OperationCode::Jump(chunk::MAX_JUMP_ARGUMENT));
+                for index in enter_body_instruction_indexes {
+                    self.patch_jump_instruction(
+                        OperationCode::Jump(chunk::MAX_JUMP_ARGUMENT),
+                        index,
+                    );
+                }
+                self.handle_case_body();
+                skip_rest_instruction_indexes.push(
+                    self.emit_jump_instruction(OperationCode::Jump(chunk::MAX_JUMP_ARGUMENT)),
+                );
+                self.patch_jump_instruction(
+                    OperationCode::Jump(chunk::MAX_JUMP_ARGUMENT),
+                    skip_body_instruction_index,
+                );
+            } else if self.match_current(&TokenType::Default) {
+                if has_default_case {
+                    self.handle_error_at_token(
+                        &self.parser.previous.unwrap(),
+                        "Switch can't have more than one 'default'.",
+                    );
+                }
+                has_default_case = true;
+                self.consume(TokenType::Colon, "Expect ':' after 'default'.");
+                // Reached only if none of the cases matched
+                self.handle_case_body();
+            } else {
                 self.handle_error_at_token(
-                    &self.parser.previous.unwrap(),
-                    "Can't return a value from an initializer.",
+                    &self.parser.current.unwrap(),
+                    "Expect 'case' or 'default' in switch.",
                 );
+                // Skip the token, so that the rest of the switch can still be checked
+                self.advance();
             }
         }
+        self.consume(TokenType::RightBrace, "Expect '}' after switch cases.");
 
-        self.compile_expression();
-        self.consume(TokenType::Semicolon, "Expect ';' after return value.");
-        self.emit_instruction(OperationCode::Return);
+        for index in skip_rest_instruction_indexes {
+            self.patch_jump_instruction(OperationCode::Jump(chunk::MAX_JUMP_ARGUMENT), index);
+        }
+        // Removes the hidden local
+        self.end_scope();
+        // Breaks jump past the removal, they remove the hidden local themselves
+        self.end_loop();
     }
 
-    fn handle_function_declaration(&mut self) {
-        let index = self.parse_variable("Expect function name.");
-        if self.current_scope_depth > 0 {
-            // We do it automatically because we allow function to use itself in its own initializer (needed for recursion)
-            self.mark_last_initialized();
+    /// Statements until the next case, each case body has its own scope
+    fn handle_case_body(&mut self) {
+        self.start_scope();
+        while !self.check_current(&TokenType::Case)
+            && !self.check_current(&TokenType::Default)
+            && !self.check_current(&TokenType::RightBrace)
+            && !self.check_current(&TokenType::Eof)
+        {
+            self.compile_declaration();
         }
-        self.handle_function(FunctionType::Function);
-        self.define_variable(index);
+        self.end_scope();
     }
 
-    fn handle_function(&mut self, function_type: FunctionType) {
-        let current_function =
-            FunctionObject::new_rc(self.get_lexeme_from_token(&self.parser.previous.unwrap()));
-        let special_token_type = match function_type {
-            FunctionType::Method => TokenType::This,
-            FunctionType::Initializer => TokenType::This,
-            _ => TokenType::Identifier,
-        };
-        let current_locals = vec![Local {
-            name: Token {
-                token_type: special_token_type,
-                start: 0,
-                length: 0,
-                line: 0,
-            },
-            depth: 0,
-            is_captured: false,
-        }];
-
-        let previous_depth = self.current_scope_depth;
+    fn start_loop(&mut self, continue_target: usize) {
+        self.compiling_loops
+            .last_mut()
+            .unwrap()
+            .push(CompilingLoop {
+                continue_target: Some(continue_target),
+                scope_depth: self.current_scope_depth,
+                break_jumps: vec![],
+            });
+    }
 
-        self.functions.push(current_function);
-        self.functions_types.push(function_type);
-        self.locals.push(current_locals);
-        self.upvalues.push(vec![]);
+    /// Switch is started outside of its hidden local, so that `break` removes it
+    fn start_switch(&mut self) {
+        self.compiling_loops
+            .last_mut()
+            .unwrap()
+            .push(CompilingLoop {
+                continue_target: None,
+                scope_depth: self.current_scope_depth - 1,
+                break_jumps: vec![],
+            });
+    }
 
-        self.current_scope_depth = 0;
+    fn end_loop(&mut self) {
+        let compiling_loop = self
+            .compiling_loops
+            .last_mut()
+            .unwrap()
+            .pop()
+            .expect("Loop should be started before it's ended");
+        for break_jump in compiling_loop.break_jumps {
+            self.patch_jump_instruction(OperationCode::Jump(chunk::MAX_JUMP_ARGUMENT), break_jump);
+        }
+    }
 
-        self.start_scope();
+    fn handle_break_statement(&mut self) {
+        let is_inside_loop = self.get_break_target().is_some();
+        if !is_inside_loop {
+            self.handle_error_at_token(
+                &self.parser.previous.unwrap(),
+                "Can't use 'break' outside of a loop or switch.",
+            );
+        }
+        self.consume(TokenType::Semicolon, "Expect ';' after 'break'.");
+        if is_inside_loop {
+            self.emit_break();
+        }
+    }
 
-        self.consume(TokenType::LeftParen, "Expect '(' after function name.");
+    fn emit_break(&mut self) {
+        if self.is_leaving_try(Completion::Break) {
+            self.emit_exit_to_finally(Completion::Break);
+            return;
+        }
+        let target = self.get_break_target().unwrap();
+        let scope_depth = self.compiling_loops.last().unwrap()[target].scope_depth;
+        self.emit_locals_removal(scope_depth);
+        let break_jump = self.emit_jump_instruction(OperationCode::Jump(chunk::MAX_JUMP_ARGUMENT));
+        self.compiling_loops.last_mut().unwrap()[target]
+            .break_jumps
+            .push(break_jump);
+    }
 
-        // Consume function parameters
-        if !self.check_current(&TokenType::RightParen) {
-            // First parameter
-            self.functions.last_mut().unwrap().borrow_mut().arity += 1;
-            let index = self.parse_variable("Expect parameter name");
-            self.define_variable(index);
-            // Other parameters
-            while self.match_current(&TokenType::Comma) {
-                self.functions.last_mut().unwrap().borrow_mut().arity += 1;
-                if self.functions.last_mut().unwrap().borrow_mut().arity > u8::MAX as usize {
-                    self.handle_error_at_token(
-                        &self.parser.current.unwrap(),
-                        "Can't have more than 255 parameters.",
-                    );
-                }
-                let index = self.parse_variable("Expect parameter name");
-                self.define_variable(index);
-            }
+    fn handle_continue_statement(&mut self) {
+        let is_inside_loop = self.get_continue_target().is_some();
+        if !is_inside_loop {
+            self.handle_error_at_token(
+                &self.parser.previous.unwrap(),
+                "Can't use 'continue' outside of a loop.",
+            );
         }
+        self.consume(TokenType::Semicolon, "Expect ';' after 'continue'.");
+        if is_inside_loop {
+            self.emit_continue();
+        }
+    }
 
-        self.consume(TokenType::RightParen, "Expect ')' after parameters.");
-        self.consume(TokenType::LeftBrace, "Expect '{' before function body.");
+    fn emit_continue(&mut self) {
+        if self.is_leaving_try(Completion::Continue) {
+            self.emit_exit_to_finally(Completion::Continue);
+            return;
+        }
+        let target = self.get_continue_target().unwrap();
+        let compiling_loop = &self.compiling_loops.last().unwrap()[target];
+        let (scope_depth, continue_target) = (
+            compiling_loop.scope_depth,
+            compiling_loop.continue_target.unwrap(),
+        );
+        self.emit_locals_removal(scope_depth);
+        self.emit_jump_back_instruction(continue_target);
+    }
+
+    /// Index of the innermost loop or switch of the current function
+    fn get_break_target(&self) -> Option<usize> {
+        self.compiling_loops.last().unwrap().len().checked_sub(1)
+    }
+
+    /// Index of the innermost loop of the current function, switches are skipped
+    fn get_continue_target(&self) -> Option<usize> {
+        self.compiling_loops
+            .last()
+            .unwrap()
+            .iter()
+            .rposition(|compiling_loop| compiling_loop.continue_target.is_some())
+    }
 
+    // Works like `end_scope` for every scope deeper than `scope_depth`, but the locals stay in the compiler,
+    // as the code after the jump (until the end of their scopes) still sees them
+    fn emit_locals_removal(&mut self, scope_depth: i32) {
+        let locals_to_remove: Vec<bool> = self
+            .locals
+            .last()
+            .unwrap()
+            .iter()
+            .rev()
+            .take_while(|local| local.depth > scope_depth)
+            .map(|local| local.is_captured)
+            .collect();
+        for is_captured in locals_to_remove {
+            match is_captured {
+                true => self.emit_instruction(OperationCode::CloseUpvalue),
+                false => self.emit_instruction(OperationCode::PopStack),
+            };
+        }
+    }
+
+    fn handle_throw_statement(&mut self) {
+        let throw_token = self.parser.previous.unwrap();
+        self.compile_expression();
+        self.consume(TokenType::Semicolon, "Expect ';' after thrown value.");
+        self.emit_instruction_at(OperationCode::Throw, &throw_token);
+    }
+
+    // Function containing `yield` is a generator - calling it creates a coroutine instead of executing its body
+    fn handle_yield(&mut self) {
+        let yield_token = self.parser.previous.unwrap();
+        match self.functions_types.last().unwrap() {
+            FunctionType::Script => {
+                self.handle_error_at_token(&yield_token, "Can't yield from top level script.")
+            }
+            FunctionType::Initializer => {
+                self.handle_error_at_token(&yield_token, "Can't yield from an initializer.")
+            }
+            FunctionType::Setter => {
+                self.handle_error_at_token(&yield_token, "Can't yield from a setter.")
+            }
+            _ => (),
+        }
+        self.functions.last().unwrap().borrow_mut().is_generator = true;
+
+        // Yielded value can be omitted (e.g. `yield;`), then it's nil
+        let has_value = !matches!(
+            self.parser.current.unwrap().token_type,
+            TokenType::Semicolon
+                | TokenType::RightParen
+                | TokenType::RightBracket
+                | TokenType::RightBrace
+                | TokenType::Comma
+                | TokenType::Colon
+        );
+        if has_value {
+            self.parse_precendence(Precedence::Assignment);
+        } else {
+            self.emit_instruction(OperationCode::Nil);
+        }
+        self.emit_instruction_at(OperationCode::Yield, &yield_token);
+    }
+
+    // `resume(coroutine)` or `resume(coroutine, value)` - value becomes the result of the `yield` the coroutine is
+    // suspended at
+    fn handle_resume(&mut self) {
+        let resume_token = self.parser.previous.unwrap();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'resume'.");
+        self.compile_expression();
+        if self.match_current(&TokenType::Comma) {
+            self.compile_expression();
+        } else {
+            self.emit_instruction(OperationCode::Nil);
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after resumed value.");
+        self.emit_instruction_at(OperationCode::Resume, &resume_token);
+    }
+
+    // Every way of leaving try statement goes through the finally block (even if there is none):
+    // - end of `try` or `catch` block - completion is `Normal`,
+    // - exception that isn't caught or is thrown by `catch` block - it's stored in the hidden local and thrown again,
+    // - `return`, `break` or `continue` - returned value is stored in the hidden local and the exit is resumed.
+    fn handle_try_statement(&mut self) {
+        self.start_scope();
+        self.emit_instruction(OperationCode::Nil);
+        let value_index = self.add_hidden_local(TokenType::Try);
+        self.emit_completion(Completion::Normal);
+        let completion_index = self.add_hidden_local(TokenType::Try);
+        self.compiling_tries.last_mut().unwrap().push(CompilingTry {
+            loops_count: self.compiling_loops.last().unwrap().len(),
+            scope_depth: self.current_scope_depth,
+            value_index,
+            completion_index,
+            finally_jumps: vec![],
+            pending_exits: vec![],
+        });
+
+        let try_handler_index =
+            self.emit_jump_instruction(OperationCode::PushHandler(chunk::MAX_JUMP_ARGUMENT));
+        self.consume(TokenType::LeftBrace, "Expect '{' after 'try'.");
+        self.start_scope();
         self.handle_block_statement();
+        self.end_scope();
+        self.emit_instruction(OperationCode::PopHandler);
+        self.emit_jump_to_finally();
 
+        // Handler is entered with the thrown value on the stack top
+        self.patch_jump_instruction(
+            OperationCode::PushHandler(chunk::MAX_JUMP_ARGUMENT),
+            try_handler_index,
+        );
+        if self.match_current(&TokenType::Catch) {
+            self.start_scope();
+            self.consume(TokenType::LeftParen, "Expect '(' after 'catch'.");
+            self.consume(TokenType::Identifier, "Expect exception variable name.");
+            // Thrown value becomes the exception variable
+            self.declare_variable();
+            self.mark_last_initialized();
+            self.consume(
+                TokenType::RightParen,
+                "Expect ')' after exception variable.",
+            );
+
+            let catch_handler_index =
+                self.emit_jump_instruction(OperationCode::PushHandler(chunk::MAX_JUMP_ARGUMENT));
+            self.consume(TokenType::LeftBrace, "Expect '{' after catch clause.");
+            self.start_scope();
+            self.handle_block_statement();
+            self.end_scope();
+            self.emit_instruction(OperationCode::PopHandler);
+            let is_exception_variable_captured =
+                self.locals.last().unwrap().last().unwrap().is_captured;
+            self.end_scope();
+            self.emit_jump_to_finally();
+
+            // Exception thrown from the catch block is on the stack top, right above the exception variable
+            self.patch_jump_instruction(
+                OperationCode::PushHandler(chunk::MAX_JUMP_ARGUMENT),
+                catch_handler_index,
+            );
+            self.emit_instruction(OperationCode::SetLocal(value_index));
+            self.emit_instruction(OperationCode::PopStack);
+            match is_exception_variable_captured {
+                true => self.emit_instruction(OperationCode::CloseUpvalue),
+                false => self.emit_instruction(OperationCode::PopStack),
+            };
+        } else {
+            if !self.check_current(&TokenType::Finally) {
+                self.handle_error_at_token(
+                    &self.parser.current.unwrap(),
+                    "Expect 'catch' or 'finally' after try block.",
+                );
+            }
+            self.emit_instruction(OperationCode::SetLocal(value_index));
+            self.emit_instruction(OperationCode::PopStack);
+        }
+        self.emit_completion(Completion::Throw);
+        self.emit_instruction(OperationCode::SetLocal(completion_index));
+        self.emit_instruction(OperationCode::PopStack);
+
+        // Exits from the finally block don't go through it again
+        let compiling_try = self
+            .compiling_tries
+            .last_mut()
+            .unwrap()
+            .pop()
+            .expect("Try statement should be compiled until its finally block");
+        for finally_jump in compiling_try.finally_jumps {
+            self.patch_jump_instruction(
+                OperationCode::Jump(chunk::MAX_JUMP_ARGUMENT),
+                finally_jump,
+            );
+        }
+        if self.match_current(&TokenType::Finally) {
+            self.consume(TokenType::LeftBrace, "Expect '{' after 'finally'.");
+            self.start_scope();
+            self.handle_block_statement();
+            self.end_scope();
+        }
+
+        // Resume the reason of entering the finally block, code of each completion never falls through
+        let completions =
+            std::iter::once(Completion::Throw).chain(compiling_try.pending_exits.iter().copied());
+        for completion in completions {
+            self.emit_instruction(OperationCode::GetLocal(completion_index));
+            self.emit_completion(completion);
+            self.emit_instruction(OperationCode::Equal);
+            let skip_completion_index =
+                self.emit_jump_instruction(OperationCode::JumpIfFalse(chunk::MAX_JUMP_ARGUMENT));
+            self.emit_instruction(OperationCode::PopStack);
+            match completion {
+                Completion::Throw => {
+                    self.emit_instruction(OperationCode::GetLocal(value_index));
+                    self.emit_instruction(OperationCode::Throw);
+                }
+                Completion::Return => {
+                    self.emit_instruction(OperationCode::GetLocal(value_index));
+                    self.emit_return();
+                }
+                Completion::Break => self.emit_break(),
+                Completion::Continue => self.emit_continue(),
+                Completion::Normal => unreachable!("Normal completion doesn't have to be resumed"),
+            }
+            self.patch_jump_instruction(
+                OperationCode::JumpIfFalse(chunk::MAX_JUMP_ARGUMENT),
+                skip_completion_index,
+            );
+            self.emit_instruction(OperationCode::PopStack);
+        }
         self.end_scope();
+    }
 
-        self.current_scope_depth = previous_depth;
+    fn emit_completion(&mut self, completion: Completion) {
+        self.emit_constant(Value::new_number(completion as u8 as f64));
+    }
 
-        self.emit_return_instruction();
+    fn emit_jump_to_finally(&mut self) {
+        let finally_jump =
+            self.emit_jump_instruction(OperationCode::Jump(chunk::MAX_JUMP_ARGUMENT));
+        self.compiling_tries
+            .last_mut()
+            .unwrap()
+            .last_mut()
+            .expect("Jump to finally block should be emitted inside try statement")
+            .finally_jumps
+            .push(finally_jump);
+    }
+
+    /// Check if the exit leaves the innermost try statement (before reaching its finally block)
+    fn is_leaving_try(&self, exit: Completion) -> bool {
+        let Some(compiling_try) = self.compiling_tries.last().unwrap().last() else {
+            return false;
+        };
+        let target = match exit {
+            Completion::Break => self.get_break_target(),
+            Completion::Continue => self.get_continue_target(),
+            _ => return true,
+        };
+        target.unwrap() < compiling_try.loops_count
+    }
+
+    /// Leave the innermost try statement through its finally block, the exit is resumed after it
+    fn emit_exit_to_finally(&mut self, exit: Completion) {
+        let compiling_try = self.compiling_tries.last().unwrap().last().unwrap();
+        let (value_index, completion_index, scope_depth) = (
+            compiling_try.value_index,
+            compiling_try.completion_index,
+            compiling_try.scope_depth,
+        );
+        if exit == Completion::Return {
+            self.emit_instruction(OperationCode::SetLocal(value_index));
+            self.emit_instruction(OperationCode::PopStack);
+        }
+        self.emit_completion(exit);
+        self.emit_instruction(OperationCode::SetLocal(completion_index));
+        self.emit_instruction(OperationCode::PopStack);
+        self.emit_locals_removal(scope_depth);
+        self.emit_instruction(OperationCode::PopHandler);
+        self.emit_jump_to_finally();
+
+        let pending_exits = &mut self
+            .compiling_tries
+            .last_mut()
+            .unwrap()
+            .last_mut()
+            .unwrap()
+            .pending_exits;
+        if !pending_exits.contains(&exit) {
+            pending_exits.push(exit);
+        }
+    }
+
+    fn handle_return_statement(&mut self) {
+        if *self.functions_types.last().unwrap() == FunctionType::Script {
+            self.handle_error_at_token(
+                &self.parser.previous.unwrap(),
+                "Cannot return from top level script.",
+            );
+            return;
+        }
+
+        if self.match_current(&TokenType::Semicolon) {
+            self.emit_return_instruction();
+            return;
+        } else {
+            // If we are using "return" inside initializer, and it's followed by expression (meaning we are trying to return
+            // value from initializer), we report it as error
+            match self.functions_types.last().unwrap() {
+                FunctionType::Initializer => self.handle_error_at_token(
+                    &self.parser.previous.unwrap(),
+                    "Can't return a value from an initializer.",
+                ),
+                FunctionType::Setter => self.handle_error_at_token(
+                    &self.parser.previous.unwrap(),
+                    "Can't return a value from a setter.",
+                ),
+                _ => (),
+            }
+        }
+
+        self.compile_expression();
+        self.consume(TokenType::Semicolon, "Expect ';' after return value.");
+        self.emit_return();
+    }
+
+    fn handle_function_declaration(&mut self) {
+        let index = self.parse_variable("Expect function name.");
+        if self.current_scope_depth > 0 {
+            // We do it automatically because we allow function to use itself in its own initializer (needed for recursion)
+            self.mark_last_initialized();
+        }
+        self.handle_function(FunctionType::Function);
+        self.define_variable(index);
+    }
+
+    fn handle_function(&mut self, function_type: FunctionType) {
+        let name = self.get_lexeme_from_token(&self.parser.previous.unwrap());
+        let previous_depth = self.start_function(function_type, name);
+        self.consume(TokenType::LeftParen, "Expect '(' after function name.");
+        self.parse_parameters();
+        self.consume(TokenType::LeftBrace, "Expect '{' before function body.");
+        self.handle_block_statement();
+        self.end_function(previous_depth);
+    }
+
+    fn handle_lambda(&mut self) {
+        // We asume 'fun' was already consumed
+        let name = Self::get_lambda_name(&self.parser.previous.unwrap());
+        let previous_depth = self.start_function(FunctionType::Function, &name);
+        self.consume(TokenType::LeftParen, "Expect '(' after 'fun'.");
+        self.parse_parameters();
+        self.consume(TokenType::LeftBrace, "Expect '{' before function body.");
+        self.handle_block_statement();
+        self.end_function(previous_depth);
+    }
+
+    fn handle_arrow_function(&mut self) {
+        // We asume '(' was already consumed
+        let name = Self::get_lambda_name(&self.parser.previous.unwrap());
+        let previous_depth = self.start_function(FunctionType::Function, &name);
+        self.parse_parameters();
+        self.consume(TokenType::EqualGreater, "Expect '=>' after parameters.");
+        // Body of the arrow function is a single expression, which value is returned
+        self.compile_expression();
+        self.emit_return();
+        self.end_function(previous_depth);
+    }
+
+    /// Check (without consuming anything) if just consumed '(' starts parameters of the arrow function `(a, b) => a + b`
+    fn is_arrow_function_ahead(&self) -> bool {
+        let mut lexer = self.lexer.clone();
+        let mut next_token_type = || {
+            lexer
+                .scan_token()
+                .map_or(TokenType::Eof, |token| token.token_type)
+        };
+        let mut token_type = self.parser.current.unwrap().token_type;
+        if token_type != TokenType::RightParen {
+            loop {
+                if token_type != TokenType::Identifier {
+                    return false;
+                }
+                match next_token_type() {
+                    TokenType::Comma => token_type = next_token_type(),
+                    TokenType::RightParen => break,
+                    _ => return false,
+                }
+            }
+        }
+        next_token_type() == TokenType::EqualGreater
+    }
+
+    /// Anonymous functions are named after the line they start at, so they can be told apart in stack traces
+    fn get_lambda_name(start_token: &Token) -> String {
+        format!("<lambda@line {}>", start_token.line)
+    }
+
+    /// Start compiling new function, returns scope depth of the enclosing function, that should be passed to `end_function`
+    fn start_function(&mut self, function_type: FunctionType, name: &str) -> i32 {
+        let current_function = FunctionObject::new_rc(name);
+        current_function.borrow_mut().module_index = self.module_index;
+        let special_token_type = match function_type {
+            FunctionType::Method => TokenType::This,
+            FunctionType::Initializer => TokenType::This,
+            FunctionType::Setter => TokenType::This,
+            _ => TokenType::Identifier,
+        };
+        let current_locals = vec![Local {
+            name: Token {
+                token_type: special_token_type,
+                start: 0,
+                length: 0,
+                line: 0,
+                column: 0,
+            },
+            depth: 0,
+            is_captured: false,
+            is_mutable: true,
+        }];
+
+        let previous_depth = self.current_scope_depth;
+
+        self.functions.push(current_function);
+        self.functions_types.push(function_type);
+        self.locals.push(current_locals);
+        self.upvalues.push(vec![]);
+        self.compiling_loops.push(vec![]);
+        self.compiling_tries.push(vec![]);
+
+        self.current_scope_depth = 0;
+
+        self.start_scope();
+
+        previous_depth
+    }
+
+    /// Parse function parameters up to (and including) the closing ')'
+    fn parse_parameters(&mut self) {
+        // Consume function parameters
+        if !self.check_current(&TokenType::RightParen) {
+            // First parameter
+            self.functions.last_mut().unwrap().borrow_mut().arity += 1;
+            let index = self.parse_variable("Expect parameter name");
+            self.define_variable(index);
+            // Other parameters
+            while self.match_current(&TokenType::Comma) {
+                self.functions.last_mut().unwrap().borrow_mut().arity += 1;
+                if self.functions.last_mut().unwrap().borrow_mut().arity > u8::MAX as usize {
+                    self.handle_error_at_token(
+                        &self.parser.current.unwrap(),
+                        "Can't have more than 255 parameters.",
+                    );
+                }
+                let index = self.parse_variable("Expect parameter name");
+                self.define_variable(index);
+            }
+        }
+
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.");
+    }
+
+    /// Finish compiling current function and emit instructions creating its closure
+    fn end_function(&mut self, previous_depth: i32) {
+        if *self.functions_types.last().unwrap() == FunctionType::Setter {
+            // Setter results in its parameter, so it must be returned before the scope removes it from the stack
+            // (returning removes every local of the function anyway)
+            self.emit_return_instruction();
+            self.current_scope_depth = previous_depth;
+        } else {
+            self.end_scope();
+
+            self.current_scope_depth = previous_depth;
+
+            self.emit_return_instruction();
+        }
 
         let finished_function = self.functions.pop().unwrap();
 
         self.functions_types.pop().unwrap();
         self.locals.pop();
+        self.compiling_loops.pop();
+        self.compiling_tries.pop();
 
         let function_index = self.make_constant(Value::from(finished_function));
-        self.emit_instruction(OperationCode::Closure(function_index as u8));
+        self.emit_instruction(OperationCode::Closure(function_index));
 
         // Emitting all closure upvalues
         let upvalues = self.upvalues.pop().unwrap();
@@ -942,8 +1924,10 @@ impl<'a, 'b> Compiler<'a, 'b> {
     }
 
     fn handle_call(&mut self) {
+        // We asume '(' was already consumed
+        let left_paren = self.parser.previous.unwrap();
         let arguments_count = self.parse_argument_list();
-        self.emit_instruction(OperationCode::Call(arguments_count));
+        self.emit_instruction_at(OperationCode::Call(arguments_count), &left_paren);
     }
 
     fn handle_class_declaration(&mut self) {
@@ -961,11 +1945,15 @@ impl<'a, 'b> Compiler<'a, 'b> {
         self.declare_variable();
         self.emit_instruction(OperationCode::Class(name_constant));
         // We do it here so that we can use class inside it's own body (for something like factory methods etc)
-        self.define_variable(name_constant);
+        let variable_index = self.get_variable_index(class_name_token);
+        self.define_variable(variable_index);
 
         // We add new class to currently compiling classes
         self.compiling_classes.push(CompilingClass {
             has_base_class: false,
+            is_compiling_static_member: false,
+            is_trait: false,
+            member_names: vec![],
         });
 
         // Handle inheritance
@@ -991,6 +1979,7 @@ impl<'a, 'b> Compiler<'a, 'b> {
                 start: 0,
                 length: 0,
                 line: 0,
+                column: 0,
             });
             // Define "super"
             self.define_variable(0);
@@ -1006,12 +1995,29 @@ impl<'a, 'b> Compiler<'a, 'b> {
         // should be bind to, as the class name is right on the stack and can be read
         self.handle_named_variable(class_name_token, false);
 
-        // Class body
-        self.consume(TokenType::LeftBrace, "Expect '{' before class body.");
-        while !self.check_current(&TokenType::RightBrace) && !self.check_current(&TokenType::Eof) {
-            self.handle_class_method();
+        let mut trait_tokens = vec![];
+        let is_with_ahead = self.check_current(&TokenType::Identifier)
+            && self.get_lexeme_from_token(&self.parser.current.unwrap()) == WITH_KEYWORD;
+        if is_with_ahead {
+            self.advance();
+            loop {
+                self.consume(TokenType::Identifier, "Expect trait name.");
+                trait_tokens.push(self.parser.previous.unwrap());
+                if !self.match_current(&TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.handle_class_body();
+        self.report_trait_conflicts(&trait_tokens);
+
+        // Traits are mixed in after the body, so that the virtual machine knows which members the class defines itself.
+        // Conflicts between traits unknown to the compiler (e.g. imported or aliased ones) are reported there.
+        for trait_token in trait_tokens {
+            self.handle_named_variable(&trait_token, false);
+            self.emit_instruction_at(OperationCode::Mixin, &trait_token);
         }
-        self.consume(TokenType::RightBrace, "Expect '}' after class body.");
         // Remove class name from the stack
         self.emit_instruction(OperationCode::PopStack);
 
@@ -1026,8 +2032,159 @@ impl<'a, 'b> Compiler<'a, 'b> {
             .expect("Should not be empty during class compilation");
     }
 
+    fn handle_trait_declaration(&mut self) {
+        self.consume(TokenType::Identifier, "Expect trait name.");
+        let trait_name_token = self
+            .parser
+            .previous
+            .expect("Shouldn't be empty after consuming identifier");
+        let name_constant = self.make_identifier_constant(&trait_name_token);
+        self.declare_variable();
+        self.emit_instruction(OperationCode::Trait(name_constant));
+        let variable_index = self.get_variable_index(&trait_name_token);
+        self.define_variable(variable_index);
+
+        self.compiling_classes.push(CompilingClass {
+            has_base_class: false,
+            is_compiling_static_member: false,
+            is_trait: true,
+            member_names: vec![],
+        });
+
+        // Same as with classes, trait is kept on the stack top while its methods are defined
+        self.handle_named_variable(&trait_name_token, false);
+        self.handle_class_body();
+        // Remove trait from the stack
+        self.emit_instruction(OperationCode::PopStack);
+
+        let compiling_trait = self
+            .compiling_classes
+            .pop()
+            .expect("Should not be empty during trait compilation");
+        self.traits_members
+            .insert(trait_name_token.start, compiling_trait.member_names);
+        if self.current_scope_depth == 0 {
+            self.global_traits.insert(
+                self.get_lexeme_from_token(&trait_name_token).to_string(),
+                trait_name_token.start,
+            );
+        }
+    }
+
+    fn handle_class_body(&mut self) {
+        let is_trait = self.compiling_classes.last().unwrap().is_trait;
+        let (before_body_message, after_body_message) = match is_trait {
+            true => (
+                "Expect '{' before trait body.",
+                "Expect '}' after trait body.",
+            ),
+            false => (
+                "Expect '{' before class body.",
+                "Expect '}' after class body.",
+            ),
+        };
+        self.consume(TokenType::LeftBrace, before_body_message);
+        while !self.check_current(&TokenType::RightBrace) && !self.check_current(&TokenType::Eof) {
+            if self.match_current(&TokenType::Static) {
+                if is_trait {
+                    self.handle_error_at_token(
+                        &self.parser.previous.unwrap(),
+                        "Traits can't have static members.",
+                    );
+                }
+                self.handle_static_member();
+            } else {
+                self.handle_class_method();
+            }
+        }
+        self.consume(TokenType::RightBrace, after_body_message);
+    }
+
+    /// Report members defined by more than one of the mixed in traits, unless the class defines them itself.
+    /// Only traits declared in the same source are known to the compiler, the others are checked when they are mixed in.
+    fn report_trait_conflicts(&mut self, trait_tokens: &[Token]) {
+        let class_members = &self.compiling_classes.last().unwrap().member_names;
+        let mut conflict = None;
+        'traits: for (index, trait_token) in trait_tokens.iter().enumerate() {
+            let Some(trait_declaration) = self.resolve_trait_declaration(trait_token) else {
+                continue;
+            };
+            let trait_members = &self.traits_members[&trait_declaration];
+            for previous_token in trait_tokens[..index].iter() {
+                let Some(previous_declaration) = self.resolve_trait_declaration(previous_token)
+                else {
+                    continue;
+                };
+                if previous_declaration == trait_declaration {
+                    continue;
+                }
+                let previous_members = &self.traits_members[&previous_declaration];
+                let conflicting_member = trait_members.iter().find(|member| {
+                    previous_members.contains(member) && !class_members.contains(member)
+                });
+                if let Some(member) = conflicting_member {
+                    conflict = Some((
+                        *trait_token,
+                        format!(
+                            "Traits '{}' and '{}' both define '{}', class must define it itself.",
+                            self.get_lexeme_from_token(previous_token),
+                            self.get_lexeme_from_token(trait_token),
+                            member
+                        ),
+                    ));
+                    break 'traits;
+                }
+            }
+        }
+        if let Some((trait_token, message)) = conflict {
+            self.handle_error_at_token(&trait_token, &message);
+        }
+    }
+
+    /// Position of the trait declaration the name refers to, if it's a trait declared in this source.
+    /// Variables are looked up the same way as when they are read - locals of enclosing functions first, then globals.
+    fn resolve_trait_declaration(&self, name: &Token) -> Option<usize> {
+        for locals in self.locals.iter().rev() {
+            if let Ok(index) = self.resolve_local_variable(name, locals) {
+                let declaration = locals[index as usize].name.start;
+                return self
+                    .traits_members
+                    .contains_key(&declaration)
+                    .then_some(declaration);
+            }
+        }
+        self.global_traits
+            .get(self.get_lexeme_from_token(name))
+            .copied()
+    }
+
+    /// Remember the member defined in the class body, accessors are prefixed with "get " or "set ", as they don't
+    /// replace the method (nor the other accessor) with the same name
+    fn add_class_member_name(&mut self, prefix: &str, name: &Token) {
+        let name = format!("{}{}", prefix, self.get_lexeme_from_token(name));
+        self.compiling_classes
+            .last_mut()
+            .unwrap()
+            .member_names
+            .push(name);
+    }
+
     fn handle_class_method(&mut self) {
         self.consume(TokenType::Identifier, "Expect method name.");
+        // "get" and "set" are only special if they are followed by the property name, otherwise they are method names
+        if self.check_current(&TokenType::Identifier) {
+            match self.get_lexeme_from_token(&self.parser.previous.unwrap()) {
+                GETTER_KEYWORD => {
+                    self.handle_getter();
+                    return;
+                }
+                SETTER_KEYWORD => {
+                    self.handle_setter();
+                    return;
+                }
+                _ => (),
+            }
+        }
         let method_name_constant = self.make_identifier_constant(
             &self
                 .parser
@@ -1035,8 +2192,15 @@ impl<'a, 'b> Compiler<'a, 'b> {
                 .expect("Shouldn't be empty after consuming identifier."),
         );
 
-        let function_type = match self.get_lexeme_from_token(&self.parser.previous.unwrap()) {
-            INITIALIZER_NAME => FunctionType::Initializer,
+        let name = self.parser.previous.unwrap();
+        self.add_class_member_name("", &name);
+        let function_type = match self.get_lexeme_from_token(&name) {
+            INITIALIZER_NAME => {
+                if self.compiling_classes.last().unwrap().is_trait {
+                    self.handle_error_at_token(&name, "Traits can't have initializers.");
+                }
+                FunctionType::Initializer
+            }
             _ => FunctionType::Method,
         };
         self.handle_function(function_type);
@@ -1044,39 +2208,192 @@ impl<'a, 'b> Compiler<'a, 'b> {
         self.emit_instruction(OperationCode::Method(method_name_constant));
     }
 
+    fn handle_getter(&mut self) {
+        self.consume(TokenType::Identifier, "Expect property name.");
+        let name = self
+            .parser
+            .previous
+            .expect("Shouldn't be empty after consuming identifier.");
+        let name_constant = self.make_identifier_constant(&name);
+        self.add_class_member_name("get ", &name);
+
+        // Getter is a method without parameters list, as it's called by reading the property
+        let previous_depth =
+            self.start_function(FunctionType::Method, self.get_lexeme_from_token(&name));
+        self.consume(TokenType::LeftBrace, "Expect '{' before getter body.");
+        self.handle_block_statement();
+        self.end_function(previous_depth);
+
+        self.emit_instruction(OperationCode::Getter(name_constant));
+    }
+
+    fn handle_setter(&mut self) {
+        self.consume(TokenType::Identifier, "Expect property name.");
+        let name = self
+            .parser
+            .previous
+            .expect("Shouldn't be empty after consuming identifier.");
+        let name_constant = self.make_identifier_constant(&name);
+        self.add_class_member_name("set ", &name);
+
+        let previous_depth =
+            self.start_function(FunctionType::Setter, self.get_lexeme_from_token(&name));
+        self.consume(TokenType::LeftParen, "Expect '(' after property name.");
+        self.parse_parameters();
+        if self.functions.last().unwrap().borrow().arity != 1 {
+            self.handle_error_at_token(&name, "Setter must have exactly one parameter.");
+        }
+        self.consume(TokenType::LeftBrace, "Expect '{' before setter body.");
+        self.handle_block_statement();
+        self.end_function(previous_depth);
+
+        self.emit_instruction(OperationCode::Setter(name_constant));
+    }
+
+    /// Static method (`static name(...) {...}`) or class-level field (`static name = value;`)
+    fn handle_static_member(&mut self) {
+        self.consume(TokenType::Identifier, "Expect static member name.");
+        let name = self
+            .parser
+            .previous
+            .expect("Shouldn't be empty after consuming identifier.");
+        let name_constant = self.make_identifier_constant(&name);
+
+        self.compiling_classes
+            .last_mut()
+            .unwrap()
+            .is_compiling_static_member = true;
+        if self.check_current(&TokenType::LeftParen) {
+            // Static method is called without receiver, so it's compiled just like ordinary function
+            self.handle_function(FunctionType::Function);
+        } else {
+            if self.match_current(&TokenType::Equal) {
+                self.compile_expression();
+            } else {
+                self.emit_instruction(OperationCode::Nil);
+            }
+            self.consume(
+                TokenType::Semicolon,
+                "Expect ';' after static field declaration.",
+            );
+        }
+        self.compiling_classes
+            .last_mut()
+            .unwrap()
+            .is_compiling_static_member = false;
+
+        // Class is right below the value on the stack, same as for methods
+        self.emit_instruction_at(OperationCode::StaticField(name_constant), &name);
+    }
+
     fn handle_dot(&mut self, can_assign: bool) {
         self.consume(TokenType::Identifier, "Expect property name after '.'.");
-        let name_constant = self.make_identifier_constant(
-            &self
-                .parser
-                .previous
-                .expect("Shouldn't be empty after consuming identifier"),
-        );
+        let name = self
+            .parser
+            .previous
+            .expect("Shouldn't be empty after consuming identifier");
+        let name_constant = self.make_identifier_constant(&name);
 
         if can_assign && self.match_current(&TokenType::Equal) {
             self.compile_expression();
-            self.emit_instruction(OperationCode::SetProperty(name_constant));
+            self.emit_instruction_at(OperationCode::SetProperty(name_constant), &name);
         } else if self.match_current(&TokenType::LeftParen) {
             // If we encounter '(' right after property, we know it's gonna emmit later "Call" operation,
             // so we emit special optimize instruction which does both
             let arguments_count = self.parse_argument_list();
-            self.emit_instruction(OperationCode::InvokeProperty(
-                name_constant,
-                arguments_count,
-            ));
+            self.emit_instruction_at(
+                OperationCode::InvokeProperty(name_constant, arguments_count),
+                &name,
+            );
         } else {
             self.emit_instruction(OperationCode::GetProperty(name_constant));
         }
     }
 
+    fn handle_list(&mut self) {
+        // We asume '[' was already consumed
+        let mut items_count: u32 = 0;
+        if !self.check_current(&TokenType::RightBracket) {
+            loop {
+                self.compile_expression();
+                if items_count == chunk::MAX_INDEX_ARGUMENT {
+                    self.handle_error_at_token(
+                        &self.parser.previous.unwrap(),
+                        "Too many items in list literal.",
+                    );
+                }
+                items_count = items_count.saturating_add(1);
+                // Trailing comma is allowed, so that long lists can be written one item per line
+                if !self.match_current(&TokenType::Comma)
+                    || self.check_current(&TokenType::RightBracket)
+                {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightBracket, "Expect ']' after list items.");
+        self.emit_instruction(OperationCode::BuildList(items_count));
+    }
+
+    // Block statements are handled before expressions, so '{' at the start of expression always begins a map
+    fn handle_map(&mut self) {
+        // We asume '{' was already consumed
+        let mut entries_count: u32 = 0;
+        if !self.check_current(&TokenType::RightBrace) {
+            loop {
+                self.compile_expression();
+                self.consume(TokenType::Colon, "Expect ':' after map key.");
+                self.compile_expression();
+                if entries_count == chunk::MAX_INDEX_ARGUMENT {
+                    self.handle_error_at_token(
+                        &self.parser.previous.unwrap(),
+                        "Too many entries in map literal.",
+                    );
+                }
+                entries_count = entries_count.saturating_add(1);
+                if !self.match_current(&TokenType::Comma)
+                    || self.check_current(&TokenType::RightBrace)
+                {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after map entries.");
+        self.emit_instruction(OperationCode::BuildMap(entries_count));
+    }
+
+    fn handle_index(&mut self, can_assign: bool) {
+        // We asume '[' was already consumed
+        let left_bracket = self.parser.previous.unwrap();
+        self.compile_expression();
+        self.consume(TokenType::RightBracket, "Expect ']' after index.");
+
+        if can_assign && self.match_current(&TokenType::Equal) {
+            self.compile_expression();
+            self.emit_instruction_at(OperationCode::SetIndex, &left_bracket);
+        } else {
+            self.emit_instruction_at(OperationCode::GetIndex, &left_bracket);
+        }
+    }
+
     fn handle_this(&mut self) {
         // If we aren't compiling any class using this is an error
-        if self.compiling_classes.is_empty() {
-            self.handle_error_at_token(
-                &self.parser.previous.unwrap(),
-                "Can't use 'this' outside of class.",
-            );
-            return;
+        match self.compiling_classes.last() {
+            Some(compiling_class) if compiling_class.is_compiling_static_member => {
+                self.handle_error_at_token(
+                    &self.parser.previous.unwrap(),
+                    "Can't use 'this' in static member.",
+                );
+                return;
+            }
+            Some(_) => (),
+            None => {
+                self.handle_error_at_token(
+                    &self.parser.previous.unwrap(),
+                    "Can't use 'this' outside of class.",
+                );
+                return;
+            }
         }
         // We treat "this" as local variable so we have a lot of features for free
         // But we need to actually store it, and we do it by using
@@ -1086,6 +2403,18 @@ impl<'a, 'b> Compiler<'a, 'b> {
 
     fn handle_super(&mut self) {
         match self.compiling_classes.last() {
+            Some(compiling_class) if compiling_class.is_compiling_static_member => {
+                self.handle_error_at_token(
+                    &self.parser.previous.unwrap(),
+                    "Can't use 'super' in static member.",
+                );
+            }
+            Some(compiling_class) if compiling_class.is_trait => {
+                self.handle_error_at_token(
+                    &self.parser.previous.unwrap(),
+                    "Can't use 'super' in trait.",
+                );
+            }
             Some(compiling_class) => {
                 if !compiling_class.has_base_class {
                     self.handle_error_at_token(
@@ -1113,6 +2442,7 @@ impl<'a, 'b> Compiler<'a, 'b> {
                 start: 0,
                 length: 0,
                 line: 0,
+                column: 0,
             },
             false,
         );
@@ -1121,6 +2451,7 @@ impl<'a, 'b> Compiler<'a, 'b> {
             start: 0,
             length: 0,
             line: 0,
+            column: 0,
         };
         // Optimize immediate super method call
         if self.match_current(&TokenType::LeftParen) {
@@ -1193,16 +2524,21 @@ impl<'a, 'b> Compiler<'a, 'b> {
         }
     }
 
-    fn parse_variable(&mut self, message: &str) -> u8 {
+    fn parse_variable(&mut self, message: &str) -> u32 {
         self.consume(TokenType::Identifier, message);
 
         self.declare_variable();
-        // If we are not in global scope we don't need to add the variable to the constant table
+        self.get_variable_index(&self.parser.previous.unwrap())
+    }
+
+    /// Index expected by `define_variable` - slot for global variable and 0 for local one
+    fn get_variable_index(&mut self, name: &Token) -> u32 {
+        // If we are not in global scope we don't need a global slot
         if self.current_scope_depth > 0 {
             return 0;
         }
 
-        self.make_identifier_constant(&self.parser.previous.unwrap())
+        self.resolve_global_slot(name)
     }
 
     fn are_identifiers_equal(&self, lhs: &Token, rhs: &Token) -> bool {
@@ -1221,7 +2557,7 @@ impl<'a, 'b> Compiler<'a, 'b> {
         self.get_lexeme_from_token(lhs) == self.get_lexeme_from_token(rhs)
     }
 
-    fn define_variable(&mut self, var_index: u8) {
+    fn define_variable(&mut self, var_index: u32) {
         if self.current_scope_depth > 0 {
             self.mark_last_initialized();
             return;
@@ -1229,9 +2565,25 @@ impl<'a, 'b> Compiler<'a, 'b> {
         self.emit_instruction(OperationCode::DefineGlobal(var_index));
     }
 
+    fn define_constant(&mut self, var_index: u32) {
+        if self.current_scope_depth > 0 {
+            self.mark_last_initialized();
+            self.locals
+                .last_mut()
+                .unwrap()
+                .last_mut()
+                .expect("Last local shouldn't be empty when marking as constant.")
+                .is_mutable = false;
+            return;
+        }
+        self.emit_instruction(OperationCode::DefineConstantGlobal(var_index));
+    }
+
     fn declare_variable(&mut self) {
-        // We don't declare globals
+        // We don't declare globals, but redeclared global no longer refers to the trait (until trait declaration adds it back)
         if self.current_scope_depth == 0 {
+            let name = self.get_lexeme_from_token(&self.parser.previous.unwrap());
+            self.global_traits.remove(name);
             return;
         }
         let name = self.parser.previous.unwrap();
@@ -1260,8 +2612,7 @@ impl<'a, 'b> Compiler<'a, 'b> {
     }
 
     fn add_local_variable(&mut self, name: Token) {
-        // We use one-byte index in our vm, so we cannot have more than u8::MAX + 1
-        if self.locals.last().unwrap().len() == u8::MAX as usize + 1 {
+        if self.locals.last().unwrap().len() == chunk::MAX_INDEX_ARGUMENT as usize + 1 {
             self.handle_error_at_token(
                 &self.parser.previous.unwrap(),
                 "Too many local variables in scope.",
@@ -1272,13 +2623,28 @@ impl<'a, 'b> Compiler<'a, 'b> {
             name,
             depth: UNINITIALIZED_DEPTH,
             is_captured: false,
+            is_mutable: true,
+        });
+    }
+
+    /// Add already initialized local for value on the stack top, which is used by the compiler itself, return its index.
+    /// Fake token can't be equal to any identifier, so the local can't be referenced by user.
+    fn add_hidden_local(&mut self, token_type: TokenType) -> u32 {
+        self.add_local_variable(Token {
+            token_type,
+            start: 0,
+            length: 0,
+            line: 0,
+            column: 0,
         });
+        self.mark_last_initialized();
+        self.locals.last().unwrap().len() as u32 - 1
     }
 
-    fn add_upvalue(&mut self, index: usize, is_local: bool, depth: usize) -> usize {
+    fn add_upvalue(&mut self, index: u32, is_local: bool, is_mutable: bool, depth: usize) -> u32 {
         let upvalue_count = self.functions[depth].borrow().upvalues_count;
 
-        if index > u8::MAX as usize {
+        if upvalue_count > chunk::MAX_INDEX_ARGUMENT as usize {
             self.handle_error_at_token(
                 &self.parser.previous.unwrap(),
                 "Too many upvalues in the function.",
@@ -1287,8 +2653,9 @@ impl<'a, 'b> Compiler<'a, 'b> {
         }
 
         let upvalue = Upvalue {
-            index: index as u8,
+            index,
             is_local,
+            is_mutable,
         };
 
         match self
@@ -1299,11 +2666,11 @@ impl<'a, 'b> Compiler<'a, 'b> {
             .enumerate()
             .find(|(_, already_existing)| **already_existing == upvalue)
         {
-            Some((non_local_index, _)) => non_local_index,
+            Some((non_local_index, _)) => non_local_index as u32,
             None => {
                 self.upvalues[depth].push(upvalue);
                 self.functions[depth].borrow_mut().upvalues_count += 1;
-                upvalue_count
+                upvalue_count as u32
             }
         }
     }
@@ -1312,13 +2679,13 @@ impl<'a, 'b> Compiler<'a, 'b> {
         &self,
         name: &Token,
         locals: &[Local],
-    ) -> Result<u8, LocalVariableError> {
+    ) -> Result<u32, LocalVariableError> {
         for (index, local) in locals.iter().enumerate().rev() {
             if self.are_identifiers_equal(&local.name, name) {
                 if local.depth == UNINITIALIZED_DEPTH {
                     return Err(LocalVariableError::UsedInOwnInitializer);
                 }
-                return Ok(index as u8);
+                return Ok(index as u32);
             }
         }
 
@@ -1330,15 +2697,16 @@ impl<'a, 'b> Compiler<'a, 'b> {
     // We can't just iterate over all the locals, because we want to create this upvalue in each elements of the chain
     // So for example, if we have functions a > b > c (> means is inside of)
     // and we have var x declared in scope a and used in c, we want to create upvalue in b as well
-    fn resolve_upvalue(&mut self, name: &Token, depth: usize) -> Result<u8, UpvalueError> {
+    fn resolve_upvalue(&mut self, name: &Token, depth: usize) -> Result<u32, UpvalueError> {
         let locals = &self.locals[depth];
 
         // When adding upvalue must use depth + 1, because the depth for locals is one level higher
         if let Ok(index) = self.resolve_local_variable(name, locals) {
-            let upvalue_index = self.add_upvalue(index as usize, true, depth + 1);
+            let is_mutable = self.locals[depth][index as usize].is_mutable;
+            let upvalue_index = self.add_upvalue(index, true, is_mutable, depth + 1);
             // Mark local as captured by this closure
             self.locals[depth][index as usize].is_captured = true;
-            return Ok(upvalue_index as u8);
+            return Ok(upvalue_index);
         }
 
         // We checked all the locals
@@ -1348,7 +2716,8 @@ impl<'a, 'b> Compiler<'a, 'b> {
 
         match self.resolve_upvalue(name, depth - 1) {
             Ok(upvalue_index) => {
-                Ok(self.add_upvalue(upvalue_index as usize, false, depth + 1) as u8)
+                let is_mutable = self.upvalues[depth][upvalue_index as usize].is_mutable;
+                Ok(self.add_upvalue(upvalue_index, false, is_mutable, depth + 1))
             }
             Err(_) => Err(UpvalueError::NotFound),
         }
@@ -1390,10 +2759,18 @@ impl<'a, 'b> Compiler<'a, 'b> {
     fn compile_declaration(&mut self) {
         if self.match_current(&TokenType::Class) {
             self.handle_class_declaration();
+        } else if self.match_current(&TokenType::Trait) {
+            self.handle_trait_declaration();
         } else if self.match_current(&TokenType::Fun) {
             self.handle_function_declaration();
         } else if self.match_current(&TokenType::Var) {
             self.handle_var_declaration();
+        } else if self.match_current(&TokenType::Const) {
+            self.handle_const_declaration();
+        } else if self.match_current(&TokenType::Import) {
+            self.handle_import_declaration();
+        } else if self.match_current(&TokenType::Export) {
+            self.handle_export_declaration();
         } else {
             self.compile_statement();
         }
@@ -1414,6 +2791,16 @@ impl<'a, 'b> Compiler<'a, 'b> {
             self.handle_return_statement();
         } else if self.match_current(&TokenType::While) {
             self.handle_while_statement();
+        } else if self.match_current(&TokenType::Break) {
+            self.handle_break_statement();
+        } else if self.match_current(&TokenType::Continue) {
+            self.handle_continue_statement();
+        } else if self.match_current(&TokenType::Switch) {
+            self.handle_switch_statement();
+        } else if self.match_current(&TokenType::Throw) {
+            self.handle_throw_statement();
+        } else if self.match_current(&TokenType::Try) {
+            self.handle_try_statement();
         } else if self.match_current(&TokenType::LeftBrace) {
             self.start_scope();
             self.handle_block_statement();
@@ -1450,9 +2837,14 @@ enum Precedence {
     And,
     Equality,
     Comparison,
+    BitwiseOr,
+    BitwiseXor,
+    BitwiseAnd,
+    Shift,
     Term,
     Factor,
     Unary,
+    Exponent,
     Call,
     Primary,
 }
@@ -1471,11 +2863,16 @@ impl From<Precedence> for u8 {
             Precedence::And => 3,
             Precedence::Equality => 4,
             Precedence::Comparison => 5,
-            Precedence::Term => 6,
-            Precedence::Factor => 7,
-            Precedence::Unary => 8,
-            Precedence::Call => 9,
-            Precedence::Primary => 10,
+            Precedence::BitwiseOr => 6,
+            Precedence::BitwiseXor => 7,
+            Precedence::BitwiseAnd => 8,
+            Precedence::Shift => 9,
+            Precedence::Term => 10,
+            Precedence::Factor => 11,
+            Precedence::Unary => 12,
+            Precedence::Exponent => 13,
+            Precedence::Call => 14,
+            Precedence::Primary => 15,
         }
     }
 }
@@ -1491,11 +2888,16 @@ impl TryFrom<u8> for Precedence {
             3 => Ok(Precedence::And),
             4 => Ok(Precedence::Equality),
             5 => Ok(Precedence::Comparison),
-            6 => Ok(Precedence::Term),
-            7 => Ok(Precedence::Factor),
-            8 => Ok(Precedence::Unary),
-            9 => Ok(Precedence::Call),
-            10 => Ok(Precedence::Primary),
+            6 => Ok(Precedence::BitwiseOr),
+            7 => Ok(Precedence::BitwiseXor),
+            8 => Ok(Precedence::BitwiseAnd),
+            9 => Ok(Precedence::Shift),
+            10 => Ok(Precedence::Term),
+            11 => Ok(Precedence::Factor),
+            12 => Ok(Precedence::Unary),
+            13 => Ok(Precedence::Exponent),
+            14 => Ok(Precedence::Call),
+            15 => Ok(Precedence::Primary),
             _ => Err(PrecedenceError::OutOfBounds),
         }
     }
@@ -1508,40 +2910,71 @@ impl From<&TokenType> for Precedence {
             TokenType::RightParen => Precedence::None,
             TokenType::LeftBrace => Precedence::None,
             TokenType::RightBrace => Precedence::None,
+            TokenType::LeftBracket => Precedence::Call,
+            TokenType::RightBracket => Precedence::None,
             TokenType::Comma => Precedence::None,
+            TokenType::Colon => Precedence::None,
             TokenType::Dot => Precedence::Call,
             TokenType::Minus => Precedence::Term,
             TokenType::Plus => Precedence::Term,
             TokenType::Semicolon => Precedence::None,
             TokenType::Star => Precedence::Factor,
             TokenType::Slash => Precedence::Factor,
+            TokenType::Percent => Precedence::Factor,
+            TokenType::Ampersand => Precedence::BitwiseAnd,
+            TokenType::Pipe => Precedence::BitwiseOr,
+            TokenType::Caret => Precedence::BitwiseXor,
+            TokenType::LessLess => Precedence::Shift,
+            TokenType::GreaterGreater => Precedence::Shift,
+            TokenType::StarStar => Precedence::Exponent,
+            TokenType::Tilde => Precedence::None,
+            TokenType::TildeSlash => Precedence::Factor,
             TokenType::Bang => Precedence::None,
             TokenType::BangEqual => Precedence::Equality,
             TokenType::Equal => Precedence::None,
             TokenType::EqualEqual => Precedence::Equality,
+            TokenType::EqualGreater => Precedence::None,
             TokenType::Greater => Precedence::Equality,
             TokenType::GreaterEqual => Precedence::Equality,
             TokenType::Less => Precedence::Equality,
             TokenType::LessEqual => Precedence::Equality,
             TokenType::Identifier => Precedence::None,
             TokenType::String => Precedence::None,
+            TokenType::RawString => Precedence::None,
+            TokenType::Interpolation => Precedence::None,
             TokenType::Number => Precedence::None,
             TokenType::And => Precedence::And,
+            TokenType::Break => Precedence::None,
             TokenType::Class => Precedence::None,
+            TokenType::Const => Precedence::None,
+            TokenType::Static => Precedence::None,
+            TokenType::Continue => Precedence::None,
+            TokenType::Switch => Precedence::None,
+            TokenType::Case => Precedence::None,
+            TokenType::Default => Precedence::None,
+            TokenType::Throw => Precedence::None,
+            TokenType::Trait => Precedence::None,
+            TokenType::Try => Precedence::None,
+            TokenType::Catch => Precedence::None,
+            TokenType::Finally => Precedence::None,
             TokenType::Else => Precedence::None,
+            TokenType::Export => Precedence::None,
             TokenType::False => Precedence::None,
             TokenType::For => Precedence::None,
             TokenType::Fun => Precedence::None,
             TokenType::If => Precedence::None,
+            TokenType::Import => Precedence::None,
             TokenType::Nil => Precedence::None,
             TokenType::Or => Precedence::Or,
             TokenType::Print => Precedence::None,
+            TokenType::Resume => Precedence::None,
             TokenType::Return => Precedence::None,
             TokenType::Super => Precedence::None,
             TokenType::This => Precedence::None,
             TokenType::True => Precedence::None,
             TokenType::Var => Precedence::None,
             TokenType::While => Precedence::None,
+            TokenType::Yield => Precedence::None,
             TokenType::Eof => Precedence::None,
         }
     }
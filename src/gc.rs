@@ -0,0 +1,208 @@
+use std::{
+    cell::RefCell,
+    mem,
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    logger::Logger,
+    table::Table,
+    value::{StringObject, Value},
+};
+
+// GENERAL IDEA:
+// Every heap object is `Rc<RefCell<..>>`, so reference counting already frees everything that isn't part
+// of a reference cycle. The garbage collector only has to deal with cycles. It keeps weak references to every
+// object that can hold other objects, marks everything reachable from the VM roots and then clears references
+// stored inside unreachable objects. Once these references are gone, reference counting frees the whole cycle.
+// Interned strings are kept alive by the intern table itself, so during collection every string that
+// is referenced only by that table is removed from it.
+pub trait Traceable {
+    fn is_marked(&self) -> bool;
+
+    fn set_marked(&mut self, is_marked: bool);
+
+    /// Mark every object directly referenced by this object
+    fn trace_references(&self, garbage_collector: &mut GarbageCollector);
+
+    /// Drop every reference to other objects, so that cycles going through this object are broken
+    fn clear_references(&mut self);
+
+    /// Approximate number of bytes allocated on the heap by the object, without the object itself
+    fn get_allocated_bytes(&self) -> usize;
+}
+
+type TrackedObject = Rc<RefCell<dyn Traceable>>;
+
+type TrackedEntry = Weak<RefCell<dyn Traceable>>;
+
+pub struct GarbageCollector {
+    /// All objects that might be a part of reference cycle
+    objects: Vec<TrackedEntry>,
+    /// Objects that are marked, but their references haven't been traced yet
+    gray_objects: Vec<TrackedObject>,
+    /// Approximate number of bytes allocated by tracked objects, interned strings and the intern table itself
+    bytes_allocated: usize,
+    /// When `bytes_allocated` exceeds this value, the next collection should be performed
+    next_collection: usize,
+    /// Maximum number of bytes that can stay allocated after the collection
+    heap_limit: Option<usize>,
+}
+
+impl GarbageCollector {
+    const INITIAL_COLLECTION_THRESHOLD: usize = 1024 * 1024;
+    const HEAP_GROW_FACTOR: usize = 2;
+
+    pub fn new() -> Self {
+        GarbageCollector {
+            objects: vec![],
+            gray_objects: vec![],
+            bytes_allocated: 0,
+            next_collection: Self::INITIAL_COLLECTION_THRESHOLD,
+            heap_limit: None,
+        }
+    }
+
+    pub fn set_heap_limit(&mut self, heap_limit: Option<usize>) {
+        self.heap_limit = heap_limit;
+    }
+
+    /// Start tracking newly allocated object, so it can be reclaimed even if it ends up in a reference cycle
+    pub fn track<T: Traceable + 'static>(&mut self, object: &Rc<RefCell<T>>) {
+        self.bytes_allocated += Self::get_object_size(&*object.borrow());
+        let object: TrackedObject = object.clone();
+        self.objects.push(Rc::downgrade(&object));
+    }
+
+    /// Account for memory allocated by already tracked object (e.g. when new field is added to an instance)
+    pub fn track_growth(&mut self, bytes: usize) {
+        self.bytes_allocated += bytes;
+    }
+
+    /// Strings aren't tracked as they can't be a part of cycle, but we still want them to count towards next collection
+    pub fn track_string(&mut self, string: &Rc<RefCell<StringObject>>) {
+        self.bytes_allocated += Self::get_string_size(&string.borrow());
+    }
+
+    pub fn should_collect(&self) -> bool {
+        // Collecting as often as possible helps finding objects that are used, but aren't reachable from the roots
+        if cfg!(feature = "stress_garbage_collection") {
+            return true;
+        }
+        self.bytes_allocated > self.next_collection || self.is_heap_limit_exceeded()
+    }
+
+    pub fn is_heap_limit_exceeded(&self) -> bool {
+        match self.heap_limit {
+            Some(heap_limit) => self.bytes_allocated > heap_limit,
+            None => false,
+        }
+    }
+
+    pub fn mark_value(&mut self, value: &Value) {
+        if let Ok(function) = value.get_function_object() {
+            self.mark_object(&function);
+        } else if let Ok(closure) = value.get_closure_object() {
+            self.mark_object(&closure);
+        } else if let Ok(class) = value.get_class_object() {
+            self.mark_object(&class);
+        } else if let Ok(instance) = value.get_instance_object() {
+            self.mark_object(&instance);
+        } else if let Ok(bound_method) = value.get_bound_method_object() {
+            self.mark_object(&bound_method);
+        } else if let Ok(list) = value.get_list_object() {
+            self.mark_object(&list);
+        } else if let Ok(map) = value.get_map_object() {
+            self.mark_object(&map);
+        } else if let Ok(coroutine) = value.get_coroutine_object() {
+            self.mark_object(&coroutine);
+        }
+        // Strings are handled separately (through the intern table) and other values aren't objects at all
+    }
+
+    pub fn mark_object<T: Traceable + 'static>(&mut self, object: &Rc<RefCell<T>>) {
+        if object.borrow().is_marked() {
+            return;
+        }
+        object.borrow_mut().set_marked(true);
+        self.gray_objects.push(object.clone());
+    }
+
+    /// Finish the collection - it must be called after all the roots are marked
+    pub fn collect(&mut self, intern_strings: &mut Table) {
+        let bytes_before = self.bytes_allocated;
+
+        self.trace_references();
+        self.sweep();
+        // It must happen after the sweep, as freeing cycles might release last references to some strings
+        self.sweep_strings(intern_strings);
+
+        self.next_collection = usize::max(
+            self.bytes_allocated * Self::HEAP_GROW_FACTOR,
+            Self::INITIAL_COLLECTION_THRESHOLD,
+        );
+
+        Logger::show_garbage_collection_summary(
+            bytes_before,
+            self.bytes_allocated,
+            self.next_collection,
+        );
+    }
+
+    fn trace_references(&mut self) {
+        // We use explicit gray stack instead of recursion, so that long chains of objects don't overflow the stack
+        while let Some(object) = self.gray_objects.pop() {
+            object.borrow().trace_references(self);
+        }
+    }
+
+    fn sweep(&mut self) {
+        let mut unreachable_objects: Vec<TrackedObject> = vec![];
+        let mut bytes_alive: usize = 0;
+
+        self.objects.retain(|entry| match entry.upgrade() {
+            Some(object) => {
+                let is_marked = object.borrow().is_marked();
+                if is_marked {
+                    object.borrow_mut().set_marked(false);
+                    bytes_alive += Self::get_object_size(&*object.borrow());
+                } else {
+                    unreachable_objects.push(object);
+                }
+                is_marked
+            }
+            // Object was already freed by reference counting
+            None => false,
+        });
+
+        for object in unreachable_objects.iter() {
+            object.borrow_mut().clear_references();
+        }
+
+        self.bytes_allocated = bytes_alive;
+        // Dropping last strong references - this is where the cycles are actually freed
+        drop(unreachable_objects);
+    }
+
+    fn sweep_strings(&mut self, intern_strings: &mut Table) {
+        intern_strings.remove_unreferenced_keys();
+        self.bytes_allocated += intern_strings.get_allocated_bytes();
+        for (string, _) in intern_strings.iter() {
+            self.bytes_allocated += Self::get_string_size(&string.borrow());
+        }
+    }
+
+    fn get_object_size<T: Traceable + ?Sized>(object: &T) -> usize {
+        mem::size_of_val(object) + object.get_allocated_bytes()
+    }
+
+    fn get_string_size(string: &StringObject) -> usize {
+        mem::size_of::<StringObject>() + string.get_value().len()
+    }
+}
+
+impl Default for GarbageCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,74 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    table::Table,
+    value::{StringObject, Value},
+};
+
+/// Mapping between names of global variables and their slots in the VM globals array.
+/// Slots are assigned by the compiler, but the mapping lives as long as the VM, so the same name
+/// always resolves to the same slot (e.g. when it's used in different REPL lines).
+pub struct GlobalSlots {
+    /// Slot index (stored as number) for each name
+    slots: Table,
+    /// Name for each slot index, used when reporting errors
+    names: Vec<Rc<RefCell<StringObject>>>,
+    /// For each slot index - true if the variable can be accessed by modules importing this one
+    exported: Vec<bool>,
+}
+
+impl GlobalSlots {
+    pub fn new() -> Self {
+        GlobalSlots {
+            slots: Table::new(),
+            names: vec![],
+            exported: vec![],
+        }
+    }
+
+    /// Return slot of the global variable with provided name, new slot is assigned if name is seen for the first time
+    pub fn resolve(&mut self, name: &Rc<RefCell<StringObject>>) -> u32 {
+        if let Ok(slot) = self.slots.get(name) {
+            return slot
+                .get_number()
+                .expect("Global slot should only be represented as number")
+                as u32;
+        }
+        let slot = self.names.len() as u32;
+        self.slots
+            .insert(name.clone(), Value::new_number(slot as f64));
+        self.names.push(name.clone());
+        self.exported.push(false);
+        slot
+    }
+
+    /// Return slot of the global variable with provided name, if it has one
+    pub fn find(&self, name: &Rc<RefCell<StringObject>>) -> Option<u32> {
+        self.slots.get(name).ok().map(|slot| {
+            slot.get_number()
+                .expect("Global slot should only be represented as number") as u32
+        })
+    }
+
+    pub fn export(&mut self, slot: u32) {
+        self.exported[slot as usize] = true;
+    }
+
+    pub fn is_exported(&self, slot: u32) -> bool {
+        self.exported[slot as usize]
+    }
+
+    pub fn get_name(&self, slot: u32) -> &Rc<RefCell<StringObject>> {
+        &self.names[slot as usize]
+    }
+
+    pub fn get_slots_count(&self) -> usize {
+        self.names.len()
+    }
+}
+
+impl Default for GlobalSlots {
+    fn default() -> Self {
+        Self::new()
+    }
+}
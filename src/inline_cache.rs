@@ -0,0 +1,59 @@
+use std::{
+    cell::RefCell,
+    rc::{Rc, Weak},
+};
+
+use crate::value::{ClassObject, ClosureObject};
+
+// GENERAL IDEA:
+// Every instruction that looks up a property (call site) has its own cache, which remembers where the property
+// was found the last time it was executed. Methods are cached per class - entry is valid only for the same class
+// and only if its methods haven't changed since (`Method` and `Inherit` change class methods version).
+// Fields can't be cached per class, as every instance has its own fields table, but instances of the same class
+// usually have the same layout, so the index of the field in the table is kept as a hint, that must be verified.
+// Cache keeps only weak references, so it never keeps objects alive and garbage collector doesn't have to know
+// about it.
+
+#[derive(Clone, Default)]
+pub struct InlineCache {
+    /// Class in which the method was found
+    class: Weak<RefCell<ClassObject>>,
+    /// Version of the class methods at the moment of caching
+    methods_version: u32,
+    method: Weak<RefCell<ClosureObject>>,
+    /// Index of the entry in the instance fields table, under which the field was found the last time
+    field_index: Option<usize>,
+}
+
+impl InlineCache {
+    /// Return cached method, if it was cached for provided class and class methods haven't changed since
+    pub fn get_method(
+        &self,
+        class: &Rc<RefCell<ClassObject>>,
+    ) -> Option<Rc<RefCell<ClosureObject>>> {
+        if Weak::as_ptr(&self.class) != Rc::as_ptr(class)
+            || self.methods_version != class.borrow().get_methods_version()
+        {
+            return None;
+        }
+        self.method.upgrade()
+    }
+
+    pub fn set_method(
+        &mut self,
+        class: &Rc<RefCell<ClassObject>>,
+        method: &Rc<RefCell<ClosureObject>>,
+    ) {
+        self.class = Rc::downgrade(class);
+        self.methods_version = class.borrow().get_methods_version();
+        self.method = Rc::downgrade(method);
+    }
+
+    pub fn get_field_index(&self) -> Option<usize> {
+        self.field_index
+    }
+
+    pub fn set_field_index(&mut self, field_index: Option<usize>) {
+        self.field_index = field_index;
+    }
+}
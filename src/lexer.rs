@@ -7,43 +7,78 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
     Dot,
     Minus,
     Plus,
     Semicolon,
     Star,
     Slash,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
     // One or two characters tokens
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    /// Separates parameters and body of the arrow function
+    EqualGreater,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    GreaterGreater,
+    StarStar,
+    Tilde,
+    /// Integer division ("//" is already used by comments)
+    TildeSlash,
     // Literals
     Identifier,
     String,
+    /// String without escape sequences and interpolation, written between backticks
+    RawString,
+    /// Part of the interpolated string before "${" (the rest of the string is scanned after the interpolated expression)
+    Interpolation,
     Number,
     // Keywords
     And,
+    Break,
+    Case,
+    Catch,
     Class,
+    Const,
+    Continue,
+    Default,
     Else,
+    Export,
     False,
+    Finally,
     For,
     Fun,
     If,
+    Import,
     Nil,
     Or,
     Print,
+    Resume,
     Return,
+    Static,
     Super,
+    Switch,
     This,
+    Throw,
+    Trait,
     True,
+    Try,
     Var,
     While,
+    Yield,
     Eof,
 }
 
@@ -53,8 +88,11 @@ pub struct Token {
     pub start: usize,
     pub length: usize,
     pub line: usize,
+    /// Column (counted from 1) on which the token starts
+    pub column: usize,
 }
 
+#[derive(Clone)]
 pub struct Lexer<'a> {
     /// Index of beginning of currently processed lexeme
     start: usize,
@@ -62,10 +100,17 @@ pub struct Lexer<'a> {
     current: usize,
     /// Currently processed line
     line: usize,
+    /// Index of the first character in currently processed line
+    line_start: usize,
+    /// Column of the beginning of currently processed lexeme
+    start_column: usize,
     /// Source code characters
     source: Peekable<Chars<'a>>,
     /// Original source code string
     original_source: &'a str,
+    /// Number of currently open braces for every string interpolation being scanned (innermost last),
+    /// brace closing the interpolation is the one that is found when there are no open braces
+    interpolation_braces: Vec<usize>,
 }
 
 /// Represents error occured while scanning with provided error message and line on which it occured
@@ -81,15 +126,19 @@ impl<'a> Lexer<'a> {
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_column: 1,
             source: source.chars().peekable(),
             original_source: source,
+            interpolation_braces: vec![],
         }
     }
 
-    pub fn scan_token(&mut self) -> Result<Token, LexerError> {
+    pub fn scan_token(&mut self) -> Result<Token, LexerError<'_>> {
         self.skip_whitespaces();
 
         self.start = self.current;
+        self.start_column = self.start - self.line_start + 1;
 
         if self.is_at_end() {
             return Ok(self.make_token(TokenType::Eof));
@@ -97,7 +146,7 @@ impl<'a> Lexer<'a> {
 
         let c = self.advance();
 
-        if c.is_alphabetic() {
+        if c.is_alphabetic() || c == '_' {
             return Ok(self.make_identifier_token(c));
         }
 
@@ -108,14 +157,50 @@ impl<'a> Lexer<'a> {
         match c {
             '(' => return Ok(self.make_token(TokenType::LeftParen)),
             ')' => return Ok(self.make_token(TokenType::RightParen)),
-            '{' => return Ok(self.make_token(TokenType::LeftBrace)),
-            '}' => return Ok(self.make_token(TokenType::RightBrace)),
+            '{' => {
+                if let Some(open_braces) = self.interpolation_braces.last_mut() {
+                    *open_braces += 1;
+                }
+                return Ok(self.make_token(TokenType::LeftBrace));
+            }
+            '}' => {
+                match self.interpolation_braces.last_mut() {
+                    Some(0) => {
+                        // End of the interpolated expression, we go back to scanning the string
+                        self.interpolation_braces.pop();
+                        return self.make_string_token();
+                    }
+                    Some(open_braces) => *open_braces -= 1,
+                    None => {}
+                }
+                return Ok(self.make_token(TokenType::RightBrace));
+            }
+            '[' => return Ok(self.make_token(TokenType::LeftBracket)),
+            ']' => return Ok(self.make_token(TokenType::RightBracket)),
             ';' => return Ok(self.make_token(TokenType::Semicolon)),
             ',' => return Ok(self.make_token(TokenType::Comma)),
+            ':' => return Ok(self.make_token(TokenType::Colon)),
             '.' => return Ok(self.make_token(TokenType::Dot)),
             '-' => return Ok(self.make_token(TokenType::Minus)),
             '+' => return Ok(self.make_token(TokenType::Plus)),
-            '*' => return Ok(self.make_token(TokenType::Star)),
+            '*' => {
+                return Ok(self.handle_one_or_two_character_token(
+                    '*',
+                    TokenType::StarStar,
+                    TokenType::Star,
+                ))
+            }
+            '%' => return Ok(self.make_token(TokenType::Percent)),
+            '&' => return Ok(self.make_token(TokenType::Ampersand)),
+            '|' => return Ok(self.make_token(TokenType::Pipe)),
+            '^' => return Ok(self.make_token(TokenType::Caret)),
+            '~' => {
+                return Ok(self.handle_one_or_two_character_token(
+                    '/',
+                    TokenType::TildeSlash,
+                    TokenType::Tilde,
+                ))
+            }
             '/' => {
                 if self.peek() == '/' {
                     self.skip_comment();
@@ -131,27 +216,37 @@ impl<'a> Lexer<'a> {
                 ))
             }
             '=' => {
+                if self.match_current('>') {
+                    return Ok(self.make_token(TokenType::EqualGreater));
+                }
                 return Ok(self.handle_one_or_two_character_token(
                     '=',
                     TokenType::EqualEqual,
                     TokenType::Equal,
-                ))
+                ));
             }
             '<' => {
+                if self.match_current('<') {
+                    return Ok(self.make_token(TokenType::LessLess));
+                }
                 return Ok(self.handle_one_or_two_character_token(
                     '=',
                     TokenType::LessEqual,
                     TokenType::Less,
-                ))
+                ));
             }
             '>' => {
+                if self.match_current('>') {
+                    return Ok(self.make_token(TokenType::GreaterGreater));
+                }
                 return Ok(self.handle_one_or_two_character_token(
                     '=',
                     TokenType::GreaterEqual,
                     TokenType::Greater,
-                ))
+                ));
             }
             '"' => return self.make_string_token(),
+            '`' => return self.make_raw_string_token(),
             _ => {}
         }
 
@@ -163,10 +258,13 @@ impl<'a> Lexer<'a> {
     }
 
     fn advance(&mut self) -> char {
-        self.current += 1;
-        self.source
+        let c = self
+            .source
             .next()
-            .expect("This should never go outside of bounds as we check 'is_at_end'.")
+            .expect("This should never go outside of bounds as we check 'is_at_end'.");
+        // Indexes are in bytes, so that they can be used to slice the source
+        self.current += c.len_utf8();
+        c
     }
 
     fn match_current(&mut self, expected: char) -> bool {
@@ -202,6 +300,7 @@ impl<'a> Lexer<'a> {
             start: self.start,
             length: self.current - self.start,
             line: self.line,
+            column: self.start_column,
         }
     }
 
@@ -222,6 +321,7 @@ impl<'a> Lexer<'a> {
         while c.is_whitespace() {
             if c == '\n' {
                 self.line += 1;
+                self.line_start = self.current + 1;
             }
             self.advance();
             c = self.peek();
@@ -235,12 +335,33 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    fn make_string_token(&mut self) -> Result<Token, LexerError> {
+    // String is scanned until the closing '"' or until the beginning of interpolation ("${"), in which case the rest
+    // of the string is scanned after the interpolated expression
+    fn make_string_token(&mut self) -> Result<Token, LexerError<'_>> {
+        // Invalid escape sequence is reported only after the whole string is scanned, so that the rest of
+        // the string isn't treated as code
+        let mut escape_error: Option<&'static str> = None;
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+            match self.advance() {
+                '\n' => {
+                    self.line += 1;
+                    self.line_start = self.current;
+                }
+                '\\' => {
+                    if let Err(message) = self.skip_escape_sequence() {
+                        escape_error = escape_error.or(Some(message));
+                    }
+                }
+                '$' if self.peek() == '{' => {
+                    self.advance();
+                    self.interpolation_braces.push(0);
+                    if let Some(message) = escape_error {
+                        return Err(self.create_error(message));
+                    }
+                    return Ok(self.make_token(TokenType::Interpolation));
+                }
+                _ => {}
             }
-            self.advance();
         }
 
         if self.is_at_end() {
@@ -249,10 +370,64 @@ impl<'a> Lexer<'a> {
 
         // consume closing '"'
         self.advance();
+        if let Some(message) = escape_error {
+            return Err(self.create_error(message));
+        }
         Ok(self.make_token(TokenType::String))
     }
 
-    fn make_number_token(&mut self) -> Result<Token, LexerError> {
+    // Escape sequence is only validated here, its value is decoded by `unescape_string`
+    fn skip_escape_sequence(&mut self) -> Result<(), &'static str> {
+        if self.is_at_end() {
+            return Ok(());
+        }
+        match self.advance() {
+            'n' | 't' | 'r' | '0' | '\\' | '"' | '$' => Ok(()),
+            'u' => {
+                if !self.match_current('{') {
+                    return Err("Expect '{' after '\\u'.");
+                }
+                let mut code_point = String::new();
+                while self.peek().is_ascii_hexdigit() && code_point.len() < 6 {
+                    code_point.push(self.advance());
+                }
+                if code_point.is_empty() || !self.match_current('}') {
+                    return Err("Invalid unicode escape sequence.");
+                }
+                let code_point = u32::from_str_radix(&code_point, 16)
+                    .expect("Code point should only contain hex digits");
+                match char::from_u32(code_point) {
+                    Some(_) => Ok(()),
+                    None => Err("Invalid unicode code point."),
+                }
+            }
+            '\n' => {
+                self.line += 1;
+                self.line_start = self.current;
+                Err("Invalid escape sequence.")
+            }
+            _ => Err("Invalid escape sequence."),
+        }
+    }
+
+    fn make_raw_string_token(&mut self) -> Result<Token, LexerError<'_>> {
+        while self.peek() != '`' && !self.is_at_end() {
+            if self.advance() == '\n' {
+                self.line += 1;
+                self.line_start = self.current;
+            }
+        }
+
+        if self.is_at_end() {
+            return Err(self.create_error("Unterminated raw string"));
+        }
+
+        // consume closing '`'
+        self.advance();
+        Ok(self.make_token(TokenType::RawString))
+    }
+
+    fn make_number_token(&mut self) -> Result<Token, LexerError<'_>> {
         while self.peek().is_ascii_digit() {
             self.advance();
         }
@@ -270,9 +445,13 @@ impl<'a> Lexer<'a> {
         Ok(self.make_token(TokenType::Number))
     }
 
+    fn is_identifier_character(c: char) -> bool {
+        c.is_alphanumeric() || c == '_'
+    }
+
     fn make_identifier_token(&mut self, first_letter: char) -> Token {
         let mut second_letter: Option<char> = Option::None;
-        while self.peek().is_alphanumeric() {
+        while Self::is_identifier_character(self.peek()) {
             let c = self.advance();
             if second_letter.is_none() {
                 second_letter = Option::Some(c);
@@ -294,29 +473,63 @@ impl<'a> Lexer<'a> {
 
         match first_letter {
             'a' => self.check_for_keyword(1, "nd", TokenType::And),
-            'c' => self.check_for_keyword(1, "lass", TokenType::Class),
-            'e' => self.check_for_keyword(1, "lse", TokenType::Else),
+            'b' => self.check_for_keyword(1, "reak", TokenType::Break),
+            'c' => match second_letter {
+                Some('a') => self
+                    .check_for_keywords(2, &[("se", TokenType::Case), ("tch", TokenType::Catch)]),
+                Some('l') => self.check_for_keyword(2, "ass", TokenType::Class),
+                Some('o') => self.check_for_keywords(
+                    2,
+                    &[("nst", TokenType::Const), ("ntinue", TokenType::Continue)],
+                ),
+                Some(_) => TokenType::Identifier,
+                None => TokenType::Identifier,
+            },
+            'd' => self.check_for_keyword(1, "efault", TokenType::Default),
+            'e' => self
+                .check_for_keywords(1, &[("lse", TokenType::Else), ("xport", TokenType::Export)]),
             'f' => match second_letter {
                 Some('a') => self.check_for_keyword(2, "lse", TokenType::False),
+                Some('i') => self.check_for_keyword(2, "nally", TokenType::Finally),
                 Some('o') => self.check_for_keyword(2, "r", TokenType::For),
                 Some('u') => self.check_for_keyword(2, "n", TokenType::Fun),
                 Some(_) => TokenType::Identifier,
                 None => TokenType::Identifier,
             },
-            'i' => self.check_for_keyword(1, "f", TokenType::If),
+            'i' => {
+                self.check_for_keywords(1, &[("f", TokenType::If), ("mport", TokenType::Import)])
+            }
             'n' => self.check_for_keyword(1, "il", TokenType::Nil),
             'o' => self.check_for_keyword(1, "r", TokenType::Or),
             'p' => self.check_for_keyword(1, "rint", TokenType::Print),
-            'r' => self.check_for_keyword(1, "eturn", TokenType::Return),
-            's' => self.check_for_keyword(1, "uper", TokenType::Super),
+            'r' => self.check_for_keywords(
+                1,
+                &[("esume", TokenType::Resume), ("eturn", TokenType::Return)],
+            ),
+            's' => match second_letter {
+                Some('t') => self.check_for_keyword(2, "atic", TokenType::Static),
+                Some('u') => self.check_for_keyword(2, "per", TokenType::Super),
+                Some('w') => self.check_for_keyword(2, "itch", TokenType::Switch),
+                Some(_) => TokenType::Identifier,
+                None => TokenType::Identifier,
+            },
             't' => match second_letter {
-                Some('h') => self.check_for_keyword(2, "is", TokenType::This),
-                Some('r') => self.check_for_keyword(2, "ue", TokenType::True),
+                Some('h') => self
+                    .check_for_keywords(2, &[("is", TokenType::This), ("row", TokenType::Throw)]),
+                Some('r') => self.check_for_keywords(
+                    2,
+                    &[
+                        ("ait", TokenType::Trait),
+                        ("ue", TokenType::True),
+                        ("y", TokenType::Try),
+                    ],
+                ),
                 Some(_) => TokenType::Identifier,
                 None => TokenType::Identifier,
             },
             'v' => self.check_for_keyword(1, "ar", TokenType::Var),
             'w' => self.check_for_keyword(1, "hile", TokenType::While),
+            'y' => self.check_for_keyword(1, "ield", TokenType::Yield),
             _ => TokenType::Identifier,
         }
     }
@@ -328,18 +541,62 @@ impl<'a> Lexer<'a> {
             end_index = self.original_source.len();
         }
 
-        let actual = &self.original_source[start_index..end_index];
+        // Keyword can't contain multi-byte characters, so if slice doesn't end on character boundary it's an identifier
+        let Some(actual) = self.original_source.get(start_index..end_index) else {
+            return TokenType::Identifier;
+        };
 
-        let is_token_finished = end_index == self.original_source.len()
-            || !self.original_source[end_index..]
-                .chars()
-                .next()
-                .unwrap()
-                .is_alphabetic();
+        let is_token_finished = !self.original_source[end_index..]
+            .chars()
+            .next()
+            .is_some_and(Self::is_identifier_character);
 
         if actual == expected && is_token_finished {
             return token_type;
         }
         TokenType::Identifier
     }
+
+    /// Check for one of the keywords that start with the same letters
+    fn check_for_keywords(&self, skip: usize, candidates: &[(&str, TokenType)]) -> TokenType {
+        candidates
+            .iter()
+            .map(|(expected, token_type)| self.check_for_keyword(skip, expected, *token_type))
+            .find(|token_type| *token_type != TokenType::Identifier)
+            .unwrap_or(TokenType::Identifier)
+    }
+}
+
+/// Replace escape sequences in the string content with characters they represent.
+/// Escape sequences are expected to be already validated by the lexer.
+pub fn unescape_string(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('0') => result.push('\0'),
+            Some('u') => {
+                // Skip '{', take digits until '}'
+                chars.next();
+                let code_point: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let code_point = u32::from_str_radix(&code_point, 16)
+                    .expect("Lexer should only accept hex digits in unicode escape sequence");
+                result.push(
+                    char::from_u32(code_point)
+                        .expect("Lexer should only accept valid unicode code points"),
+                );
+            }
+            // '\\', '"' and '$' stand for themselves
+            Some(other) => result.push(other),
+            None => {}
+        }
+    }
+    result
 }
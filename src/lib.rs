@@ -1,8 +1,12 @@
 pub mod chunk;
 pub mod compiler;
 pub mod error;
+pub mod gc;
+pub mod globals;
+pub mod inline_cache;
 pub mod lexer;
 pub mod logger;
+pub mod module;
 pub mod native_functions;
 pub mod table;
 pub mod value;
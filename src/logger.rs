@@ -69,6 +69,33 @@ impl Logger {
                 OperationCode::Divide => {
                     return Ok(Self::simple_instruction("OP_DIVIDE", offset, code))
                 }
+                OperationCode::Modulo => {
+                    return Ok(Self::simple_instruction("OP_MODULO", offset, code))
+                }
+                OperationCode::Power => {
+                    return Ok(Self::simple_instruction("OP_POWER", offset, code))
+                }
+                OperationCode::IntegerDivide => {
+                    return Ok(Self::simple_instruction("OP_INTEGER_DIVIDE", offset, code))
+                }
+                OperationCode::BitwiseAnd => {
+                    return Ok(Self::simple_instruction("OP_BITWISE_AND", offset, code))
+                }
+                OperationCode::BitwiseOr => {
+                    return Ok(Self::simple_instruction("OP_BITWISE_OR", offset, code))
+                }
+                OperationCode::BitwiseXor => {
+                    return Ok(Self::simple_instruction("OP_BITWISE_XOR", offset, code))
+                }
+                OperationCode::BitwiseNot => {
+                    return Ok(Self::simple_instruction("OP_BITWISE_NOT", offset, code))
+                }
+                OperationCode::ShiftLeft => {
+                    return Ok(Self::simple_instruction("OP_SHIFT_LEFT", offset, code))
+                }
+                OperationCode::ShiftRight => {
+                    return Ok(Self::simple_instruction("OP_SHIFT_RIGHT", offset, code))
+                }
                 OperationCode::Nil => {
                     return Ok(Self::simple_instruction("OP_NIL", offset, code));
                 }
@@ -96,29 +123,18 @@ impl Logger {
                 OperationCode::PopStack => {
                     return Ok(Self::simple_instruction("OP_POP_STACK", offset, code));
                 }
-                OperationCode::DefineGlobal(global_index) => {
-                    return Ok(Self::constant_instruction(
+                OperationCode::DefineGlobal(global_slot) => {
+                    return Ok(Self::byte_instruction(
                         "OP_DEFINE_GLOBAL",
                         offset,
-                        global_index,
-                        chunk.read_constant(global_index),
+                        global_slot,
                     ))
                 }
-                OperationCode::GetGlobal(global_index) => {
-                    return Ok(Self::constant_instruction(
-                        "OP_GET_GLOBAL",
-                        offset,
-                        global_index,
-                        chunk.read_constant(global_index),
-                    ))
+                OperationCode::GetGlobal(global_slot) => {
+                    return Ok(Self::byte_instruction("OP_GET_GLOBAL", offset, global_slot))
                 }
-                OperationCode::SetGlobal(global_index) => {
-                    return Ok(Self::constant_instruction(
-                        "OP_SET_GLOBAL",
-                        offset,
-                        global_index,
-                        chunk.read_constant(global_index),
-                    ))
+                OperationCode::SetGlobal(global_slot) => {
+                    return Ok(Self::byte_instruction("OP_SET_GLOBAL", offset, global_slot))
                 }
                 OperationCode::GetLocal(local_index) => {
                     return Ok(Self::byte_instruction("OP_GET_LOCAL", offset, local_index))
@@ -154,7 +170,11 @@ impl Logger {
                     ))
                 }
                 OperationCode::Call(arguments_count) => {
-                    return Ok(Self::byte_instruction("OP_CALl", offset, arguments_count))
+                    return Ok(Self::byte_instruction(
+                        "OP_CALl",
+                        offset,
+                        u32::from(arguments_count),
+                    ))
                 }
                 OperationCode::Closure(function_index) => {
                     return Ok(Self::closure_instruction(offset, function_index, &chunk)?)
@@ -243,6 +263,95 @@ impl Logger {
                         &chunk.read_constant(method_name_index),
                     ))
                 }
+                OperationCode::BuildList(items_count) => {
+                    return Ok(Self::byte_instruction("OP_BUILD_LIST", offset, items_count))
+                }
+                OperationCode::GetIndex => {
+                    return Ok(Self::simple_instruction("OP_GET_INDEX", offset, code))
+                }
+                OperationCode::SetIndex => {
+                    return Ok(Self::simple_instruction("OP_SET_INDEX", offset, code))
+                }
+                OperationCode::Stringify => {
+                    return Ok(Self::simple_instruction("OP_STRINGIFY", offset, code))
+                }
+                OperationCode::BuildMap(entries_count) => {
+                    return Ok(Self::byte_instruction(
+                        "OP_BUILD_MAP",
+                        offset,
+                        entries_count,
+                    ))
+                }
+                OperationCode::PushHandler(bytes_to_skip) => {
+                    return Ok(Self::jump_instruction(
+                        "OP_PUSH_HANDLER",
+                        1,
+                        offset,
+                        bytes_to_skip,
+                    ))
+                }
+                OperationCode::PopHandler => {
+                    return Ok(Self::simple_instruction("OP_POP_HANDLER", offset, code))
+                }
+                OperationCode::Throw => {
+                    return Ok(Self::simple_instruction("OP_THROW", offset, code))
+                }
+                OperationCode::DefineConstantGlobal(global_slot) => {
+                    return Ok(Self::byte_instruction(
+                        "OP_DEFINE_CONSTANT_GLOBAL",
+                        offset,
+                        global_slot,
+                    ))
+                }
+                OperationCode::Import(path_index) => {
+                    return Ok(Self::constant_instruction(
+                        "OP_IMPORT",
+                        offset,
+                        path_index,
+                        chunk.read_constant(path_index),
+                    ))
+                }
+                OperationCode::StaticField(field_name_index) => {
+                    return Ok(Self::constant_instruction(
+                        "OP_STATIC_FIELD",
+                        offset,
+                        field_name_index,
+                        chunk.read_constant(field_name_index),
+                    ))
+                }
+                OperationCode::Getter(property_name_index) => {
+                    return Ok(Self::constant_instruction(
+                        "OP_GETTER",
+                        offset,
+                        property_name_index,
+                        chunk.read_constant(property_name_index),
+                    ))
+                }
+                OperationCode::Setter(property_name_index) => {
+                    return Ok(Self::constant_instruction(
+                        "OP_SETTER",
+                        offset,
+                        property_name_index,
+                        chunk.read_constant(property_name_index),
+                    ))
+                }
+                OperationCode::Trait(trait_name_index) => {
+                    return Ok(Self::constant_instruction(
+                        "OP_TRAIT",
+                        offset,
+                        trait_name_index,
+                        chunk.read_constant(trait_name_index),
+                    ))
+                }
+                OperationCode::Mixin => {
+                    return Ok(Self::simple_instruction("OP_MIXIN", offset, code))
+                }
+                OperationCode::Yield => {
+                    return Ok(Self::simple_instruction("OP_YIELD", offset, code))
+                }
+                OperationCode::Resume => {
+                    return Ok(Self::simple_instruction("OP_RESUME", offset, code))
+                }
             }
         }
         Ok(0)
@@ -259,6 +368,23 @@ impl Logger {
         }
     }
 
+    pub fn show_garbage_collection_summary(
+        bytes_before: usize,
+        bytes_after: usize,
+        next_collection: usize,
+    ) {
+        #[cfg(feature = "log_garbage_collection")]
+        {
+            println!(
+                "-- gc collected {} bytes (from {} to {}) next at {}",
+                bytes_before.saturating_sub(bytes_after),
+                bytes_before,
+                bytes_after,
+                next_collection
+            );
+        }
+    }
+
     #[cfg(feature = "log_trace_execution")]
     fn simple_instruction(name: &str, offset: usize, code: OperationCode) -> usize {
         println!("{}", name);
@@ -269,7 +395,7 @@ impl Logger {
     fn constant_instruction(
         name: &str,
         offset: usize,
-        constant_index: u8,
+        constant_index: u32,
         constant_value: Value,
     ) -> usize {
         print!("{:<16} {:>4} '", name, constant_index);
@@ -279,13 +405,13 @@ impl Logger {
     }
 
     #[cfg(feature = "log_trace_execution")]
-    fn byte_instruction(name: &str, offset: usize, index: u8) -> usize {
+    fn byte_instruction(name: &str, offset: usize, index: u32) -> usize {
         println!("{:<16} {:>4}", name, index);
         offset + OperationCode::get_instruction_bytes_length(&OperationCode::GetLocal(index))
     }
 
     #[cfg(feature = "log_trace_execution")]
-    fn jump_instruction(name: &str, direction: i16, offset: usize, bytes_to_skip: u16) -> usize {
+    fn jump_instruction(name: &str, direction: i64, offset: usize, bytes_to_skip: u32) -> usize {
         use crate::chunk;
 
         let len = chunk::JUMP_INSTRUCTION_ARGUMENT_LENGTH + 1;
@@ -294,7 +420,7 @@ impl Logger {
             "{:<16} {:>4} -> {}",
             name,
             offset,
-            (offset + len) as i16 + (direction * bytes_to_skip as i16)
+            (offset + len) as i64 + (direction * bytes_to_skip as i64)
         );
 
         offset + len
@@ -303,7 +429,7 @@ impl Logger {
     #[cfg(feature = "log_trace_execution")]
     fn closure_instruction(
         mut offset: usize,
-        index: u8,
+        index: u32,
         chunk: &Chunk,
     ) -> Result<usize, LoggerError> {
         let function = chunk.read_constant(index);
@@ -318,7 +444,7 @@ impl Logger {
                 .map_err(LoggerError::ConversionError)?;
 
             let to_print: &str;
-            let index: u8;
+            let index: u32;
 
             let should_increase = false;
 
@@ -341,7 +467,7 @@ impl Logger {
                 offset, to_print, index
             );
 
-            offset += 2;
+            offset += OperationCode::get_instruction_bytes_length(&upvalue_instruction);
         }
         Ok(offset)
     }
@@ -350,7 +476,7 @@ impl Logger {
     fn invoke_property_instruction(
         name: &str,
         offset: usize,
-        property_name_index: u8,
+        property_name_index: u32,
         arguments_count: u8,
         property_name: &Value,
     ) -> usize {
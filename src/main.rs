@@ -1,6 +1,7 @@
 use std::{
     env, fs,
     io::{self, BufRead, Write},
+    path::Path,
     process::exit,
 };
 
@@ -11,8 +12,21 @@ fn usage() {
     exit(error::INVALID_USAGE_ERROR_CODE);
 }
 
-fn start_repl() {
+/// Environment variable with additional directories (separated like in `PATH`) in which modules are looked for
+const MODULE_SEARCH_PATH_VARIABLE: &str = "RUSTYLOX_PATH";
+
+fn create_vm() -> vm::VirtualMachine {
     let mut vm = vm::VirtualMachine::new();
+    if let Some(search_paths) = env::var_os(MODULE_SEARCH_PATH_VARIABLE) {
+        for search_path in env::split_paths(&search_paths) {
+            vm.add_module_search_path(search_path);
+        }
+    }
+    vm
+}
+
+fn start_repl() {
+    let mut vm = create_vm();
 
     let stdin = io::stdin();
     loop {
@@ -32,7 +46,8 @@ fn start_repl() {
 }
 
 fn run_file(file_name: &str) {
-    let mut vm = vm::VirtualMachine::new();
+    let mut vm = create_vm();
+    vm.set_script_path(Path::new(file_name));
     match fs::read_to_string(file_name) {
         Ok(file_content) => {
             let result = vm.interpret(&file_content);
@@ -0,0 +1,90 @@
+use std::{
+    cell::RefCell,
+    fmt, iter,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    globals::GlobalSlots,
+    value::{StringObject, Value},
+};
+
+// GENERAL IDEA:
+// Every imported file is compiled as a separate module with its own global variables, so that names defined in
+// different files never clash. The main script (together with every REPL line) is the first module. Functions
+// remember the index of the module they were compiled in, so that call frames know which globals to use.
+// Modules are executed only once and cached by their canonical path - importing the same file again gives the same
+// module object. Module can't be imported while it's still being executed (import cycle), as the importer would
+// see only some of its exports.
+
+/// Index of the module holding the main script
+pub const MAIN_MODULE_INDEX: usize = 0;
+
+pub struct Module {
+    /// Path of the module file shown in diagnostics, `None` for the main script
+    pub name: Option<String>,
+    /// Directory against which relative imports of the module are resolved
+    pub directory: PathBuf,
+    /// Slots of global variables, shared by every program compiled as a part of the module
+    pub global_slots: GlobalSlots,
+    /// Values of global variables indexed by their slots, `None` means the variable isn't defined (yet)
+    pub globals: Vec<Option<Value>>,
+    /// Flags of global variables indexed by their slots, `true` means the variable was defined as constant
+    pub constant_globals: Vec<bool>,
+}
+
+impl Module {
+    pub fn new(name: Option<String>, directory: PathBuf) -> Self {
+        Module {
+            name,
+            directory,
+            global_slots: GlobalSlots::new(),
+            globals: vec![],
+            constant_globals: vec![],
+        }
+    }
+
+    /// Make room for every global variable that has a slot assigned
+    pub fn resize_globals(&mut self) {
+        let slots_count = self.global_slots.get_slots_count();
+        self.globals.resize(slots_count, None);
+        self.constant_globals.resize(slots_count, false);
+    }
+
+    pub fn define_global(&mut self, name: &Rc<RefCell<StringObject>>, value: Value) {
+        let slot = self.global_slots.resolve(name) as usize;
+        self.resize_globals();
+        self.globals[slot] = Some(value);
+    }
+
+    /// Value of the exported global variable, `None` if there is no such export or it isn't defined (yet)
+    pub fn get_export(&self, name: &Rc<RefCell<StringObject>>) -> Option<Value> {
+        let slot = self.global_slots.find(name)?;
+        if !self.global_slots.is_exported(slot) {
+            return None;
+        }
+        self.globals[slot as usize].clone()
+    }
+
+    /// Location prefix used in diagnostics, so that it's clear in which file the line is
+    pub fn format_line(&self, line: impl fmt::Display) -> String {
+        match &self.name {
+            Some(name) => format!("{} line {}", name, line),
+            None => format!("line {}", line),
+        }
+    }
+}
+
+/// Find the file of the imported module. Path is checked against the directory of the importing module first and
+/// then against every search path, absolute paths are used as they are.
+pub fn resolve_module_path(
+    path: &str,
+    directory: &Path,
+    search_paths: &[PathBuf],
+) -> Option<PathBuf> {
+    iter::once(directory)
+        .chain(search_paths.iter().map(PathBuf::as_path))
+        .map(|directory| directory.join(path))
+        .find(|candidate| candidate.is_file())
+}
@@ -1,18 +1,34 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, mem, rc::Rc};
 
 use crate::value::{StringObject, Value};
 
-type EntryKey = Rc<RefCell<StringObject>>;
+/// Key under which values are stored in the `Table`
+pub trait TableKey: Clone {
+    fn get_hash(&self) -> u32;
+    fn is_same_key(&self, other: &Self) -> bool;
+}
+
+impl TableKey for Rc<RefCell<StringObject>> {
+    fn get_hash(&self) -> u32 {
+        self.borrow().get_hash()
+    }
+
+    // Thanks to this we don't have to check if strings (possibly very long) are equal - we just
+    // check if underlying pointers point to the same place in memory
+    fn is_same_key(&self, other: &Self) -> bool {
+        StringObject::are_equal_rc(self, other)
+    }
+}
 
 #[derive(Clone)]
-enum TableEntry {
-    Value(Entry),
+enum TableEntry<K: TableKey> {
+    Value(Entry<K>),
     Tombstone,
     Empty,
 }
 
-impl TableEntry {
-    pub fn get_value(&mut self) -> TableEntry {
+impl<K: TableKey> TableEntry<K> {
+    pub fn get_value(&mut self) -> TableEntry<K> {
         // TODO: check how take in Option is implemented and try to do something similar
         match self {
             TableEntry::Value(v) => {
@@ -27,13 +43,13 @@ impl TableEntry {
 }
 
 #[derive(Clone)]
-struct Entry {
-    key: EntryKey,
+struct Entry<K: TableKey> {
+    key: K,
     value: Value,
 }
 
-impl Entry {
-    fn new(key: EntryKey, value: Value) -> Self {
+impl<K: TableKey> Entry<K> {
+    fn new(key: K, value: Value) -> Self {
         Self { key, value }
     }
 }
@@ -46,13 +62,15 @@ pub enum InsertResult {
 #[derive(Debug)]
 pub struct KeyNotFound {}
 
-pub struct Table {
+/// Hash table with string keys by default (used for interned strings, fields, methods etc.),
+/// but any `TableKey` can be used (e.g. `Value` in maps)
+pub struct Table<K: TableKey = Rc<RefCell<StringObject>>> {
     /// Number of entires and tombstones in the table
     entries_count: usize,
-    entries: Vec<TableEntry>,
+    entries: Vec<TableEntry<K>>,
 }
 
-impl Table {
+impl<K: TableKey> Table<K> {
     const INITIAL_TABLE_SIZE: usize = 8;
 
     pub fn new() -> Self {
@@ -64,9 +82,17 @@ impl Table {
 
     const TABLE_MAX_LOAD: f32 = 0.75;
 
-    pub fn insert(&mut self, key: EntryKey, value: Value) -> InsertResult {
-        if self.entries_count + 1 > (self.entries.len() as f32 * Self::TABLE_MAX_LOAD) as usize {
-            let new_capacity = self.entries.len() * 2;
+    pub fn insert(&mut self, key: K, value: Value) -> InsertResult {
+        if self.entries_count + 1 > Self::get_max_load(self.entries.len()) {
+            // Tombstones are dropped by the rehash, so the table only has to grow if most of its load are live
+            // entries. Otherwise (e.g. after many removals) it's rehashed with room for as many new entries as
+            // there are live ones, which also lets the table shrink.
+            let live_count = self.iter().count();
+            let new_capacity = if live_count * 2 >= self.entries_count {
+                self.entries.len() * 2
+            } else {
+                Self::get_capacity_for(live_count * 2)
+            };
             self.adjust_size(new_capacity);
         }
         let entry_index = Self::find_entry(&self.entries, &key);
@@ -84,7 +110,7 @@ impl Table {
         result
     }
 
-    pub fn insert_all_from(from: &Table, to: &mut Table) {
+    pub fn insert_all_from(from: &Table<K>, to: &mut Table<K>) {
         for entry in &from.entries {
             if let TableEntry::Value(entry) = entry {
                 to.insert(entry.key.clone(), entry.value.clone());
@@ -92,7 +118,7 @@ impl Table {
         }
     }
 
-    pub fn get(&self, key: &EntryKey) -> Result<&Value, KeyNotFound> {
+    pub fn get(&self, key: &K) -> Result<&Value, KeyNotFound> {
         if self.entries_count == 0 {
             return Err(KeyNotFound {});
         }
@@ -105,7 +131,39 @@ impl Table {
         }
     }
 
-    pub fn remove(&mut self, key: &EntryKey) -> Result<(), KeyNotFound> {
+    /// Index of the entry with provided key, it can be later used with `get_at` and `replace_at` to skip the lookup
+    pub fn find_index(&self, key: &K) -> Option<usize> {
+        if self.entries_count == 0 {
+            return None;
+        }
+        let entry_index = Self::find_entry(&self.entries, key);
+        match &self.entries[entry_index] {
+            TableEntry::Value(_) => Some(entry_index),
+            _ => None,
+        }
+    }
+
+    /// Get value stored at provided index, but only if it's stored under provided key
+    pub fn get_at(&self, index: usize, key: &K) -> Option<&Value> {
+        match self.entries.get(index) {
+            Some(TableEntry::Value(entry)) if entry.key.is_same_key(key) => Some(&entry.value),
+            _ => None,
+        }
+    }
+
+    /// Replace value stored at provided index, but only if it's stored under provided key.
+    /// Returns false if nothing was replaced.
+    pub fn replace_at(&mut self, index: usize, key: &K, value: Value) -> bool {
+        match self.entries.get_mut(index) {
+            Some(TableEntry::Value(entry)) if entry.key.is_same_key(key) => {
+                entry.value = value;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Result<(), KeyNotFound> {
         if self.entries_count == 0 {
             return Err(KeyNotFound {});
         }
@@ -120,21 +178,45 @@ impl Table {
         }
     }
 
+    /// Iterate over all key-value pairs stored in the table (in no particular order)
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &Value)> {
+        self.entries.iter().filter_map(|entry| match entry {
+            TableEntry::Value(entry) => Some((&entry.key, &entry.value)),
+            _ => None,
+        })
+    }
+
+    fn get_max_load(capacity: usize) -> usize {
+        (capacity as f32 * Self::TABLE_MAX_LOAD) as usize
+    }
+
+    /// The smallest capacity (power of 2) that can hold provided number of entries without exceeding the max load
+    fn get_capacity_for(entries_count: usize) -> usize {
+        let mut capacity = Self::INITIAL_TABLE_SIZE;
+        while entries_count + 1 > Self::get_max_load(capacity) {
+            capacity *= 2;
+        }
+        capacity
+    }
+
+    /// Approximate number of bytes allocated for the table entries
+    pub fn get_allocated_bytes(&self) -> usize {
+        self.entries.capacity() * mem::size_of::<TableEntry<K>>()
+    }
+
     // We use entries instead of passing self so that we can use it on `adjust_size` for new entries array
-    fn find_entry(entries: &[TableEntry], key: &EntryKey) -> usize {
+    fn find_entry(entries: &[TableEntry<K>], key: &K) -> usize {
         // Using modulo is quite slow, but we know that:
         // - our size is always power of 2
         // - a % b, when b is 2^x, is the same as a & (b-1)
-        let mut index = key.borrow().get_hash() as usize & (entries.len() - 1);
+        let mut index = key.get_hash() as usize & (entries.len() - 1);
         let mut first_tombstone_index: Option<usize> = None;
         // Thanks to the load factor and the way we grow the array there will never be a case of infinite loop
         loop {
             let entry = &entries[index];
             match entry {
                 TableEntry::Value(entry) => {
-                    // Thanks to this we don't have to check if strings (possibly very long) are equal - we just
-                    // check if underlying pointers point to the same place in memory
-                    if StringObject::are_equal_rc(&entry.key, key) {
+                    if entry.key.is_same_key(key) {
                         return index;
                     }
                 }
@@ -152,6 +234,25 @@ impl Table {
         }
     }
 
+    fn adjust_size(&mut self, new_capacity: usize) {
+        let mut new_entries: Vec<TableEntry<K>> = vec![TableEntry::Empty; new_capacity];
+        let mut new_count: usize = 0;
+
+        for i in 0..self.entries.len() {
+            if let TableEntry::Value(entry) = &self.entries[i] {
+                // We know that here we are not adding new elemnts, so the count never changes here
+                let dest_index = Self::find_entry(&new_entries, &entry.key);
+                new_entries[dest_index] = self.entries[i].get_value();
+                new_count += 1;
+            }
+        }
+
+        self.entries = new_entries;
+        self.entries_count = new_count;
+    }
+}
+
+impl Table<Rc<RefCell<StringObject>>> {
     pub fn find_string(&self, new_string: &str) -> Option<Rc<RefCell<StringObject>>> {
         if self.entries_count == 0 {
             return None;
@@ -173,25 +274,29 @@ impl Table {
         }
     }
 
-    fn adjust_size(&mut self, new_capacity: usize) {
-        let mut new_entries: Vec<TableEntry> = vec![TableEntry::Empty; new_capacity];
-        let mut new_count: usize = 0;
-
-        for i in 0..self.entries.len() {
-            if let TableEntry::Value(entry) = &self.entries[i] {
-                // We know that here we are not adding new elemnts, so the count never changes here
-                let dest_index = Self::find_entry(&new_entries, &entry.key);
-                new_entries[dest_index] = self.entries[i].get_value();
-                new_count += 1;
+    /// Remove every entry whose key isn't referenced anywhere outside of the table
+    pub fn remove_unreferenced_keys(&mut self) {
+        let mut live_count = 0;
+        let mut is_any_removed = false;
+        for entry in self.entries.iter_mut() {
+            if let TableEntry::Value(value) = entry {
+                if Rc::strong_count(&value.key) == 1 {
+                    *entry = TableEntry::Tombstone;
+                    is_any_removed = true;
+                } else {
+                    live_count += 1;
+                }
             }
         }
-
-        self.entries = new_entries;
-        self.entries_count = new_count;
+        // Rehashing drops the tombstones (and resets `entries_count`), so that removed strings don't make
+        // the table grow
+        if is_any_removed {
+            self.adjust_size(Self::get_capacity_for(live_count));
+        }
     }
 }
 
-impl Default for Table {
+impl<K: TableKey> Default for Table<K> {
     fn default() -> Self {
         Self::new()
     }
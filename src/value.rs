@@ -1,7 +1,12 @@
 use core::fmt;
-use std::{cell::RefCell, cmp::Ordering, mem::ManuallyDrop, rc::Rc};
+use std::{cell::RefCell, cmp::Ordering, mem, rc::Rc};
 
-use crate::{chunk::Chunk, table::Table};
+use crate::{
+    chunk::Chunk,
+    gc::{GarbageCollector, Traceable},
+    table::{Table, TableKey},
+    vm::CallFrame,
+};
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum ValueType {
@@ -15,6 +20,10 @@ pub enum ValueType {
     ClassObject,
     InstanceObject,
     BoundMethodObject,
+    ListObject,
+    MapObject,
+    ModuleObject,
+    CoroutineObject,
 }
 
 #[derive(Clone)]
@@ -67,6 +76,11 @@ pub struct FunctionObject {
     pub upvalues_count: usize,
     pub chunk: Chunk,
     pub name: Rc<RefCell<StringObject>>,
+    /// Index of the module whose global variables are used by the function
+    pub module_index: usize,
+    /// Functions containing `yield` create coroutine when called, instead of executing their body right away
+    pub is_generator: bool,
+    is_marked: bool,
 }
 
 impl FunctionObject {
@@ -76,6 +90,9 @@ impl FunctionObject {
             upvalues_count: 0,
             chunk: Chunk::new(),
             name: StringObject::new_rc(name),
+            module_index: 0,
+            is_generator: false,
+            is_marked: false,
         }
     }
 
@@ -95,15 +112,27 @@ impl FunctionObject {
     }
 }
 
-impl From<Rc<RefCell<FunctionObject>>> for Value {
-    fn from(value: Rc<RefCell<FunctionObject>>) -> Self {
-        Value {
-            value_type: ValueType::FunctionObject,
-            actual_value: UnderlyingValue {
-                function_object: ManuallyDrop::new(value),
-            },
+impl Traceable for FunctionObject {
+    fn is_marked(&self) -> bool {
+        self.is_marked
+    }
+
+    fn set_marked(&mut self, is_marked: bool) {
+        self.is_marked = is_marked;
+    }
+
+    fn trace_references(&self, garbage_collector: &mut GarbageCollector) {
+        // Function is never a part of cycle, but it's tracked so that its constants count towards the heap size
+        for constant in self.chunk.iter_constants() {
+            garbage_collector.mark_value(constant);
         }
     }
+
+    fn clear_references(&mut self) {}
+
+    fn get_allocated_bytes(&self) -> usize {
+        self.chunk.get_allocated_bytes()
+    }
 }
 
 pub type NativeFunction = fn(&[Value]) -> Value;
@@ -111,6 +140,7 @@ pub type NativeFunction = fn(&[Value]) -> Value;
 pub struct ClosureObject {
     pub function: Rc<RefCell<FunctionObject>>,
     pub upvalues: Vec<Rc<RefCell<UpvalueObject>>>,
+    is_marked: bool,
 }
 
 impl ClosureObject {
@@ -118,6 +148,7 @@ impl ClosureObject {
         ClosureObject {
             function,
             upvalues: vec![],
+            is_marked: false,
         }
     }
 
@@ -137,15 +168,29 @@ impl ClosureObject {
     }
 }
 
-impl From<Rc<RefCell<ClosureObject>>> for Value {
-    fn from(value: Rc<RefCell<ClosureObject>>) -> Self {
-        Value {
-            value_type: ValueType::ClosureObject,
-            actual_value: UnderlyingValue {
-                closure_object: ManuallyDrop::new(value),
-            },
+impl Traceable for ClosureObject {
+    fn is_marked(&self) -> bool {
+        self.is_marked
+    }
+
+    fn set_marked(&mut self, is_marked: bool) {
+        self.is_marked = is_marked;
+    }
+
+    fn trace_references(&self, garbage_collector: &mut GarbageCollector) {
+        garbage_collector.mark_object(&self.function);
+        for upvalue in self.upvalues.iter() {
+            garbage_collector.mark_object(upvalue);
         }
     }
+
+    fn clear_references(&mut self) {
+        self.upvalues.clear();
+    }
+
+    fn get_allocated_bytes(&self) -> usize {
+        self.upvalues.capacity() * mem::size_of::<Rc<RefCell<UpvalueObject>>>()
+    }
 }
 
 // GENERAL IDEA:
@@ -158,6 +203,45 @@ pub struct UpvalueObject {
     pub stack_index: Option<usize>,
     /// None when variable is still on the stack
     pub variable: Option<Rc<RefCell<Value>>>,
+    is_marked: bool,
+}
+
+impl UpvalueObject {
+    pub fn new_rc(stack_index: usize) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(UpvalueObject {
+            stack_index: Some(stack_index),
+            variable: None,
+            is_marked: false,
+        }))
+    }
+}
+
+impl Traceable for UpvalueObject {
+    fn is_marked(&self) -> bool {
+        self.is_marked
+    }
+
+    fn set_marked(&mut self, is_marked: bool) {
+        self.is_marked = is_marked;
+    }
+
+    fn trace_references(&self, garbage_collector: &mut GarbageCollector) {
+        // Open upvalue points to the stack, which is already a root
+        if let Some(variable) = &self.variable {
+            garbage_collector.mark_value(&variable.borrow());
+        }
+    }
+
+    fn clear_references(&mut self) {
+        self.variable = None;
+    }
+
+    fn get_allocated_bytes(&self) -> usize {
+        match self.variable {
+            Some(_) => mem::size_of::<RefCell<Value>>(),
+            None => 0,
+        }
+    }
 }
 
 impl PartialEq for UpvalueObject {
@@ -202,15 +286,54 @@ pub struct ClassObject {
     name: Rc<RefCell<StringObject>>,
     // We must ensure that the only elements that are inserted here are closures
     pub methods: Table,
+    /// Changed every time methods are modified, so that inline caches can tell if cached method is still valid
+    methods_version: u32,
+    /// Closures called when the property is read (without arguments) or assigned (with the assigned value)
+    pub getters: Table,
+    pub setters: Table,
+    /// Class-level fields, static methods are stored here as well (as closures that don't take "this")
+    pub static_fields: Table,
+    /// Static fields which aren't found in this class are looked up in the base class
+    base_class: Option<Rc<RefCell<ClassObject>>>,
+    /// Traits are bundles of methods that can't be instantiated, only mixed into classes
+    pub is_trait: bool,
+    /// Traits mixed into this class, so that members brought by two different traits can be told apart from the others
+    mixed_traits: Vec<Rc<RefCell<ClassObject>>>,
+    is_marked: bool,
 }
 
 pub struct InvalidMethodType {}
 
+/// Member that is defined by two traits mixed into the same class, while the class doesn't define it itself
+pub struct TraitConflict {
+    pub first_trait: String,
+    pub second_trait: String,
+    /// Accessors are prefixed with "get " or "set ", as they don't conflict with the method with the same name
+    pub member: String,
+}
+
+type ClassMembers = fn(&ClassObject) -> &Table;
+
+/// Each kind of the class member, with the prefix used to describe it
+const CLASS_MEMBERS_KINDS: [(&str, ClassMembers); 3] = [
+    ("", |class| &class.methods),
+    ("get ", |class| &class.getters),
+    ("set ", |class| &class.setters),
+];
+
 impl ClassObject {
     fn new(name: &str) -> Self {
         ClassObject {
             name: StringObject::new_rc(name),
             methods: Table::new(),
+            methods_version: 0,
+            getters: Table::new(),
+            setters: Table::new(),
+            static_fields: Table::new(),
+            base_class: None,
+            is_trait: false,
+            mixed_traits: vec![],
+            is_marked: false,
         }
     }
 
@@ -235,13 +358,185 @@ impl ClassObject {
             return Err(InvalidMethodType {});
         }
         self.methods.insert(method_name, method);
+        self.methods_version = self.methods_version.wrapping_add(1);
         Ok(())
     }
+
+    pub fn add_getter(
+        &mut self,
+        property_name: Rc<RefCell<StringObject>>,
+        getter: Value,
+    ) -> Result<(), InvalidMethodType> {
+        if !getter.is_closure_object() {
+            return Err(InvalidMethodType {});
+        }
+        self.getters.insert(property_name, getter);
+        Ok(())
+    }
+
+    pub fn add_setter(
+        &mut self,
+        property_name: Rc<RefCell<StringObject>>,
+        setter: Value,
+    ) -> Result<(), InvalidMethodType> {
+        if !setter.is_closure_object() {
+            return Err(InvalidMethodType {});
+        }
+        self.setters.insert(property_name, setter);
+        Ok(())
+    }
+
+    /// Copy every method (and property accessor) of the base class into this class. Static fields aren't copied, but looked up
+    /// in the base class instead, so that assigning them in the base class is visible in derived ones
+    pub fn inherit(&mut self, base_class: &Rc<RefCell<ClassObject>>) {
+        self.mix_in(&base_class.borrow());
+        self.base_class = Some(base_class.clone());
+    }
+
+    /// Copy every method (and property accessor) of the other class into this class, replacing existing ones
+    pub fn mix_in(&mut self, other: &ClassObject) {
+        Table::insert_all_from(&other.methods, &mut self.methods);
+        Table::insert_all_from(&other.getters, &mut self.getters);
+        Table::insert_all_from(&other.setters, &mut self.setters);
+        self.methods_version = self.methods_version.wrapping_add(1);
+    }
+
+    /// Copy members of the trait into this class, which is done after the class body is defined. Inherited members are replaced,
+    /// members defined by the class itself are kept and member already mixed in from another trait is a conflict
+    pub fn mix_in_trait(
+        &mut self,
+        mixed_trait: &Rc<RefCell<ClassObject>>,
+    ) -> Result<(), TraitConflict> {
+        let other = mixed_trait.borrow();
+        let mut mixed_members = [vec![], vec![], vec![]];
+        for (kind_index, (prefix, members)) in CLASS_MEMBERS_KINDS.iter().enumerate() {
+            for (name, member) in members(&other).iter() {
+                let Ok(current) = members(self).get(name) else {
+                    mixed_members[kind_index].push((name.clone(), member.clone()));
+                    continue;
+                };
+                let is_current = |class: &ClassObject| {
+                    members(class)
+                        .get(name)
+                        .is_ok_and(|defined| Self::are_same_closures(defined, current))
+                };
+                let previous_trait = self
+                    .mixed_traits
+                    .iter()
+                    .find(|previous_trait| is_current(&previous_trait.borrow()));
+                if let Some(previous_trait) = previous_trait {
+                    if Rc::ptr_eq(previous_trait, mixed_trait)
+                        || Self::are_same_closures(member, current)
+                    {
+                        continue;
+                    }
+                    return Err(TraitConflict {
+                        first_trait: previous_trait.borrow().name.borrow().get_value().to_owned(),
+                        second_trait: other.name.borrow().get_value().to_owned(),
+                        member: format!("{}{}", prefix, name.borrow().get_value()),
+                    });
+                }
+                let is_inherited = self
+                    .base_class
+                    .as_ref()
+                    .is_some_and(|base_class| is_current(&base_class.borrow()));
+                if is_inherited {
+                    mixed_members[kind_index].push((name.clone(), member.clone()));
+                }
+            }
+        }
+
+        let [methods, getters, setters] = mixed_members;
+        for (name, method) in methods {
+            self.methods.insert(name, method);
+        }
+        for (name, getter) in getters {
+            self.getters.insert(name, getter);
+        }
+        for (name, setter) in setters {
+            self.setters.insert(name, setter);
+        }
+        self.mixed_traits.push(mixed_trait.clone());
+        self.methods_version = self.methods_version.wrapping_add(1);
+        Ok(())
+    }
+
+    fn are_same_closures(lhs: &Value, rhs: &Value) -> bool {
+        match (lhs.get_closure_object(), rhs.get_closure_object()) {
+            (Ok(lhs), Ok(rhs)) => ClosureObject::are_equal_rc(&lhs, &rhs),
+            _ => false,
+        }
+    }
+
+    /// Value of the static field defined in this class or any of its base classes
+    pub fn get_static_field(&self, name: &Rc<RefCell<StringObject>>) -> Option<Value> {
+        match self.static_fields.get(name) {
+            Ok(value) => Some(value.clone()),
+            Err(_) => self
+                .base_class
+                .as_ref()
+                .and_then(|base_class| base_class.borrow().get_static_field(name)),
+        }
+    }
+
+    pub fn get_methods_version(&self) -> u32 {
+        self.methods_version
+    }
+}
+
+impl Traceable for ClassObject {
+    fn is_marked(&self) -> bool {
+        self.is_marked
+    }
+
+    fn set_marked(&mut self, is_marked: bool) {
+        self.is_marked = is_marked;
+    }
+
+    fn trace_references(&self, garbage_collector: &mut GarbageCollector) {
+        for (_, method) in self.methods.iter() {
+            garbage_collector.mark_value(method);
+        }
+        for (_, getter) in self.getters.iter() {
+            garbage_collector.mark_value(getter);
+        }
+        for (_, setter) in self.setters.iter() {
+            garbage_collector.mark_value(setter);
+        }
+        for (_, field) in self.static_fields.iter() {
+            garbage_collector.mark_value(field);
+        }
+        if let Some(base_class) = &self.base_class {
+            garbage_collector.mark_object(base_class);
+        }
+        for mixed_trait in &self.mixed_traits {
+            garbage_collector.mark_object(mixed_trait);
+        }
+    }
+
+    fn clear_references(&mut self) {
+        self.methods = Table::new();
+        self.methods_version = self.methods_version.wrapping_add(1);
+        self.getters = Table::new();
+        self.setters = Table::new();
+        self.static_fields = Table::new();
+        self.base_class = None;
+        self.mixed_traits = vec![];
+    }
+
+    fn get_allocated_bytes(&self) -> usize {
+        self.methods.get_allocated_bytes()
+            + self.getters.get_allocated_bytes()
+            + self.setters.get_allocated_bytes()
+            + self.static_fields.get_allocated_bytes()
+            + self.mixed_traits.capacity() * mem::size_of::<Rc<RefCell<ClassObject>>>()
+    }
 }
 
 pub struct InstanceObject {
     pub fields: Table,
     pub class: Rc<RefCell<ClassObject>>,
+    is_marked: bool,
 }
 
 impl InstanceObject {
@@ -249,6 +544,7 @@ impl InstanceObject {
         InstanceObject {
             class: class.clone(),
             fields: Table::new(),
+            is_marked: false,
         }
     }
 
@@ -268,14 +564,45 @@ impl InstanceObject {
     }
 }
 
+impl Traceable for InstanceObject {
+    fn is_marked(&self) -> bool {
+        self.is_marked
+    }
+
+    fn set_marked(&mut self, is_marked: bool) {
+        self.is_marked = is_marked;
+    }
+
+    fn trace_references(&self, garbage_collector: &mut GarbageCollector) {
+        garbage_collector.mark_object(&self.class);
+        for (_, field) in self.fields.iter() {
+            garbage_collector.mark_value(field);
+        }
+    }
+
+    fn clear_references(&mut self) {
+        // Class doesn't have to be cleared - every cycle going through it also goes through `methods` table
+        self.fields = Table::new();
+    }
+
+    fn get_allocated_bytes(&self) -> usize {
+        self.fields.get_allocated_bytes()
+    }
+}
+
 pub struct BoundMethodObject {
     instance: Rc<RefCell<InstanceObject>>,
     pub method: Rc<RefCell<ClosureObject>>,
+    is_marked: bool,
 }
 
 impl BoundMethodObject {
     fn new(instance: Rc<RefCell<InstanceObject>>, method: Rc<RefCell<ClosureObject>>) -> Self {
-        BoundMethodObject { instance, method }
+        BoundMethodObject {
+            instance,
+            method,
+            is_marked: false,
+        }
     }
 
     fn transform_to_rc(self) -> Rc<RefCell<Self>> {
@@ -301,254 +628,420 @@ impl BoundMethodObject {
     }
 }
 
-impl From<Rc<RefCell<InstanceObject>>> for Value {
-    fn from(value: Rc<RefCell<InstanceObject>>) -> Self {
-        Value {
-            value_type: ValueType::InstanceObject,
-            actual_value: UnderlyingValue {
-                instance_object: ManuallyDrop::new(value),
-            },
-        }
+impl Traceable for BoundMethodObject {
+    fn is_marked(&self) -> bool {
+        self.is_marked
+    }
+
+    fn set_marked(&mut self, is_marked: bool) {
+        self.is_marked = is_marked;
+    }
+
+    fn trace_references(&self, garbage_collector: &mut GarbageCollector) {
+        garbage_collector.mark_object(&self.instance);
+        garbage_collector.mark_object(&self.method);
+    }
+
+    fn clear_references(&mut self) {
+        // Bound method is immutable, so it can only be a part of cycle that goes through some other (clearable) object
+    }
+
+    fn get_allocated_bytes(&self) -> usize {
+        0
     }
 }
 
-#[repr(C)]
-union UnderlyingValue {
-    boolean: bool,
-    number: f64,
-    string_object: ManuallyDrop<Rc<RefCell<StringObject>>>,
-    function_object: ManuallyDrop<Rc<RefCell<FunctionObject>>>,
-    native_function: NativeFunction,
-    closure_object: ManuallyDrop<Rc<RefCell<ClosureObject>>>,
-    class_object: ManuallyDrop<Rc<RefCell<ClassObject>>>,
-    instance_object: ManuallyDrop<Rc<RefCell<InstanceObject>>>,
-    bound_method_object: ManuallyDrop<Rc<RefCell<BoundMethodObject>>>,
+pub struct ListObject {
+    pub items: Vec<Value>,
+    is_marked: bool,
 }
 
-pub struct Value {
-    value_type: ValueType,
-    actual_value: UnderlyingValue,
+impl ListObject {
+    fn new(items: Vec<Value>) -> Self {
+        ListObject {
+            items,
+            is_marked: false,
+        }
+    }
+
+    fn transform_to_rc(self) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(self))
+    }
+
+    pub fn new_rc(items: Vec<Value>) -> Rc<RefCell<Self>> {
+        Self::new(items).transform_to_rc()
+    }
+
+    pub fn are_equal_rc(lhs: &Rc<RefCell<ListObject>>, rhs: &Rc<RefCell<ListObject>>) -> bool {
+        Rc::ptr_eq(lhs, rhs)
+    }
 }
 
-#[derive(Debug)]
-pub struct ValueInterpretingError {}
+impl Traceable for ListObject {
+    fn is_marked(&self) -> bool {
+        self.is_marked
+    }
 
-impl Value {
-    pub fn new_bool(value: bool) -> Value {
-        Value {
-            value_type: ValueType::Bool,
-            actual_value: UnderlyingValue { boolean: value },
+    fn set_marked(&mut self, is_marked: bool) {
+        self.is_marked = is_marked;
+    }
+
+    fn trace_references(&self, garbage_collector: &mut GarbageCollector) {
+        for item in self.items.iter() {
+            garbage_collector.mark_value(item);
         }
     }
 
-    pub fn is_bool(&self) -> bool {
-        self.value_type == ValueType::Bool
+    fn clear_references(&mut self) {
+        self.items.clear();
     }
 
-    pub fn get_bool(&self) -> Result<bool, ValueInterpretingError> {
-        match self.value_type {
-            ValueType::Bool => unsafe { Ok(self.actual_value.boolean) },
-            _ => Err(ValueInterpretingError {}),
-        }
+    fn get_allocated_bytes(&self) -> usize {
+        self.items.capacity() * mem::size_of::<Value>()
     }
+}
 
-    pub fn new_number(value: f64) -> Value {
-        Value {
-            value_type: ValueType::Number,
-            actual_value: UnderlyingValue { number: value },
+pub struct MapObject {
+    pub entries: Table<Value>,
+    is_marked: bool,
+}
+
+impl MapObject {
+    fn new(entries: Table<Value>) -> Self {
+        MapObject {
+            entries,
+            is_marked: false,
         }
     }
 
-    pub fn is_number(&self) -> bool {
-        self.value_type == ValueType::Number
+    fn transform_to_rc(self) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(self))
     }
 
-    pub fn get_number(&self) -> Result<f64, ValueInterpretingError> {
-        match self.value_type {
-            ValueType::Number => unsafe { Ok(self.actual_value.number) },
-            _ => Err(ValueInterpretingError {}),
-        }
+    pub fn new_rc(entries: Table<Value>) -> Rc<RefCell<Self>> {
+        Self::new(entries).transform_to_rc()
     }
 
-    pub fn new_nil() -> Value {
-        Value {
-            value_type: ValueType::Nil,
-            actual_value: UnderlyingValue { number: 0.0 },
-        }
+    pub fn are_equal_rc(lhs: &Rc<RefCell<MapObject>>, rhs: &Rc<RefCell<MapObject>>) -> bool {
+        Rc::ptr_eq(lhs, rhs)
     }
+}
 
-    pub fn is_nil(&self) -> bool {
-        self.value_type == ValueType::Nil
+impl Traceable for MapObject {
+    fn is_marked(&self) -> bool {
+        self.is_marked
     }
 
-    pub fn new_string_object(value: &str, intern_strings: &mut Table) -> Value {
-        if let Some(already_existing) = intern_strings.find_string(value) {
-            return Value {
-                actual_value: UnderlyingValue {
-                    string_object: ManuallyDrop::new(already_existing.clone()),
-                },
-                value_type: ValueType::StringObject,
-            };
-        }
-        let key = StringObject::new_rc(value);
-        let value_key = key.clone();
-        intern_strings.insert(key, Value::new_nil());
-        Value {
-            value_type: ValueType::StringObject,
-            actual_value: UnderlyingValue {
-                string_object: ManuallyDrop::new(value_key),
-            },
+    fn set_marked(&mut self, is_marked: bool) {
+        self.is_marked = is_marked;
+    }
+
+    // Keys can only be primitives or strings, so only values can reference other objects
+    fn trace_references(&self, garbage_collector: &mut GarbageCollector) {
+        for (_, value) in self.entries.iter() {
+            garbage_collector.mark_value(value);
         }
     }
 
-    pub fn is_string_object(&self) -> bool {
-        self.value_type == ValueType::StringObject
+    fn clear_references(&mut self) {
+        self.entries = Table::new();
     }
 
-    pub fn get_string_object(&self) -> Result<&Rc<RefCell<StringObject>>, ValueInterpretingError> {
-        match self.value_type {
-            ValueType::StringObject => unsafe { Ok(&self.actual_value.string_object) },
-            _ => Err(ValueInterpretingError {}),
-        }
+    fn get_allocated_bytes(&self) -> usize {
+        self.entries.get_allocated_bytes()
     }
+}
 
-    pub fn new_function_object(name: &str) -> Value {
-        Value {
-            value_type: ValueType::FunctionObject,
-            actual_value: UnderlyingValue {
-                function_object: ManuallyDrop::new(FunctionObject::new_rc(name)),
-            },
+/// Imported module, its exports are read from the module global variables (kept by the virtual machine)
+pub struct ModuleObject {
+    pub name: Rc<RefCell<StringObject>>,
+    pub module_index: usize,
+}
+
+impl ModuleObject {
+    fn new(name: &str, module_index: usize) -> Self {
+        ModuleObject {
+            name: StringObject::new_rc(name),
+            module_index,
         }
     }
 
-    pub fn is_function_object(&self) -> bool {
-        self.value_type == ValueType::FunctionObject
+    fn transform_to_rc(self) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(self))
     }
 
-    pub fn get_function_object(
-        &self,
-    ) -> Result<&Rc<RefCell<FunctionObject>>, ValueInterpretingError> {
-        match self.value_type {
-            ValueType::FunctionObject => unsafe { Ok(&self.actual_value.function_object) },
-            _ => Err(ValueInterpretingError {}),
-        }
+    pub fn new_rc(name: &str, module_index: usize) -> Rc<RefCell<Self>> {
+        Self::new(name, module_index).transform_to_rc()
     }
 
-    pub fn new_native_function(function: NativeFunction) -> Value {
-        Value {
-            value_type: ValueType::NativeFunction,
-            actual_value: UnderlyingValue {
-                native_function: function,
-            },
+    pub fn are_equal_rc(lhs: &Rc<RefCell<ModuleObject>>, rhs: &Rc<RefCell<ModuleObject>>) -> bool {
+        Rc::ptr_eq(lhs, rhs)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CoroutineState {
+    /// Created or stopped at `yield`, waiting to be resumed
+    Suspended,
+    /// Resumed and not yet stopped (coroutines that resumed other coroutines are running as well)
+    Running,
+    /// Generator function returned or threw, coroutine can't be resumed anymore
+    Finished,
+}
+
+/// Call of the generator function that is executed step by step - every `resume` continues it until the next `yield`
+pub struct CoroutineObject {
+    /// Closure of the called generator function
+    pub closure: Rc<RefCell<ClosureObject>>,
+    pub state: CoroutineState,
+    /// Stack segment of the suspended coroutine, starting with the called value and its arguments.
+    /// While the coroutine is running, its values are on top of the virtual machine stack instead.
+    pub stack: Vec<Value>,
+    /// Frame of the suspended generator function, its stack indices are relative to the start of the segment
+    pub(crate) frame: Option<CallFrame>,
+    /// Upvalues of variables from the stack segment, they are closed while the coroutine is suspended
+    /// (so that closures can still use them) and reopened when it's resumed, indices are relative to the segment
+    pub upvalues: Vec<(usize, Rc<RefCell<UpvalueObject>>)>,
+    is_marked: bool,
+}
+
+impl CoroutineObject {
+    fn new(closure: Rc<RefCell<ClosureObject>>, stack: Vec<Value>, frame: CallFrame) -> Self {
+        CoroutineObject {
+            closure,
+            state: CoroutineState::Suspended,
+            stack,
+            frame: Some(frame),
+            upvalues: vec![],
+            is_marked: false,
         }
     }
 
-    pub fn is_native_function(&self) -> bool {
-        self.value_type == ValueType::NativeFunction
+    fn transform_to_rc(self) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(self))
     }
 
-    pub fn get_native_function(&self) -> Result<NativeFunction, ValueInterpretingError> {
-        match self.value_type {
-            ValueType::NativeFunction => unsafe { Ok(self.actual_value.native_function) },
-            _ => Err(ValueInterpretingError {}),
-        }
+    pub(crate) fn new_rc(
+        closure: Rc<RefCell<ClosureObject>>,
+        stack: Vec<Value>,
+        frame: CallFrame,
+    ) -> Rc<RefCell<Self>> {
+        Self::new(closure, stack, frame).transform_to_rc()
     }
 
-    pub fn new_closure_object(function: Rc<RefCell<FunctionObject>>) -> Value {
-        Value {
-            value_type: ValueType::ClosureObject,
-            actual_value: UnderlyingValue {
-                closure_object: ManuallyDrop::new(ClosureObject::new_rc(function)),
-            },
-        }
+    pub fn are_equal_rc(
+        lhs: &Rc<RefCell<CoroutineObject>>,
+        rhs: &Rc<RefCell<CoroutineObject>>,
+    ) -> bool {
+        Rc::ptr_eq(lhs, rhs)
     }
+}
 
-    pub fn is_closure_object(&self) -> bool {
-        self.value_type == ValueType::ClosureObject
+impl Traceable for CoroutineObject {
+    fn is_marked(&self) -> bool {
+        self.is_marked
     }
 
-    pub fn get_closure_object(
-        &self,
-    ) -> Result<&Rc<RefCell<ClosureObject>>, ValueInterpretingError> {
-        match self.value_type {
-            ValueType::ClosureObject => unsafe { Ok(&self.actual_value.closure_object) },
-            _ => Err(ValueInterpretingError {}),
-        }
+    fn set_marked(&mut self, is_marked: bool) {
+        self.is_marked = is_marked;
     }
 
-    pub fn new_class_object(name: &str) -> Value {
-        Value {
-            value_type: ValueType::ClassObject,
-            actual_value: UnderlyingValue {
-                class_object: ManuallyDrop::new(ClassObject::new_rc(name)),
-            },
+    // Suspended frame only references the closure, which is marked anyway
+    fn trace_references(&self, garbage_collector: &mut GarbageCollector) {
+        garbage_collector.mark_object(&self.closure);
+        for value in self.stack.iter() {
+            garbage_collector.mark_value(value);
+        }
+        for (_, upvalue) in self.upvalues.iter() {
+            garbage_collector.mark_object(upvalue);
         }
     }
 
-    pub fn is_class_object(&self) -> bool {
-        self.value_type == ValueType::ClassObject
+    fn clear_references(&mut self) {
+        self.stack.clear();
+        self.frame = None;
+        self.upvalues.clear();
     }
 
-    pub fn get_class_object(&self) -> Result<&Rc<RefCell<ClassObject>>, ValueInterpretingError> {
-        match self.value_type {
-            ValueType::ClassObject => unsafe { Ok(&self.actual_value.class_object) },
-            _ => Err(ValueInterpretingError {}),
-        }
+    fn get_allocated_bytes(&self) -> usize {
+        self.stack.capacity() * mem::size_of::<Value>()
+            + self.upvalues.capacity() * mem::size_of::<(usize, Rc<RefCell<UpvalueObject>>)>()
     }
+}
 
-    pub fn new_instance_object(class: &Rc<RefCell<ClassObject>>) -> Value {
-        Value {
-            value_type: ValueType::InstanceObject,
-            actual_value: UnderlyingValue {
-                instance_object: ManuallyDrop::new(InstanceObject::new_rc(class)),
-            },
-        }
+#[derive(Debug)]
+pub struct ValueInterpretingError {}
+
+// Underlying representation of `Value` can be selected with "nan_boxing" feature, but both of them expose the same API
+#[cfg(not(feature = "nan_boxing"))]
+mod tagged_union;
+#[cfg(not(feature = "nan_boxing"))]
+pub use tagged_union::{ObjectReference, Value};
+
+#[cfg(feature = "nan_boxing")]
+mod nan_boxing;
+#[cfg(feature = "nan_boxing")]
+pub use nan_boxing::{ObjectReference, Value};
+
+impl Value {
+    pub fn is_bool(&self) -> bool {
+        self.get_type() == ValueType::Bool
     }
 
-    pub fn is_instance_object(&self) -> bool {
-        self.value_type == ValueType::InstanceObject
+    pub fn is_number(&self) -> bool {
+        self.get_type() == ValueType::Number
+    }
+
+    pub fn is_nil(&self) -> bool {
+        self.get_type() == ValueType::Nil
     }
 
-    pub fn get_instance_object(
-        &self,
-    ) -> Result<&Rc<RefCell<InstanceObject>>, ValueInterpretingError> {
-        match self.value_type {
-            ValueType::InstanceObject => unsafe { Ok(&self.actual_value.instance_object) },
-            _ => Err(ValueInterpretingError {}),
+    /// Intern the string, only string that wasn't interned before counts towards the heap size
+    pub fn new_string_object(
+        value: &str,
+        intern_strings: &mut Table,
+        garbage_collector: &mut GarbageCollector,
+    ) -> Value {
+        if let Some(already_existing) = intern_strings.find_string(value) {
+            return Value::from(already_existing);
         }
+        let key = StringObject::new_rc(value);
+        garbage_collector.track_string(&key);
+        let value_key = key.clone();
+        let bytes_before = intern_strings.get_allocated_bytes();
+        intern_strings.insert(key, Value::new_nil());
+        // Intern table entries count towards the heap size as well
+        garbage_collector.track_growth(
+            intern_strings
+                .get_allocated_bytes()
+                .saturating_sub(bytes_before),
+        );
+        Value::from(value_key)
+    }
+
+    pub fn is_string_object(&self) -> bool {
+        self.get_type() == ValueType::StringObject
+    }
+
+    pub fn new_function_object(name: &str) -> Value {
+        Value::from(FunctionObject::new_rc(name))
+    }
+
+    pub fn is_function_object(&self) -> bool {
+        self.get_type() == ValueType::FunctionObject
+    }
+
+    pub fn is_native_function(&self) -> bool {
+        self.get_type() == ValueType::NativeFunction
+    }
+
+    pub fn new_closure_object(
+        function: Rc<RefCell<FunctionObject>>,
+        garbage_collector: &mut GarbageCollector,
+    ) -> Value {
+        let closure = ClosureObject::new_rc(function);
+        garbage_collector.track(&closure);
+        Value::from(closure)
+    }
+
+    pub fn is_closure_object(&self) -> bool {
+        self.get_type() == ValueType::ClosureObject
+    }
+
+    pub fn new_class_object(name: &str, garbage_collector: &mut GarbageCollector) -> Value {
+        let class = ClassObject::new_rc(name);
+        garbage_collector.track(&class);
+        Value::from(class)
+    }
+
+    pub fn is_class_object(&self) -> bool {
+        self.get_type() == ValueType::ClassObject
+    }
+
+    pub fn new_instance_object(
+        class: &Rc<RefCell<ClassObject>>,
+        garbage_collector: &mut GarbageCollector,
+    ) -> Value {
+        let instance = InstanceObject::new_rc(class);
+        garbage_collector.track(&instance);
+        Value::from(instance)
+    }
+
+    pub fn is_instance_object(&self) -> bool {
+        self.get_type() == ValueType::InstanceObject
     }
 
     pub fn new_bound_method_object(
         instance: Rc<RefCell<InstanceObject>>,
         method: Rc<RefCell<ClosureObject>>,
+        garbage_collector: &mut GarbageCollector,
     ) -> Value {
-        Value {
-            value_type: ValueType::BoundMethodObject,
-            actual_value: UnderlyingValue {
-                bound_method_object: ManuallyDrop::new(BoundMethodObject::new_rc(instance, method)),
-            },
-        }
+        let bound_method = BoundMethodObject::new_rc(instance, method);
+        garbage_collector.track(&bound_method);
+        Value::from(bound_method)
     }
 
     pub fn is_bound_method_object(&self) -> bool {
-        self.value_type == ValueType::BoundMethodObject
+        self.get_type() == ValueType::BoundMethodObject
     }
 
-    pub fn get_bound_method_object(
-        &self,
-    ) -> Result<&Rc<RefCell<BoundMethodObject>>, ValueInterpretingError> {
-        match self.value_type {
-            ValueType::BoundMethodObject => unsafe { Ok(&self.actual_value.bound_method_object) },
-            _ => Err(ValueInterpretingError {}),
-        }
+    pub fn new_list_object(items: Vec<Value>, garbage_collector: &mut GarbageCollector) -> Value {
+        let list = ListObject::new_rc(items);
+        garbage_collector.track(&list);
+        Value::from(list)
+    }
+
+    pub fn is_list_object(&self) -> bool {
+        self.get_type() == ValueType::ListObject
+    }
+
+    pub fn new_map_object(
+        entries: Table<Value>,
+        garbage_collector: &mut GarbageCollector,
+    ) -> Value {
+        let map = MapObject::new_rc(entries);
+        garbage_collector.track(&map);
+        Value::from(map)
     }
 
-    pub fn get_type(&self) -> ValueType {
-        self.value_type
+    pub fn is_map_object(&self) -> bool {
+        self.get_type() == ValueType::MapObject
+    }
+
+    // Module doesn't reference any object, so it can't be a part of cycle and isn't tracked
+    pub fn new_module_object(name: &str, module_index: usize) -> Value {
+        Value::from(ModuleObject::new_rc(name, module_index))
+    }
+
+    pub fn is_module_object(&self) -> bool {
+        self.get_type() == ValueType::ModuleObject
+    }
+
+    pub(crate) fn new_coroutine_object(
+        closure: Rc<RefCell<ClosureObject>>,
+        stack: Vec<Value>,
+        frame: CallFrame,
+        garbage_collector: &mut GarbageCollector,
+    ) -> Value {
+        let coroutine = CoroutineObject::new_rc(closure, stack, frame);
+        garbage_collector.track(&coroutine);
+        Value::from(coroutine)
+    }
+
+    pub fn is_coroutine_object(&self) -> bool {
+        self.get_type() == ValueType::CoroutineObject
+    }
+
+    /// Only values that are compared by their content (and not by reference) can be used as map keys
+    pub fn is_hashable(&self) -> bool {
+        matches!(
+            self.get_type(),
+            ValueType::Nil | ValueType::Bool | ValueType::Number | ValueType::StringObject
+        )
     }
 
     pub fn is_falsey(&self) -> bool {
-        match self.value_type {
+        match self.get_type() {
             ValueType::Bool => !self.get_bool().expect("Bool type should contain bool"),
             ValueType::Nil => true,
             _ => false,
@@ -556,10 +1049,10 @@ impl Value {
     }
 
     pub fn are_values_equal(lhs: &Value, rhs: &Value) -> bool {
-        if lhs.value_type != rhs.value_type {
+        if lhs.get_type() != rhs.get_type() {
             return false;
         }
-        match lhs.value_type {
+        match lhs.get_type() {
             ValueType::Bool => {
                 lhs.get_bool().expect("Bool type should contain bool")
                     == rhs.get_bool().expect("Bool type should contain bool")
@@ -570,141 +1063,115 @@ impl Value {
                     == rhs.get_number().expect("Number type should contain number")
             }
             ValueType::StringObject => StringObject::are_equal_rc(
-                lhs.get_string_object()
+                &lhs.get_string_object()
                     .expect("StringObject type should contain String Object"),
-                rhs.get_string_object()
+                &rhs.get_string_object()
                     .expect("StringObject type should contain String Object"),
             ),
             ValueType::FunctionObject => FunctionObject::are_equal_rc(
-                lhs.get_function_object()
+                &lhs.get_function_object()
                     .expect("FunctionObject type should contain function object"),
-                rhs.get_function_object()
+                &rhs.get_function_object()
                     .expect("FunctionObject type should contain function object"),
             ),
             ValueType::NativeFunction => {
                 // We are comparing function pointers here
-                lhs.get_native_function()
-                    .expect("NativeFunction type should contain native function")
-                    == rhs
-                        .get_native_function()
-                        .expect("NativeFunction type should contain native function")
+                std::ptr::fn_addr_eq(
+                    lhs.get_native_function()
+                        .expect("NativeFunction type should contain native function"),
+                    rhs.get_native_function()
+                        .expect("NativeFunction type should contain native function"),
+                )
             }
             ValueType::ClosureObject => ClosureObject::are_equal_rc(
-                lhs.get_closure_object()
+                &lhs.get_closure_object()
                     .expect("ClosureObject type should contain closure object"),
-                rhs.get_closure_object()
+                &rhs.get_closure_object()
                     .expect("ClosureObject type should contain closure object"),
             ),
             ValueType::ClassObject => ClassObject::are_equal_rc(
-                lhs.get_class_object()
+                &lhs.get_class_object()
                     .expect("ClassObject type should containt class object"),
-                rhs.get_class_object()
+                &rhs.get_class_object()
                     .expect("ClassObject type should containt class object"),
             ),
             ValueType::InstanceObject => InstanceObject::are_equal_rc(
-                lhs.get_instance_object()
+                &lhs.get_instance_object()
                     .expect("InstanceObject type should contain instance object"),
-                rhs.get_instance_object()
+                &rhs.get_instance_object()
                     .expect("InstanceObject type should contain instance object"),
             ),
             ValueType::BoundMethodObject => BoundMethodObject::are_equal_rc(
-                lhs.get_bound_method_object()
+                &lhs.get_bound_method_object()
                     .expect("BoundMethodObject type should contain bound method object."),
-                rhs.get_bound_method_object()
+                &rhs.get_bound_method_object()
                     .expect("BoundMethodObject type should contain bound method object."),
             ),
+            ValueType::ListObject => ListObject::are_equal_rc(
+                &lhs.get_list_object()
+                    .expect("ListObject type should contain list object"),
+                &rhs.get_list_object()
+                    .expect("ListObject type should contain list object"),
+            ),
+            ValueType::MapObject => MapObject::are_equal_rc(
+                &lhs.get_map_object()
+                    .expect("MapObject type should contain map object"),
+                &rhs.get_map_object()
+                    .expect("MapObject type should contain map object"),
+            ),
+            ValueType::ModuleObject => ModuleObject::are_equal_rc(
+                &lhs.get_module_object()
+                    .expect("ModuleObject type should contain module object"),
+                &rhs.get_module_object()
+                    .expect("ModuleObject type should contain module object"),
+            ),
+            ValueType::CoroutineObject => CoroutineObject::are_equal_rc(
+                &lhs.get_coroutine_object()
+                    .expect("CoroutineObject type should contain coroutine object"),
+                &rhs.get_coroutine_object()
+                    .expect("CoroutineObject type should contain coroutine object"),
+            ),
         }
     }
 }
 
-impl Clone for Value {
-    fn clone(&self) -> Self {
-        let actual_value_clone = match self.value_type {
-            ValueType::Bool => UnderlyingValue {
-                boolean: self.get_bool().expect("Bool type should contain bool"),
+impl TableKey for Value {
+    // Only values for which `is_hashable` returns true can be used as keys
+    fn get_hash(&self) -> u32 {
+        match self.get_type() {
+            ValueType::Nil => 0,
+            ValueType::Bool => match self.get_bool().expect("Bool type should contain bool") {
+                true => 1,
+                false => 2,
             },
-            ValueType::Nil => UnderlyingValue { number: 0.0 },
-            ValueType::Number => UnderlyingValue {
-                number: self
+            ValueType::Number => {
+                // Adding 0.0 turns -0.0 into 0.0, as they are equal they must have the same hash
+                let bits = (self
                     .get_number()
-                    .expect("Number type type should contain number"),
-            },
-            ValueType::StringObject => UnderlyingValue {
-                string_object: ManuallyDrop::new(
-                    self.get_string_object()
-                        .expect("StringObject type should contain String Object")
-                        .clone(),
-                ),
-            },
-            ValueType::FunctionObject => UnderlyingValue {
-                function_object: ManuallyDrop::new(
-                    self.get_function_object()
-                        .expect("FunctionObject type should containt Function Object")
-                        .clone(),
-                ),
-            },
-            ValueType::NativeFunction => UnderlyingValue {
-                native_function: self
-                    .get_native_function()
-                    .expect("NativeFunction type should contain native function"),
-            },
-            ValueType::ClosureObject => UnderlyingValue {
-                closure_object: ManuallyDrop::new(
-                    self.get_closure_object()
-                        .expect("ClosureObject type should contain closure object")
-                        .clone(),
-                ),
-            },
-            ValueType::ClassObject => UnderlyingValue {
-                class_object: ManuallyDrop::new(
-                    self.get_class_object()
-                        .expect("ClassObject type should contain class object")
-                        .clone(),
-                ),
-            },
-            ValueType::InstanceObject => UnderlyingValue {
-                instance_object: ManuallyDrop::new(
-                    self.get_instance_object()
-                        .expect("InstanceObject type should contain instance object")
-                        .clone(),
-                ),
-            },
-            ValueType::BoundMethodObject => UnderlyingValue {
-                bound_method_object: ManuallyDrop::new(
-                    self.get_bound_method_object()
-                        .expect("BoundMethodObject type should contain bound method object.")
-                        .clone(),
-                ),
-            },
-        };
-        Self {
-            value_type: self.value_type,
-            actual_value: actual_value_clone,
+                    .expect("Number type should contain number")
+                    + 0.0)
+                    .to_bits();
+                // Whole numbers differ only in the highest bits, so they are mixed into the lower ones
+                // (which are used by the table to pick the entry)
+                (bits.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32) as u32
+            }
+            ValueType::StringObject => self
+                .get_string_object()
+                .expect("StringObject type should contain String Object")
+                .borrow()
+                .get_hash(),
+            _ => panic!("Only hashable values can be used as table keys"),
         }
     }
-}
 
-impl Drop for Value {
-    fn drop(&mut self) {
-        if self.is_string_object() {
-            unsafe { ManuallyDrop::drop(&mut self.actual_value.string_object) }
-        } else if self.is_function_object() {
-            unsafe { ManuallyDrop::drop(&mut self.actual_value.function_object) }
-        } else if self.is_closure_object() {
-            unsafe { ManuallyDrop::drop(&mut self.actual_value.closure_object) }
-        } else if self.is_class_object() {
-            unsafe { ManuallyDrop::drop(&mut self.actual_value.class_object) }
-        } else if self.is_instance_object() {
-            unsafe { ManuallyDrop::drop(&mut self.actual_value.instance_object) }
-        } else if self.is_bound_method_object() {
-            unsafe { ManuallyDrop::drop(&mut self.actual_value.bound_method_object) }
-        }
+    fn is_same_key(&self, other: &Self) -> bool {
+        Value::are_values_equal(self, other)
     }
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.value_type {
+        match self.get_type() {
             ValueType::Bool => write!(
                 f,
                 "{}",
@@ -748,16 +1215,15 @@ impl fmt::Display for Value {
                     .borrow()
                     .get_value()
             ),
-            ValueType::ClassObject => write!(
-                f,
-                "<class {}>",
-                self.get_class_object()
-                    .expect("ClassObject type should contain class object")
-                    .borrow()
-                    .name
-                    .borrow()
-                    .get_value()
-            ),
+            ValueType::ClassObject => {
+                let class = self
+                    .get_class_object()
+                    .expect("ClassObject type should contain class object");
+                let class = class.borrow();
+                let kind = if class.is_trait { "trait" } else { "class" };
+                let result = write!(f, "<{} {}>", kind, class.name.borrow().get_value());
+                result
+            }
             ValueType::InstanceObject => write!(
                 f,
                 "<instance {}>",
@@ -784,6 +1250,65 @@ impl fmt::Display for Value {
                     .borrow()
                     .get_value()
             ),
+            ValueType::ListObject => {
+                let list = self
+                    .get_list_object()
+                    .expect("ListObject type should contain list object");
+                // List is mutably borrowed while its items are written, so if it (directly or not) contains
+                // itself, the inner occurrence can't be borrowed and we don't end up in infinite recursion
+                let Ok(list) = list.try_borrow_mut() else {
+                    return write!(f, "[...]");
+                };
+                write!(f, "[")?;
+                for (index, item) in list.items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            ValueType::MapObject => {
+                let map = self
+                    .get_map_object()
+                    .expect("MapObject type should contain map object");
+                // Same as with lists, map containing itself is printed only once
+                let Ok(map) = map.try_borrow_mut() else {
+                    return write!(f, "{{...}}");
+                };
+                write!(f, "{{")?;
+                for (index, (key, value)) in map.entries.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+            ValueType::ModuleObject => write!(
+                f,
+                "<module {}>",
+                self.get_module_object()
+                    .expect("ModuleObject type should contain module object")
+                    .borrow()
+                    .name
+                    .borrow()
+                    .get_value()
+            ),
+            ValueType::CoroutineObject => write!(
+                f,
+                "<coroutine {}>",
+                self.get_coroutine_object()
+                    .expect("CoroutineObject type should contain coroutine object")
+                    .borrow()
+                    .closure
+                    .borrow()
+                    .function
+                    .borrow()
+                    .name
+                    .borrow()
+                    .get_value()
+            ),
         }
     }
 }
@@ -813,6 +1338,14 @@ impl ValueContainer {
     pub fn get_value(&self, offset: usize) -> Value {
         self.values[offset].clone()
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        self.values.iter()
+    }
+
+    pub fn get_allocated_bytes(&self) -> usize {
+        self.values.capacity() * mem::size_of::<Value>()
+    }
 }
 
 impl Default for ValueContainer {
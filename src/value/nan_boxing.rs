@@ -0,0 +1,319 @@
+use std::{cell::RefCell, marker::PhantomData, mem::ManuallyDrop, ops::Deref, rc::Rc};
+
+use super::{
+    BoundMethodObject, ClassObject, ClosureObject, CoroutineObject, FunctionObject, InstanceObject,
+    ListObject, MapObject, ModuleObject, NativeFunction, StringObject, ValueInterpretingError,
+    ValueType,
+};
+
+// GENERAL IDEA:
+// Every value is stored in a single 64 bit word. Numbers are stored as they are. Everything else is stored
+// inside quiet NaN - all exponent bits and the quiet bit are set, sign bit and bits 48-50 hold type tag and
+// the lowest 48 bits hold the payload (boolean, function pointer or pointer to the object).
+// To make sure that no "real" NaN is mistaken for other value, all NaNs created by the program are
+// normalized to the single canonical NaN, which has all tag bits set to zero.
+
+const QUIET_NAN: u64 = 0x7ff8_0000_0000_0000;
+const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+const LOW_TAG_BITS_SHIFT: u64 = 48;
+const LOW_TAG_BITS_MASK: u64 = 0b111;
+const PAYLOAD_MASK: u64 = 0x0000_ffff_ffff_ffff;
+
+// Tag 0 is reserved for the canonical NaN
+const NIL_TAG: u64 = 1;
+const BOOL_TAG: u64 = 2;
+const NATIVE_FUNCTION_TAG: u64 = 3;
+const STRING_OBJECT_TAG: u64 = 4;
+const FUNCTION_OBJECT_TAG: u64 = 5;
+const CLOSURE_OBJECT_TAG: u64 = 6;
+const CLASS_OBJECT_TAG: u64 = 7;
+const INSTANCE_OBJECT_TAG: u64 = 8;
+const BOUND_METHOD_OBJECT_TAG: u64 = 9;
+const LIST_OBJECT_TAG: u64 = 10;
+const MAP_OBJECT_TAG: u64 = 11;
+const MODULE_OBJECT_TAG: u64 = 12;
+const COROUTINE_OBJECT_TAG: u64 = 13;
+
+pub struct Value {
+    bits: u64,
+}
+
+/// Reference to the object stored inside the value.
+/// It behaves like `&Rc<RefCell<T>>` - it borrows the value and never touches the reference count.
+pub struct ObjectReference<'a, T> {
+    object: ManuallyDrop<Rc<RefCell<T>>>,
+    value: PhantomData<&'a Value>,
+}
+
+impl<T> Deref for ObjectReference<'_, T> {
+    type Target = Rc<RefCell<T>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.object
+    }
+}
+
+impl Value {
+    fn new_tagged(tag: u64, payload: u64) -> Value {
+        debug_assert!(payload & !PAYLOAD_MASK == 0, "Payload must fit in 48 bits");
+        let sign = if tag & 0b1000 != 0 { SIGN_BIT } else { 0 };
+        Value {
+            bits: QUIET_NAN | sign | ((tag & LOW_TAG_BITS_MASK) << LOW_TAG_BITS_SHIFT) | payload,
+        }
+    }
+
+    fn get_tag(&self) -> u64 {
+        if self.bits & QUIET_NAN != QUIET_NAN {
+            return 0;
+        }
+        let sign = if self.bits & SIGN_BIT != 0 { 0b1000 } else { 0 };
+        sign | ((self.bits >> LOW_TAG_BITS_SHIFT) & LOW_TAG_BITS_MASK)
+    }
+
+    fn get_payload(&self) -> u64 {
+        self.bits & PAYLOAD_MASK
+    }
+
+    fn new_object<T>(tag: u64, object: Rc<RefCell<T>>) -> Value {
+        Self::new_tagged(tag, Rc::into_raw(object) as u64)
+    }
+
+    fn get_object<T>(&self, tag: u64) -> Result<ObjectReference<'_, T>, ValueInterpretingError> {
+        if self.get_tag() != tag {
+            return Err(ValueInterpretingError {});
+        }
+        // SAFETY: value with this tag always holds pointer created with `Rc::into_raw`, which is kept alive
+        // for as long as the value exists. Reference is never dropped, so the reference count stays the same.
+        let object = unsafe { Rc::from_raw(self.get_payload() as *const RefCell<T>) };
+        Ok(ObjectReference {
+            object: ManuallyDrop::new(object),
+            value: PhantomData,
+        })
+    }
+
+    pub fn new_bool(value: bool) -> Value {
+        Self::new_tagged(BOOL_TAG, value as u64)
+    }
+
+    pub fn get_bool(&self) -> Result<bool, ValueInterpretingError> {
+        match self.get_tag() {
+            BOOL_TAG => Ok(self.get_payload() != 0),
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn new_number(value: f64) -> Value {
+        // Only canonical NaN can be stored as number, other NaNs could be confused with tagged values
+        let value = if value.is_nan() { f64::NAN } else { value };
+        Value {
+            bits: value.to_bits(),
+        }
+    }
+
+    pub fn get_number(&self) -> Result<f64, ValueInterpretingError> {
+        match self.get_tag() {
+            0 => Ok(f64::from_bits(self.bits)),
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn new_nil() -> Value {
+        Self::new_tagged(NIL_TAG, 0)
+    }
+
+    pub fn get_string_object(
+        &self,
+    ) -> Result<ObjectReference<'_, StringObject>, ValueInterpretingError> {
+        self.get_object(STRING_OBJECT_TAG)
+    }
+
+    pub fn get_function_object(
+        &self,
+    ) -> Result<ObjectReference<'_, FunctionObject>, ValueInterpretingError> {
+        self.get_object(FUNCTION_OBJECT_TAG)
+    }
+
+    pub fn new_native_function(function: NativeFunction) -> Value {
+        Self::new_tagged(NATIVE_FUNCTION_TAG, function as usize as u64)
+    }
+
+    pub fn get_native_function(&self) -> Result<NativeFunction, ValueInterpretingError> {
+        match self.get_tag() {
+            // SAFETY: payload was created from the function pointer in `new_native_function`
+            NATIVE_FUNCTION_TAG => unsafe {
+                Ok(std::mem::transmute::<usize, NativeFunction>(
+                    self.get_payload() as usize,
+                ))
+            },
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn get_closure_object(
+        &self,
+    ) -> Result<ObjectReference<'_, ClosureObject>, ValueInterpretingError> {
+        self.get_object(CLOSURE_OBJECT_TAG)
+    }
+
+    pub fn get_class_object(
+        &self,
+    ) -> Result<ObjectReference<'_, ClassObject>, ValueInterpretingError> {
+        self.get_object(CLASS_OBJECT_TAG)
+    }
+
+    pub fn get_instance_object(
+        &self,
+    ) -> Result<ObjectReference<'_, InstanceObject>, ValueInterpretingError> {
+        self.get_object(INSTANCE_OBJECT_TAG)
+    }
+
+    pub fn get_bound_method_object(
+        &self,
+    ) -> Result<ObjectReference<'_, BoundMethodObject>, ValueInterpretingError> {
+        self.get_object(BOUND_METHOD_OBJECT_TAG)
+    }
+
+    pub fn get_list_object(
+        &self,
+    ) -> Result<ObjectReference<'_, ListObject>, ValueInterpretingError> {
+        self.get_object(LIST_OBJECT_TAG)
+    }
+
+    pub fn get_map_object(&self) -> Result<ObjectReference<'_, MapObject>, ValueInterpretingError> {
+        self.get_object(MAP_OBJECT_TAG)
+    }
+
+    pub fn get_module_object(
+        &self,
+    ) -> Result<ObjectReference<'_, ModuleObject>, ValueInterpretingError> {
+        self.get_object(MODULE_OBJECT_TAG)
+    }
+
+    pub fn get_coroutine_object(
+        &self,
+    ) -> Result<ObjectReference<'_, CoroutineObject>, ValueInterpretingError> {
+        self.get_object(COROUTINE_OBJECT_TAG)
+    }
+
+    pub fn get_type(&self) -> ValueType {
+        match self.get_tag() {
+            NIL_TAG => ValueType::Nil,
+            BOOL_TAG => ValueType::Bool,
+            NATIVE_FUNCTION_TAG => ValueType::NativeFunction,
+            STRING_OBJECT_TAG => ValueType::StringObject,
+            FUNCTION_OBJECT_TAG => ValueType::FunctionObject,
+            CLOSURE_OBJECT_TAG => ValueType::ClosureObject,
+            CLASS_OBJECT_TAG => ValueType::ClassObject,
+            INSTANCE_OBJECT_TAG => ValueType::InstanceObject,
+            BOUND_METHOD_OBJECT_TAG => ValueType::BoundMethodObject,
+            LIST_OBJECT_TAG => ValueType::ListObject,
+            MAP_OBJECT_TAG => ValueType::MapObject,
+            MODULE_OBJECT_TAG => ValueType::ModuleObject,
+            COROUTINE_OBJECT_TAG => ValueType::CoroutineObject,
+            _ => ValueType::Number,
+        }
+    }
+
+    /// Increment (or decrement) reference count of the object stored inside the value
+    fn update_reference_count(&self, increment: bool) {
+        unsafe fn update<T>(pointer: u64, increment: bool) {
+            let pointer = pointer as *const RefCell<T>;
+            if increment {
+                Rc::increment_strong_count(pointer);
+            } else {
+                Rc::decrement_strong_count(pointer);
+            }
+        }
+
+        let pointer = self.get_payload();
+        // SAFETY: pointers of these types were created with `Rc::into_raw` and are alive while the value exists
+        unsafe {
+            match self.get_tag() {
+                STRING_OBJECT_TAG => update::<StringObject>(pointer, increment),
+                FUNCTION_OBJECT_TAG => update::<FunctionObject>(pointer, increment),
+                CLOSURE_OBJECT_TAG => update::<ClosureObject>(pointer, increment),
+                CLASS_OBJECT_TAG => update::<ClassObject>(pointer, increment),
+                INSTANCE_OBJECT_TAG => update::<InstanceObject>(pointer, increment),
+                BOUND_METHOD_OBJECT_TAG => update::<BoundMethodObject>(pointer, increment),
+                LIST_OBJECT_TAG => update::<ListObject>(pointer, increment),
+                MAP_OBJECT_TAG => update::<MapObject>(pointer, increment),
+                MODULE_OBJECT_TAG => update::<ModuleObject>(pointer, increment),
+                COROUTINE_OBJECT_TAG => update::<CoroutineObject>(pointer, increment),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl From<Rc<RefCell<StringObject>>> for Value {
+    fn from(value: Rc<RefCell<StringObject>>) -> Self {
+        Value::new_object(STRING_OBJECT_TAG, value)
+    }
+}
+
+impl From<Rc<RefCell<FunctionObject>>> for Value {
+    fn from(value: Rc<RefCell<FunctionObject>>) -> Self {
+        Value::new_object(FUNCTION_OBJECT_TAG, value)
+    }
+}
+
+impl From<Rc<RefCell<ClosureObject>>> for Value {
+    fn from(value: Rc<RefCell<ClosureObject>>) -> Self {
+        Value::new_object(CLOSURE_OBJECT_TAG, value)
+    }
+}
+
+impl From<Rc<RefCell<ClassObject>>> for Value {
+    fn from(value: Rc<RefCell<ClassObject>>) -> Self {
+        Value::new_object(CLASS_OBJECT_TAG, value)
+    }
+}
+
+impl From<Rc<RefCell<InstanceObject>>> for Value {
+    fn from(value: Rc<RefCell<InstanceObject>>) -> Self {
+        Value::new_object(INSTANCE_OBJECT_TAG, value)
+    }
+}
+
+impl From<Rc<RefCell<BoundMethodObject>>> for Value {
+    fn from(value: Rc<RefCell<BoundMethodObject>>) -> Self {
+        Value::new_object(BOUND_METHOD_OBJECT_TAG, value)
+    }
+}
+
+impl From<Rc<RefCell<ListObject>>> for Value {
+    fn from(value: Rc<RefCell<ListObject>>) -> Self {
+        Value::new_object(LIST_OBJECT_TAG, value)
+    }
+}
+
+impl From<Rc<RefCell<MapObject>>> for Value {
+    fn from(value: Rc<RefCell<MapObject>>) -> Self {
+        Value::new_object(MAP_OBJECT_TAG, value)
+    }
+}
+
+impl From<Rc<RefCell<ModuleObject>>> for Value {
+    fn from(value: Rc<RefCell<ModuleObject>>) -> Self {
+        Value::new_object(MODULE_OBJECT_TAG, value)
+    }
+}
+
+impl From<Rc<RefCell<CoroutineObject>>> for Value {
+    fn from(value: Rc<RefCell<CoroutineObject>>) -> Self {
+        Value::new_object(COROUTINE_OBJECT_TAG, value)
+    }
+}
+
+impl Clone for Value {
+    fn clone(&self) -> Self {
+        self.update_reference_count(true);
+        Value { bits: self.bits }
+    }
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        self.update_reference_count(false);
+    }
+}
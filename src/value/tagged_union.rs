@@ -0,0 +1,460 @@
+use std::{cell::RefCell, mem::ManuallyDrop, ops::Deref, rc::Rc};
+
+use super::{
+    BoundMethodObject, ClassObject, ClosureObject, CoroutineObject, FunctionObject, InstanceObject,
+    ListObject, MapObject, ModuleObject, NativeFunction, StringObject, ValueInterpretingError,
+    ValueType,
+};
+
+// Default representation of the value - type tag followed by the union holding the actual value
+
+#[repr(C)]
+union UnderlyingValue {
+    boolean: bool,
+    number: f64,
+    string_object: ManuallyDrop<Rc<RefCell<StringObject>>>,
+    function_object: ManuallyDrop<Rc<RefCell<FunctionObject>>>,
+    native_function: NativeFunction,
+    closure_object: ManuallyDrop<Rc<RefCell<ClosureObject>>>,
+    class_object: ManuallyDrop<Rc<RefCell<ClassObject>>>,
+    instance_object: ManuallyDrop<Rc<RefCell<InstanceObject>>>,
+    bound_method_object: ManuallyDrop<Rc<RefCell<BoundMethodObject>>>,
+    list_object: ManuallyDrop<Rc<RefCell<ListObject>>>,
+    map_object: ManuallyDrop<Rc<RefCell<MapObject>>>,
+    module_object: ManuallyDrop<Rc<RefCell<ModuleObject>>>,
+    coroutine_object: ManuallyDrop<Rc<RefCell<CoroutineObject>>>,
+}
+
+pub struct Value {
+    value_type: ValueType,
+    actual_value: UnderlyingValue,
+}
+
+/// Reference to the object stored inside the value
+pub struct ObjectReference<'a, T> {
+    object: &'a Rc<RefCell<T>>,
+}
+
+impl<T> Deref for ObjectReference<'_, T> {
+    type Target = Rc<RefCell<T>>;
+
+    fn deref(&self) -> &Self::Target {
+        self.object
+    }
+}
+
+impl Value {
+    pub fn new_bool(value: bool) -> Value {
+        Value {
+            value_type: ValueType::Bool,
+            actual_value: UnderlyingValue { boolean: value },
+        }
+    }
+
+    pub fn get_bool(&self) -> Result<bool, ValueInterpretingError> {
+        match self.value_type {
+            ValueType::Bool => unsafe { Ok(self.actual_value.boolean) },
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn new_number(value: f64) -> Value {
+        Value {
+            value_type: ValueType::Number,
+            actual_value: UnderlyingValue { number: value },
+        }
+    }
+
+    pub fn get_number(&self) -> Result<f64, ValueInterpretingError> {
+        match self.value_type {
+            ValueType::Number => unsafe { Ok(self.actual_value.number) },
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn new_nil() -> Value {
+        Value {
+            value_type: ValueType::Nil,
+            actual_value: UnderlyingValue { number: 0.0 },
+        }
+    }
+
+    pub fn get_string_object(
+        &self,
+    ) -> Result<ObjectReference<'_, StringObject>, ValueInterpretingError> {
+        match self.value_type {
+            ValueType::StringObject => unsafe {
+                Ok(ObjectReference {
+                    object: &self.actual_value.string_object,
+                })
+            },
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn get_function_object(
+        &self,
+    ) -> Result<ObjectReference<'_, FunctionObject>, ValueInterpretingError> {
+        match self.value_type {
+            ValueType::FunctionObject => unsafe {
+                Ok(ObjectReference {
+                    object: &self.actual_value.function_object,
+                })
+            },
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn new_native_function(function: NativeFunction) -> Value {
+        Value {
+            value_type: ValueType::NativeFunction,
+            actual_value: UnderlyingValue {
+                native_function: function,
+            },
+        }
+    }
+
+    pub fn get_native_function(&self) -> Result<NativeFunction, ValueInterpretingError> {
+        match self.value_type {
+            ValueType::NativeFunction => unsafe { Ok(self.actual_value.native_function) },
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn get_closure_object(
+        &self,
+    ) -> Result<ObjectReference<'_, ClosureObject>, ValueInterpretingError> {
+        match self.value_type {
+            ValueType::ClosureObject => unsafe {
+                Ok(ObjectReference {
+                    object: &self.actual_value.closure_object,
+                })
+            },
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn get_class_object(
+        &self,
+    ) -> Result<ObjectReference<'_, ClassObject>, ValueInterpretingError> {
+        match self.value_type {
+            ValueType::ClassObject => unsafe {
+                Ok(ObjectReference {
+                    object: &self.actual_value.class_object,
+                })
+            },
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn get_instance_object(
+        &self,
+    ) -> Result<ObjectReference<'_, InstanceObject>, ValueInterpretingError> {
+        match self.value_type {
+            ValueType::InstanceObject => unsafe {
+                Ok(ObjectReference {
+                    object: &self.actual_value.instance_object,
+                })
+            },
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn get_bound_method_object(
+        &self,
+    ) -> Result<ObjectReference<'_, BoundMethodObject>, ValueInterpretingError> {
+        match self.value_type {
+            ValueType::BoundMethodObject => unsafe {
+                Ok(ObjectReference {
+                    object: &self.actual_value.bound_method_object,
+                })
+            },
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn get_list_object(
+        &self,
+    ) -> Result<ObjectReference<'_, ListObject>, ValueInterpretingError> {
+        match self.value_type {
+            ValueType::ListObject => unsafe {
+                Ok(ObjectReference {
+                    object: &self.actual_value.list_object,
+                })
+            },
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn get_map_object(&self) -> Result<ObjectReference<'_, MapObject>, ValueInterpretingError> {
+        match self.value_type {
+            ValueType::MapObject => unsafe {
+                Ok(ObjectReference {
+                    object: &self.actual_value.map_object,
+                })
+            },
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn get_module_object(
+        &self,
+    ) -> Result<ObjectReference<'_, ModuleObject>, ValueInterpretingError> {
+        match self.value_type {
+            ValueType::ModuleObject => unsafe {
+                Ok(ObjectReference {
+                    object: &self.actual_value.module_object,
+                })
+            },
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn get_coroutine_object(
+        &self,
+    ) -> Result<ObjectReference<'_, CoroutineObject>, ValueInterpretingError> {
+        match self.value_type {
+            ValueType::CoroutineObject => unsafe {
+                Ok(ObjectReference {
+                    object: &self.actual_value.coroutine_object,
+                })
+            },
+            _ => Err(ValueInterpretingError {}),
+        }
+    }
+
+    pub fn get_type(&self) -> ValueType {
+        self.value_type
+    }
+}
+
+impl From<Rc<RefCell<StringObject>>> for Value {
+    fn from(value: Rc<RefCell<StringObject>>) -> Self {
+        Value {
+            value_type: ValueType::StringObject,
+            actual_value: UnderlyingValue {
+                string_object: ManuallyDrop::new(value),
+            },
+        }
+    }
+}
+
+impl From<Rc<RefCell<FunctionObject>>> for Value {
+    fn from(value: Rc<RefCell<FunctionObject>>) -> Self {
+        Value {
+            value_type: ValueType::FunctionObject,
+            actual_value: UnderlyingValue {
+                function_object: ManuallyDrop::new(value),
+            },
+        }
+    }
+}
+
+impl From<Rc<RefCell<ClosureObject>>> for Value {
+    fn from(value: Rc<RefCell<ClosureObject>>) -> Self {
+        Value {
+            value_type: ValueType::ClosureObject,
+            actual_value: UnderlyingValue {
+                closure_object: ManuallyDrop::new(value),
+            },
+        }
+    }
+}
+
+impl From<Rc<RefCell<ClassObject>>> for Value {
+    fn from(value: Rc<RefCell<ClassObject>>) -> Self {
+        Value {
+            value_type: ValueType::ClassObject,
+            actual_value: UnderlyingValue {
+                class_object: ManuallyDrop::new(value),
+            },
+        }
+    }
+}
+
+impl From<Rc<RefCell<InstanceObject>>> for Value {
+    fn from(value: Rc<RefCell<InstanceObject>>) -> Self {
+        Value {
+            value_type: ValueType::InstanceObject,
+            actual_value: UnderlyingValue {
+                instance_object: ManuallyDrop::new(value),
+            },
+        }
+    }
+}
+
+impl From<Rc<RefCell<BoundMethodObject>>> for Value {
+    fn from(value: Rc<RefCell<BoundMethodObject>>) -> Self {
+        Value {
+            value_type: ValueType::BoundMethodObject,
+            actual_value: UnderlyingValue {
+                bound_method_object: ManuallyDrop::new(value),
+            },
+        }
+    }
+}
+
+impl From<Rc<RefCell<ListObject>>> for Value {
+    fn from(value: Rc<RefCell<ListObject>>) -> Self {
+        Value {
+            value_type: ValueType::ListObject,
+            actual_value: UnderlyingValue {
+                list_object: ManuallyDrop::new(value),
+            },
+        }
+    }
+}
+
+impl From<Rc<RefCell<MapObject>>> for Value {
+    fn from(value: Rc<RefCell<MapObject>>) -> Self {
+        Value {
+            value_type: ValueType::MapObject,
+            actual_value: UnderlyingValue {
+                map_object: ManuallyDrop::new(value),
+            },
+        }
+    }
+}
+
+impl From<Rc<RefCell<ModuleObject>>> for Value {
+    fn from(value: Rc<RefCell<ModuleObject>>) -> Self {
+        Value {
+            value_type: ValueType::ModuleObject,
+            actual_value: UnderlyingValue {
+                module_object: ManuallyDrop::new(value),
+            },
+        }
+    }
+}
+
+impl From<Rc<RefCell<CoroutineObject>>> for Value {
+    fn from(value: Rc<RefCell<CoroutineObject>>) -> Self {
+        Value {
+            value_type: ValueType::CoroutineObject,
+            actual_value: UnderlyingValue {
+                coroutine_object: ManuallyDrop::new(value),
+            },
+        }
+    }
+}
+
+impl Clone for Value {
+    fn clone(&self) -> Self {
+        let actual_value_clone = match self.value_type {
+            ValueType::Bool => UnderlyingValue {
+                boolean: self.get_bool().expect("Bool type should contain bool"),
+            },
+            ValueType::Nil => UnderlyingValue { number: 0.0 },
+            ValueType::Number => UnderlyingValue {
+                number: self
+                    .get_number()
+                    .expect("Number type type should contain number"),
+            },
+            ValueType::StringObject => UnderlyingValue {
+                string_object: ManuallyDrop::new(
+                    self.get_string_object()
+                        .expect("StringObject type should contain String Object")
+                        .clone(),
+                ),
+            },
+            ValueType::FunctionObject => UnderlyingValue {
+                function_object: ManuallyDrop::new(
+                    self.get_function_object()
+                        .expect("FunctionObject type should containt Function Object")
+                        .clone(),
+                ),
+            },
+            ValueType::NativeFunction => UnderlyingValue {
+                native_function: self
+                    .get_native_function()
+                    .expect("NativeFunction type should contain native function"),
+            },
+            ValueType::ClosureObject => UnderlyingValue {
+                closure_object: ManuallyDrop::new(
+                    self.get_closure_object()
+                        .expect("ClosureObject type should contain closure object")
+                        .clone(),
+                ),
+            },
+            ValueType::ClassObject => UnderlyingValue {
+                class_object: ManuallyDrop::new(
+                    self.get_class_object()
+                        .expect("ClassObject type should contain class object")
+                        .clone(),
+                ),
+            },
+            ValueType::InstanceObject => UnderlyingValue {
+                instance_object: ManuallyDrop::new(
+                    self.get_instance_object()
+                        .expect("InstanceObject type should contain instance object")
+                        .clone(),
+                ),
+            },
+            ValueType::BoundMethodObject => UnderlyingValue {
+                bound_method_object: ManuallyDrop::new(
+                    self.get_bound_method_object()
+                        .expect("BoundMethodObject type should contain bound method object.")
+                        .clone(),
+                ),
+            },
+            ValueType::ListObject => UnderlyingValue {
+                list_object: ManuallyDrop::new(
+                    self.get_list_object()
+                        .expect("ListObject type should contain list object")
+                        .clone(),
+                ),
+            },
+            ValueType::MapObject => UnderlyingValue {
+                map_object: ManuallyDrop::new(
+                    self.get_map_object()
+                        .expect("MapObject type should contain map object")
+                        .clone(),
+                ),
+            },
+            ValueType::ModuleObject => UnderlyingValue {
+                module_object: ManuallyDrop::new(
+                    self.get_module_object()
+                        .expect("ModuleObject type should contain module object")
+                        .clone(),
+                ),
+            },
+            ValueType::CoroutineObject => UnderlyingValue {
+                coroutine_object: ManuallyDrop::new(
+                    self.get_coroutine_object()
+                        .expect("CoroutineObject type should contain coroutine object")
+                        .clone(),
+                ),
+            },
+        };
+        Self {
+            value_type: self.value_type,
+            actual_value: actual_value_clone,
+        }
+    }
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        if self.is_string_object() {
+            unsafe { ManuallyDrop::drop(&mut self.actual_value.string_object) }
+        } else if self.is_function_object() {
+            unsafe { ManuallyDrop::drop(&mut self.actual_value.function_object) }
+        } else if self.is_closure_object() {
+            unsafe { ManuallyDrop::drop(&mut self.actual_value.closure_object) }
+        } else if self.is_class_object() {
+            unsafe { ManuallyDrop::drop(&mut self.actual_value.class_object) }
+        } else if self.is_instance_object() {
+            unsafe { ManuallyDrop::drop(&mut self.actual_value.instance_object) }
+        } else if self.is_bound_method_object() {
+            unsafe { ManuallyDrop::drop(&mut self.actual_value.bound_method_object) }
+        } else if self.is_list_object() {
+            unsafe { ManuallyDrop::drop(&mut self.actual_value.list_object) }
+        } else if self.is_map_object() {
+            unsafe { ManuallyDrop::drop(&mut self.actual_value.map_object) }
+        } else if self.is_module_object() {
+            unsafe { ManuallyDrop::drop(&mut self.actual_value.module_object) }
+        } else if self.is_coroutine_object() {
+            unsafe { ManuallyDrop::drop(&mut self.actual_value.coroutine_object) }
+        }
+    }
+}
@@ -1,14 +1,24 @@
-use std::{cell::RefCell, collections::BTreeSet, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
+    fs, iter, mem,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 use crate::{
-    chunk::{OperationCode, OperationCodeConversionError},
+    chunk::{DecodedInstruction, OperationCode, OperationCodeConversionError},
     compiler::{Compiler, FunctionType, INITIALIZER_NAME},
+    gc::{GarbageCollector, Traceable},
+    inline_cache::InlineCache,
     logger::Logger,
+    module::{self, Module, MAIN_MODULE_INDEX},
     native_functions,
-    table::{InsertResult, Table},
+    table::Table,
     value::{
-        ClassObject, ClosureObject, NativeFunction, StringObject, UpvalueObject,
-        UpvalueObjectBTreeWrapper, Value, ValueType,
+        ClassObject, ClosureObject, CoroutineObject, CoroutineState, FunctionObject,
+        InstanceObject, ListObject, MapObject, ModuleObject, NativeFunction, StringObject,
+        UpvalueObject, UpvalueObjectBTreeWrapper, Value, ValueType,
     },
 };
 
@@ -25,6 +35,7 @@ pub enum VirtualMachineError {
     StackOutOfBounds,
     InvalidVariableType,
     DivideByZero,
+    NonIntegerOperand,
     InvalidVariableNameType,
     UndefinedVariable,
     CallOnNotCallable,
@@ -35,15 +46,209 @@ pub enum VirtualMachineError {
     PropertyOutsideInstance,
     UndefinedProperty,
     HandlingMethodWithoutClass,
+    OutOfMemory,
+    StackOverflow,
+    IndexOutOfBounds,
+    InvalidMapKey,
+    UndefinedMapKey,
+    UncaughtException,
+    ConstantAssignment,
+    ImportFailure,
+    InvalidCoroutineState,
+    TraitConflict,
+}
+
+/// Lox code executed when the virtual machine is created, it defines built-in classes
+const PRELUDE: &str = "class Error { init(message) { this.message = message; } }";
+/// Class of errors reported by the virtual machine itself, defined by the prelude
+const ERROR_CLASS_NAME: &str = "Error";
+const ERROR_MESSAGE_FIELD: &str = "message";
+const ERROR_STACK_FIELD: &str = "stack";
+
+/// Operators that can be overloaded by defining method with special name in the class of the (left) operand
+#[derive(Clone, Copy)]
+enum OverloadedOperator {
+    Add,
+    Substract,
+    Multiply,
+    Divide,
+    Modulo,
+    Negate,
+    Equal,
+    Greater,
+    Less,
+    Index,
+    /// Conversion to string used by `print` and string interpolation
+    Str,
+    // Reflected operators are defined in the class of the right operand and called with the left operand as the argument
+    ReflectedAdd,
+    ReflectedSubstract,
+    ReflectedMultiply,
+    ReflectedDivide,
+    ReflectedModulo,
+}
+
+impl OverloadedOperator {
+    const ALL: [OverloadedOperator; 16] = [
+        OverloadedOperator::Add,
+        OverloadedOperator::Substract,
+        OverloadedOperator::Multiply,
+        OverloadedOperator::Divide,
+        OverloadedOperator::Modulo,
+        OverloadedOperator::Negate,
+        OverloadedOperator::Equal,
+        OverloadedOperator::Greater,
+        OverloadedOperator::Less,
+        OverloadedOperator::Index,
+        OverloadedOperator::Str,
+        OverloadedOperator::ReflectedAdd,
+        OverloadedOperator::ReflectedSubstract,
+        OverloadedOperator::ReflectedMultiply,
+        OverloadedOperator::ReflectedDivide,
+        OverloadedOperator::ReflectedModulo,
+    ];
+
+    fn get_method_name(self) -> &'static str {
+        match self {
+            OverloadedOperator::Add => "__add",
+            OverloadedOperator::Substract => "__sub",
+            OverloadedOperator::Multiply => "__mul",
+            OverloadedOperator::Divide => "__div",
+            OverloadedOperator::Modulo => "__mod",
+            OverloadedOperator::Negate => "__neg",
+            OverloadedOperator::Equal => "__eq",
+            OverloadedOperator::Greater => "__gt",
+            OverloadedOperator::Less => "__lt",
+            OverloadedOperator::Index => "__index",
+            OverloadedOperator::Str => "__str",
+            OverloadedOperator::ReflectedAdd => "__radd",
+            OverloadedOperator::ReflectedSubstract => "__rsub",
+            OverloadedOperator::ReflectedMultiply => "__rmul",
+            OverloadedOperator::ReflectedDivide => "__rdiv",
+            OverloadedOperator::ReflectedModulo => "__rmod",
+        }
+    }
+
+    /// Operator of the right operand that is used when the left operand doesn't overload this one.
+    /// Equality is symmetric and comparisons are mirrored (`a < b` is the same as `b > a`).
+    fn get_reflected(self) -> Option<OverloadedOperator> {
+        match self {
+            OverloadedOperator::Add => Some(OverloadedOperator::ReflectedAdd),
+            OverloadedOperator::Substract => Some(OverloadedOperator::ReflectedSubstract),
+            OverloadedOperator::Multiply => Some(OverloadedOperator::ReflectedMultiply),
+            OverloadedOperator::Divide => Some(OverloadedOperator::ReflectedDivide),
+            OverloadedOperator::Modulo => Some(OverloadedOperator::ReflectedModulo),
+            OverloadedOperator::Equal => Some(OverloadedOperator::Equal),
+            OverloadedOperator::Greater => Some(OverloadedOperator::Less),
+            OverloadedOperator::Less => Some(OverloadedOperator::Greater),
+            _ => None,
+        }
+    }
+}
+
+/// Module whose script is currently being executed as a result of the import
+struct LoadingModule {
+    /// Canonical path of the module file, under which the module is cached once it's loaded
+    path: PathBuf,
+    object: Rc<RefCell<ModuleObject>>,
+    /// Number of frames below the one executing the module script, when it's the current number of frames after
+    /// returning, the module is loaded
+    frames_count: usize,
 }
 
-struct CallFrame {
+/// Place to which the execution jumps when a value is thrown inside the `try` (or `catch`) block
+struct ExceptionHandler {
+    /// Index of the first instruction of the handler
+    handler_start: usize,
+    /// Stack size at the moment of registering the handler, everything above it is removed before the jump
+    stack_height: usize,
+}
+
+pub(crate) struct CallFrame {
     /// Closure that was called
     closure: Rc<RefCell<ClosureObject>>,
-    /// Index of next to execute instruction in function chunk
+    /// Function of the called closure, cached so that its constants can be read without going through the closure
+    function: Rc<RefCell<FunctionObject>>,
+    /// Decoded instructions of the function chunk
+    decoded_instructions: Rc<Vec<DecodedInstruction>>,
+    /// Inline caches of the function chunk
+    inline_caches: Rc<Vec<RefCell<InlineCache>>>,
+    /// Index of the inline cache of the currently executed instruction
+    inline_cache_index: Option<u32>,
+    /// Index of next to execute instruction in decoded instructions of the function chunk
     instruction_pointer: usize,
     /// Index of stack where frame local variables start
-    stack_start: u8,
+    stack_start: usize,
+    /// Module whose global variables are used by the function, cached so that it's not read from the function
+    module_index: usize,
+    /// Exception handlers registered by the frame, the most recent one is the last
+    handlers: Vec<ExceptionHandler>,
+    /// Returned value is converted to bool (with the same rule as conditions), used by `__eq` which is the result of `==`
+    returns_bool: bool,
+}
+
+impl CallFrame {
+    fn new(closure: Rc<RefCell<ClosureObject>>, stack_start: usize) -> Self {
+        let function = closure.borrow().function.clone();
+        let decoded_instructions = function.borrow().chunk.get_decoded_instructions();
+        let inline_caches = function.borrow().chunk.get_inline_caches();
+        let module_index = function.borrow().module_index;
+        CallFrame {
+            closure,
+            function,
+            decoded_instructions,
+            inline_caches,
+            inline_cache_index: None,
+            instruction_pointer: 0,
+            stack_start,
+            module_index,
+            handlers: vec![],
+            returns_bool: false,
+        }
+    }
+
+    /// Return instruction pointed by the instruction pointer and move the pointer to the next one
+    fn read_operation_code(&mut self) -> Result<OperationCode, VirtualMachineError> {
+        let instruction = self
+            .decoded_instructions
+            .get(self.instruction_pointer)
+            .copied()
+            .ok_or(VirtualMachineError::InvalidInstructionFormat(
+                OperationCodeConversionError::InvalidFormat,
+            ))?;
+        self.instruction_pointer += 1;
+        self.inline_cache_index = instruction.inline_cache_index;
+        Ok(instruction.operation_code)
+    }
+
+    /// Inline cache of the last read instruction
+    fn get_inline_cache(&self) -> &RefCell<InlineCache> {
+        let index = self
+            .inline_cache_index
+            .expect("Only instructions that look up properties use inline cache");
+        &self.inline_caches[index as usize]
+    }
+
+    fn read_constant(&self, index: u32) -> Value {
+        self.function.borrow().chunk.read_constant(index)
+    }
+
+    /// Move the frame to another place of the stack, exception handlers are moved along with it
+    fn move_to_stack_start(&mut self, stack_start: usize) {
+        for handler in self.handlers.iter_mut() {
+            handler.stack_height = handler.stack_height - self.stack_start + stack_start;
+        }
+        self.stack_start = stack_start;
+    }
+}
+
+/// Coroutine that was resumed and hasn't yielded nor finished yet, its frame and stack segment are on top of the ones
+/// of the code that resumed it
+struct RunningCoroutine {
+    coroutine: Rc<RefCell<CoroutineObject>>,
+    /// Number of frames below the one that resumed the coroutine, when it's the current number of frames after
+    /// returning, the coroutine is finished
+    frames_count: usize,
 }
 
 pub struct VirtualMachine {
@@ -51,14 +256,37 @@ pub struct VirtualMachine {
     frames: Vec<CallFrame>,
     /// Internal stack for holding literals
     stack: Vec<Value>,
-    /// Collection of intern strings
+    /// Collection of intern strings, strings that aren't used anymore are removed from it during garbage collection
     strings: Table,
-    /// Collection of global variables
-    globals: Table,
+    /// Every module that was ever loaded (with its global variables), the first one is the main script
+    modules: Vec<Module>,
+    /// Loaded modules by canonical paths of their files
+    imported_modules: HashMap<PathBuf, Rc<RefCell<ModuleObject>>>,
+    /// Stack of modules that are being executed, the most recently imported one is the last
+    loading_modules: Vec<LoadingModule>,
+    /// Directories in which modules are looked for, if they can't be found relative to the importing file
+    module_search_paths: Vec<PathBuf>,
+    /// Global variables defined by the virtual machine itself (natives and prelude), available in every module
+    builtins: Vec<(Rc<RefCell<StringObject>>, Value)>,
     /// Collection of all upvalues that points to variables that are still on the stack
     open_upvalues: BTreeSet<UpvalueObjectBTreeWrapper>,
     /// Special string to lookup initializer method in class
     init_string: Rc<RefCell<StringObject>>,
+    /// Special strings to lookup methods overloading operators, indexed by `OverloadedOperator`
+    operator_method_names: Vec<Rc<RefCell<StringObject>>>,
+    /// Collector responsible for reclaiming objects that are part of reference cycles
+    garbage_collector: GarbageCollector,
+    /// Maximum number of nested function calls (including the implicit main one)
+    max_call_depth: usize,
+    /// Class of errors reported by the virtual machine, kept separately from the global, which can be reassigned
+    error_class: Rc<RefCell<ClassObject>>,
+    /// Value that is currently being thrown, it's set before returning the error that starts unwinding the stack
+    thrown_value: Option<Value>,
+    /// For every call whose result is awaited by the instruction that made it - number of frames below the caller.
+    /// Returning to the caller finishes execution of the nested instructions instead of continuing the caller.
+    nested_calls: Vec<usize>,
+    /// Stack of coroutines that are being executed, the most recently resumed one is the last
+    running_coroutines: Vec<RunningCoroutine>,
 }
 
 struct BinaryOperationArguments {
@@ -68,41 +296,110 @@ struct BinaryOperationArguments {
 
 impl VirtualMachine {
     const INITIAL_STACK_SIZE: usize = 8;
+    const DEFAULT_MAX_CALL_DEPTH: usize = 10000;
+    /// Every awaited call executes instructions recursively, so their depth is limited by the native stack size
+    const MAX_NESTED_CALLS_DEPTH: usize = 200;
 
     pub fn new() -> Self {
         let mut strings = Table::new();
-        let init_string = Value::new_string_object(INITIALIZER_NAME, &mut strings);
+        let mut garbage_collector = GarbageCollector::new();
+        let init_string =
+            Value::new_string_object(INITIALIZER_NAME, &mut strings, &mut garbage_collector);
+        let operator_method_names = OverloadedOperator::ALL
+            .iter()
+            .map(|operator| {
+                Value::new_string_object(
+                    operator.get_method_name(),
+                    &mut strings,
+                    &mut garbage_collector,
+                )
+                .get_string_object()
+                .unwrap()
+                .clone()
+            })
+            .collect();
 
         let mut vm = VirtualMachine {
             frames: Vec::with_capacity(Self::INITIAL_STACK_SIZE),
             stack: Vec::with_capacity(Self::INITIAL_STACK_SIZE),
             strings,
-            globals: Table::new(),
+            modules: vec![Module::new(None, PathBuf::new())],
+            imported_modules: HashMap::new(),
+            loading_modules: vec![],
+            module_search_paths: vec![],
+            builtins: vec![],
             open_upvalues: BTreeSet::new(),
             init_string: init_string.get_string_object().unwrap().clone(),
+            operator_method_names,
+            garbage_collector,
+            max_call_depth: Self::DEFAULT_MAX_CALL_DEPTH,
+            // Replaced by the class defined in the prelude
+            error_class: ClassObject::new_rc(ERROR_CLASS_NAME),
+            thrown_value: None,
+            nested_calls: vec![],
+            running_coroutines: vec![],
         };
 
         vm.define_native_function("clock", native_functions::clock_native);
+        vm.define_prelude();
+        vm.save_builtins();
 
         vm
     }
 
+    /// Set path of the interpreted script file, so that its imports are resolved relative to its directory
+    pub fn set_script_path(&mut self, path: &Path) {
+        self.modules[MAIN_MODULE_INDEX].directory =
+            path.parent().map(Path::to_path_buf).unwrap_or_default();
+    }
+
+    /// Add directory in which modules are looked for, if they can't be found relative to the importing file
+    pub fn add_module_search_path(&mut self, path: PathBuf) {
+        self.module_search_paths.push(path);
+    }
+
+    /// Limit number of bytes the program can allocate, exceeding it results in runtime error
+    pub fn set_heap_limit(&mut self, heap_limit: Option<usize>) {
+        self.garbage_collector.set_heap_limit(heap_limit);
+    }
+
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.max_call_depth = max_call_depth;
+    }
+
     pub fn reset(&mut self) {
         self.reset_stack();
     }
 
     fn reset_stack(&mut self) {
         self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+        self.thrown_value = None;
+        self.loading_modules.clear();
+        // Frames of running coroutines were removed, so they can't be continued
+        for running in self.running_coroutines.drain(..) {
+            running.coroutine.borrow_mut().state = CoroutineState::Finished;
+        }
     }
 
     pub fn interpret(&mut self, source: &str) -> InterpretResult {
-        let mut compiler = Compiler::new(source, FunctionType::Script);
-        let compile_result = compiler.compile(&mut self.strings);
+        let mut compiler = Compiler::new(source, FunctionType::Script, MAIN_MODULE_INDEX, None);
+        let main_module = &mut self.modules[MAIN_MODULE_INDEX];
+        let compile_result = compiler.compile(
+            &mut self.strings,
+            &mut self.garbage_collector,
+            &mut main_module.global_slots,
+        );
+        // New global variables could be introduced, even if compilation failed
+        main_module.resize_globals();
         match compile_result {
             Ok(function) => {
+                self.track_function(&function);
                 // Pushing on the stack for GC reasons
                 self.stack_push(Value::from(function.clone()));
                 let main_closure = ClosureObject::new_rc(function);
+                self.garbage_collector.track(&main_closure);
                 self.stack_pop().unwrap();
                 self.stack_push(Value::from(main_closure.clone()));
                 // Calling our implicit main which wraps the whole program
@@ -120,61 +417,117 @@ impl VirtualMachine {
     fn run(&mut self) -> Result<InterpretResult, VirtualMachineError> {
         let mut frame = self.frames.pop().expect("Shouldn't be empty.");
         loop {
+            let error = match self.execute_instructions(&mut frame) {
+                Ok(result) => return Ok(result),
+                Err(error) => error,
+            };
+            // Only thrown values (including reported runtime errors) can be caught, other errors mean that
+            // the bytecode is invalid
+            let Some(thrown_value) = self.thrown_value.take() else {
+                self.reset();
+                return Err(error);
+            };
+            if !self.catch_thrown_value(thrown_value, &mut frame) {
+                return Err(error);
+            }
+        }
+    }
+
+    fn execute_instructions(
+        &mut self,
+        frame: &mut CallFrame,
+    ) -> Result<InterpretResult, VirtualMachineError> {
+        loop {
+            // It's the only place where we collect garbage, as here no object is borrowed and every
+            // value that is still in use is reachable from the roots
+            if self.garbage_collector.should_collect() {
+                self.collect_garbage(frame);
+                // Limit is checked only after the collection, so that memory that can be reclaimed doesn't count
+                if self.garbage_collector.is_heap_limit_exceeded() {
+                    self.runtime_error_message("Out of memory", frame);
+                    return Err(VirtualMachineError::OutOfMemory);
+                }
+            }
+
             Logger::show_stack_content(&self.stack);
-            Logger::disassemble_instruction(
-                &frame.closure.borrow().function.borrow().chunk,
-                frame.instruction_pointer,
-            )
-            .unwrap();
-
-            let instruction = frame
-                .closure
-                .borrow_mut()
-                .function
-                .borrow_mut()
-                .chunk
-                .read_operation_code(frame.instruction_pointer)
-                .map_err(VirtualMachineError::InvalidInstructionFormat)?;
-            frame.instruction_pointer += OperationCode::get_instruction_bytes_length(&instruction);
+            #[cfg(feature = "log_trace_execution")]
+            {
+                let function = frame.function.borrow();
+                Logger::disassemble_instruction(
+                    &function.chunk,
+                    function
+                        .chunk
+                        .get_instruction_offset(frame.instruction_pointer),
+                )
+                .unwrap();
+            }
+
+            let instruction = frame.read_operation_code()?;
             match instruction {
                 OperationCode::Return => {
-                    let result = self.stack_pop().expect(
+                    let mut result = self.stack_pop().expect(
                         "When returning from function there should be result value on the stack",
                     );
+                    if frame.returns_bool {
+                        result = Value::new_bool(!result.is_falsey());
+                    }
                     // Close every upvalue owned by returning function
-                    self.close_upvalue(frame.stack_start as usize);
+                    self.close_upvalue(frame.stack_start);
                     // We executed all the frames (including the "implicit" main one) - it's time to finish
                     if self.frames.is_empty() {
                         self.stack_pop().expect("When finish program there should be the global script on the stack that must be removed");
                         return Ok(InterpretResult::Ok);
                     }
                     // Remove function itself, function arguments and function local variables from the stack (so we want to remove everything that starts in the stack_start of frame)
-                    self.stack.truncate(frame.stack_start as usize);
-                    frame = self.frames.pop().expect("Shouldn't be empty");
-                    // Push result back on stack to make it available for outter function
-                    self.stack_push(result);
+                    self.stack.truncate(frame.stack_start);
+                    *frame = self.frames.pop().expect("Shouldn't be empty");
+                    let is_module_loaded = self
+                        .loading_modules
+                        .last()
+                        .is_some_and(|module| module.frames_count == self.frames.len());
+                    if is_module_loaded {
+                        // Script of the imported module finished, so the import results in the module itself
+                        let module = self.loading_modules.pop().unwrap();
+                        self.stack_push(Value::from(module.object.clone()));
+                        self.imported_modules.insert(module.path, module.object);
+                    } else {
+                        // Push result back on stack to make it available for outter function
+                        self.stack_push(result);
+                    }
+                    let is_coroutine_finished = self
+                        .running_coroutines
+                        .last()
+                        .is_some_and(|running| running.frames_count == self.frames.len());
+                    if is_coroutine_finished {
+                        // Generator function returned, so its result is the result of the last `resume`
+                        let running = self.running_coroutines.pop().unwrap();
+                        running.coroutine.borrow_mut().state = CoroutineState::Finished;
+                    }
+                    if self.nested_calls.last() == Some(&self.frames.len()) {
+                        return Ok(InterpretResult::Ok);
+                    }
                 }
                 OperationCode::Constant(constant_index) => {
-                    let value = frame
-                        .closure
-                        .borrow_mut()
-                        .function
-                        .borrow_mut()
-                        .chunk
-                        .read_constant(constant_index);
+                    let value = frame.read_constant(constant_index);
                     self.stack_push(value);
                 }
                 OperationCode::Negate => {
+                    if self.call_operator_method(OverloadedOperator::Negate, 0, frame)? {
+                        continue;
+                    }
                     let value = self.stack_pop()?;
                     match Value::get_number(&value) {
                         Ok(num_value) => self.stack_push(Value::new_number(-num_value)),
                         Err(_) => {
-                            self.runtime_error_message("Operand must be a number", &frame);
+                            self.runtime_error_message("Operand must be a number", frame);
                             return Err(VirtualMachineError::InvalidVariableType);
                         }
                     }
                 }
                 OperationCode::Add => {
+                    if self.call_operator_method(OverloadedOperator::Add, 1, frame)? {
+                        continue;
+                    }
                     let args = self.read_binary_operation_arguments()?;
 
                     match args.lhs.get_type() {
@@ -183,7 +536,7 @@ impl VirtualMachine {
                             Err(VirtualMachineError::InvalidVariableType) => {
                                 self.runtime_error_message(
                                     "Both operands must be numbers or strings",
-                                    &frame,
+                                    frame,
                                 );
                                 return Err(VirtualMachineError::InvalidVariableType);
                             }
@@ -194,7 +547,7 @@ impl VirtualMachine {
                             Err(VirtualMachineError::InvalidVariableType) => {
                                 self.runtime_error_message(
                                     "Both operands must be numbers or strings",
-                                    &frame,
+                                    frame,
                                 );
                                 return Err(VirtualMachineError::InvalidVariableType);
                             }
@@ -203,49 +556,114 @@ impl VirtualMachine {
                         _ => {
                             self.runtime_error_message(
                                 "Both operand must be numbers or strings",
-                                &frame,
+                                frame,
                             );
                             return Err(VirtualMachineError::InvalidVariableType);
                         }
                     }
                 }
                 OperationCode::Substract => {
+                    if self.call_operator_method(OverloadedOperator::Substract, 1, frame)? {
+                        continue;
+                    }
                     let args = self.read_binary_operation_arguments()?;
                     match self.substract_numbers(&args.lhs, &args.rhs) {
                         Ok(value) => self.stack_push(value),
                         Err(VirtualMachineError::InvalidVariableType) => {
-                            self.runtime_error_message("Both operands must be numbers", &frame);
+                            self.runtime_error_message("Both operands must be numbers", frame);
                             return Err(VirtualMachineError::InvalidVariableType);
                         }
                         Err(_) => panic!("Shouldn't raise any other type of error"),
                     }
                 }
                 OperationCode::Multiply => {
+                    if self.call_operator_method(OverloadedOperator::Multiply, 1, frame)? {
+                        continue;
+                    }
                     let args = self.read_binary_operation_arguments()?;
                     match self.multiply_numbers(&args.lhs, &args.rhs) {
                         Ok(value) => self.stack_push(value),
                         Err(VirtualMachineError::InvalidVariableType) => {
-                            self.runtime_error_message("Both operands must be numbers", &frame);
+                            self.runtime_error_message("Both operands must be numbers", frame);
                             return Err(VirtualMachineError::InvalidVariableType);
                         }
                         Err(_) => panic!("Shouldn't raise any other type of error"),
                     }
                 }
                 OperationCode::Divide => {
+                    if self.call_operator_method(OverloadedOperator::Divide, 1, frame)? {
+                        continue;
+                    }
                     let args = self.read_binary_operation_arguments()?;
                     match self.divide_numbers(&args.lhs, &args.rhs) {
                         Ok(value) => self.stack_push(value),
                         Err(VirtualMachineError::InvalidVariableType) => {
-                            self.runtime_error_message("Both operands must be numbers", &frame);
+                            self.runtime_error_message("Both operands must be numbers", frame);
                             return Err(VirtualMachineError::InvalidVariableType);
                         }
                         Err(VirtualMachineError::DivideByZero) => {
-                            self.runtime_error_message("You cannot divide by 0", &frame);
+                            self.runtime_error_message("You cannot divide by 0", frame);
                             return Err(VirtualMachineError::DivideByZero);
                         }
                         Err(_) => panic!("Shouldn't raise any other type of error"),
                     }
                 }
+                OperationCode::Modulo => {
+                    if self.call_operator_method(OverloadedOperator::Modulo, 1, frame)? {
+                        continue;
+                    }
+                    self.apply_number_operation(Self::modulo_numbers, frame)?
+                }
+                OperationCode::Power => {
+                    self.apply_number_operation(|lhs, rhs| Ok(lhs.powf(rhs)), frame)?
+                }
+                OperationCode::IntegerDivide => {
+                    self.apply_number_operation(Self::integer_divide_numbers, frame)?
+                }
+                // Bitwise operations work on numbers converted to 64 bit integers (fractional part is dropped)
+                OperationCode::BitwiseAnd => self.apply_number_operation(
+                    |lhs, rhs| {
+                        let (lhs, rhs) = Self::get_integer_operands(lhs, rhs)?;
+                        Ok((lhs & rhs) as f64)
+                    },
+                    frame,
+                )?,
+                OperationCode::BitwiseOr => self.apply_number_operation(
+                    |lhs, rhs| {
+                        let (lhs, rhs) = Self::get_integer_operands(lhs, rhs)?;
+                        Ok((lhs | rhs) as f64)
+                    },
+                    frame,
+                )?,
+                OperationCode::BitwiseXor => self.apply_number_operation(
+                    |lhs, rhs| {
+                        let (lhs, rhs) = Self::get_integer_operands(lhs, rhs)?;
+                        Ok((lhs ^ rhs) as f64)
+                    },
+                    frame,
+                )?,
+                OperationCode::BitwiseNot => {
+                    let value = self.stack_pop()?;
+                    match Value::get_number(&value) {
+                        Ok(num_value) if num_value.fract() == 0.0 => {
+                            self.stack_push(Value::new_number(!(num_value as i64) as f64))
+                        }
+                        Ok(_) => {
+                            self.runtime_error_message("Operand must be an integer", frame);
+                            return Err(VirtualMachineError::NonIntegerOperand);
+                        }
+                        Err(_) => {
+                            self.runtime_error_message("Operand must be a number", frame);
+                            return Err(VirtualMachineError::InvalidVariableType);
+                        }
+                    }
+                }
+                OperationCode::ShiftLeft => {
+                    self.apply_number_operation(Self::shift_left_numbers, frame)?
+                }
+                OperationCode::ShiftRight => {
+                    self.apply_number_operation(Self::shift_right_numbers, frame)?
+                }
                 OperationCode::Nil => self.stack_push(Value::new_nil()),
                 OperationCode::True => self.stack_push(Value::new_bool(true)),
                 OperationCode::False => self.stack_push(Value::new_bool(false)),
@@ -254,174 +672,186 @@ impl VirtualMachine {
                     self.stack_push(Value::new_bool(value.is_falsey()));
                 }
                 OperationCode::Equal => {
+                    // Comparison with nil is always done by identity, so `__eq` is never called with nil
+                    let is_nil_compared =
+                        self.stack_peek(0)?.is_nil() || self.stack_peek(1)?.is_nil();
+                    if !is_nil_compared
+                        && self.call_operator_method(OverloadedOperator::Equal, 1, frame)?
+                    {
+                        continue;
+                    }
                     let args = self.read_binary_operation_arguments()?;
                     self.stack_push(Value::new_bool(Value::are_values_equal(
                         &args.lhs, &args.rhs,
                     )))
                 }
                 OperationCode::Greater => {
+                    if self.call_operator_method(OverloadedOperator::Greater, 1, frame)? {
+                        continue;
+                    }
                     let args = self.read_binary_operation_arguments()?;
                     match self.compare_greater(&args.lhs, &args.rhs) {
                         Ok(value) => self.stack_push(Value::new_bool(value)),
                         Err(VirtualMachineError::InvalidVariableType) => {
-                            self.runtime_error_message("Both operands must be numbers", &frame);
+                            self.runtime_error_message("Both operands must be numbers", frame);
                             return Err(VirtualMachineError::InvalidVariableType);
                         }
                         Err(_) => panic!("Shouldn't raise any other type of error"),
                     }
                 }
                 OperationCode::Less => {
+                    if self.call_operator_method(OverloadedOperator::Less, 1, frame)? {
+                        continue;
+                    }
                     let args = self.read_binary_operation_arguments()?;
                     match self.compare_less(&args.lhs, &args.rhs) {
                         Ok(value) => self.stack_push(Value::new_bool(value)),
                         Err(VirtualMachineError::InvalidVariableType) => {
-                            self.runtime_error_message("Both operands must be numbers", &frame);
+                            self.runtime_error_message("Both operands must be numbers", frame);
                             return Err(VirtualMachineError::InvalidVariableType);
                         }
                         Err(_) => panic!("Shouldn't raise any other type of error"),
                     }
                 }
                 OperationCode::Print => {
-                    let value = self.stack_pop()?;
-                    println!("{}", value);
+                    // Value is removed only after the conversion, so that it's not collected in the meantime
+                    let value = self.stack_peek(0)?.clone();
+                    let string = self.stringify_value(&value, frame)?;
+                    self.stack_pop()?;
+                    println!("{}", string);
                 }
                 OperationCode::PopStack => {
                     self.stack_pop()?;
                 }
-                OperationCode::DefineGlobal(global_var_index) => {
-                    let name = frame
-                        .closure
-                        .borrow()
-                        .function
-                        .borrow()
-                        .chunk
-                        .read_constant(global_var_index);
-                    match name.get_string_object() {
-                        // Popping only after the value is added to globals is by design.
-                        // It's done this way to ensue the VM can still find the value
-                        // even if garbage collection is triggered right in the middle of adding to the `Table`
-                        Ok(var_name) => {
-                            self.globals
-                                .insert(var_name.clone(), self.stack_peek(0)?.clone());
-                            self.stack_pop()?;
-                        }
-                        Err(_) => return Err(VirtualMachineError::InvalidVariableNameType),
+                OperationCode::DefineGlobal(global_slot)
+                | OperationCode::DefineConstantGlobal(global_slot) => {
+                    let module = &self.modules[frame.module_index];
+                    // Constant can't be replaced by redefining it (e.g. in the next REPL line)
+                    if module.constant_globals[global_slot as usize] {
+                        let message = format!(
+                            "Can't redefine constant '{}'.",
+                            module
+                                .global_slots
+                                .get_name(global_slot)
+                                .borrow()
+                                .get_value()
+                        );
+                        self.runtime_error_message(&message, frame);
+                        return Err(VirtualMachineError::ConstantAssignment);
                     }
+                    // Popping only after the value is added to globals is by design.
+                    // It's done this way to ensue the VM can still find the value
+                    // even if garbage collection is triggered right in the middle of adding it
+                    let value = self.stack_peek(0)?.clone();
+                    let module = &mut self.modules[frame.module_index];
+                    module.globals[global_slot as usize] = Some(value);
+                    module.constant_globals[global_slot as usize] =
+                        matches!(instruction, OperationCode::DefineConstantGlobal(_));
+                    self.stack_pop()?;
                 }
-                OperationCode::GetGlobal(global_var_index) => {
-                    let name = frame
-                        .closure
-                        .borrow()
-                        .function
-                        .borrow()
-                        .chunk
-                        .read_constant(global_var_index);
-                    let name_string_object = name
-                        .get_string_object()
-                        .map_err(|_| VirtualMachineError::InvalidVariableNameType)?;
-                    match self.globals.get(name_string_object) {
-                        Ok(value) => self.stack_push(value.clone()),
-                        Err(_) => {
-                            self.runtime_error_message(
-                                &format!(
-                                    "Undefined variable '{}'",
-                                    name_string_object.borrow().get_value()
-                                ),
-                                &frame,
+                OperationCode::GetGlobal(global_slot) => {
+                    let module = &self.modules[frame.module_index];
+                    match &module.globals[global_slot as usize] {
+                        Some(value) => self.stack_push(value.clone()),
+                        None => {
+                            let message = format!(
+                                "Undefined variable '{}'",
+                                module
+                                    .global_slots
+                                    .get_name(global_slot)
+                                    .borrow()
+                                    .get_value()
                             );
+                            self.runtime_error_message(&message, frame);
                             return Err(VirtualMachineError::UndefinedVariable);
                         }
                     }
                 }
-                OperationCode::SetGlobal(global_var_index) => {
-                    let name = frame
-                        .closure
-                        .borrow()
-                        .function
-                        .borrow()
-                        .chunk
-                        .read_constant(global_var_index);
-                    let name_string_object = name
-                        .get_string_object()
-                        .map_err(|_| VirtualMachineError::InvalidVariableNameType)?;
-                    match self
-                        .globals
-                        .insert(name_string_object.clone(), self.stack_peek(0)?.clone())
-                    {
-                        InsertResult::Added => {
-                            // The variable hasn't been defined yet - it's runtime error, as there is no implicit variable declaration in lox
-                            // We delete this, as we don't want to keep this "zombie" variable to provide better REPL experience
-                            self.globals.remove(name_string_object).expect("It should always be possible to remove element that was just added");
-                            self.runtime_error_message(
-                                &format!(
-                                    "Undefined variable '{}'.",
-                                    name_string_object.borrow().get_value()
-                                ),
-                                &frame,
-                            );
-                            return Err(VirtualMachineError::UndefinedVariable);
-                        }
-                        InsertResult::Replaced => {}
+                OperationCode::SetGlobal(global_slot) => {
+                    let module = &self.modules[frame.module_index];
+                    // There is no implicit variable declaration in lox, so only defined variable can be assigned
+                    if module.globals[global_slot as usize].is_none() {
+                        let message = format!(
+                            "Undefined variable '{}'.",
+                            module
+                                .global_slots
+                                .get_name(global_slot)
+                                .borrow()
+                                .get_value()
+                        );
+                        self.runtime_error_message(&message, frame);
+                        return Err(VirtualMachineError::UndefinedVariable);
                     }
+                    if module.constant_globals[global_slot as usize] {
+                        let message = format!(
+                            "Can't assign to constant '{}'.",
+                            module
+                                .global_slots
+                                .get_name(global_slot)
+                                .borrow()
+                                .get_value()
+                        );
+                        self.runtime_error_message(&message, frame);
+                        return Err(VirtualMachineError::ConstantAssignment);
+                    }
+                    let value = self.stack_peek(0)?.clone();
+                    self.modules[frame.module_index].globals[global_slot as usize] = Some(value);
                 }
                 OperationCode::GetLocal(local_var_index) => {
                     // We must push it even though it's already on the stack as other instructions
                     // read data only from the top of the stack
                     self.stack_push(
-                        self.stack[(frame.stack_start + local_var_index) as usize].clone(),
+                        self.stack[frame.stack_start + local_var_index as usize].clone(),
                     );
                 }
                 OperationCode::SetLocal(local_var_index) => {
-                    self.stack[(frame.stack_start + local_var_index) as usize] = self.stack_peek(0).expect("Index of local var in the stack should be correct as the same index is used in compiler locals").clone();
+                    self.stack[frame.stack_start + local_var_index as usize] = self.stack_peek(0).expect("Index of local var in the stack should be correct as the same index is used in compiler locals").clone();
                 }
-                OperationCode::JumpIfFalse(bytes_to_skip) => {
+                // Decoded jumps hold the index of the instruction they jump to
+                OperationCode::JumpIfFalse(target) => {
                     if self
                         .stack_peek(0)
                         .expect("Stack shouldn't be empty during conditional jump operation")
                         .is_falsey()
                     {
-                        frame.instruction_pointer += bytes_to_skip as usize;
+                        frame.instruction_pointer = target as usize;
                     }
                 }
-                OperationCode::Jump(bytes_to_skip) => {
-                    frame.instruction_pointer += bytes_to_skip as usize;
+                OperationCode::Jump(target) => {
+                    frame.instruction_pointer = target as usize;
                 }
-                OperationCode::JumpIfTrue(bytes_to_skip) => {
+                OperationCode::JumpIfTrue(target) => {
                     if !self
                         .stack_peek(0)
                         .expect("Stack shouldn't be empty during conditional jump operation")
                         .is_falsey()
                     {
-                        frame.instruction_pointer += bytes_to_skip as usize;
+                        frame.instruction_pointer = target as usize;
                     }
                 }
-                OperationCode::JumpBack(bytes_to_skip) => {
-                    frame.instruction_pointer -= bytes_to_skip as usize;
+                OperationCode::JumpBack(target) => {
+                    frame.instruction_pointer = target as usize;
                 }
                 OperationCode::Call(arguments_count) => {
                     // We know that on the stack its always like:
                     // <function> arg0 arg1 arg2 ...
                     // So peeking arguments_count always gets us the function itself from the stack
                     let callee = self.stack_peek(arguments_count as usize)?.clone();
-                    // We don't use frames with native functions, as we let rust handle them
-                    let should_swap_frames = self.should_swap_frames(&callee);
-                    self.handle_call_value(callee, arguments_count, &frame)?;
-                    if should_swap_frames {
-                        frame = self.swap_call_frames_top(frame);
+                    let frames_count = self.frames.len();
+                    self.handle_call_value(callee, arguments_count, frame)?;
+                    // We don't use frames with native functions (as we let rust handle them), classes without
+                    // initializer and generator functions (which only create the coroutine)
+                    if self.frames.len() > frames_count {
+                        self.swap_call_frames_top(frame);
                     }
                 }
                 OperationCode::Closure(function_index) => {
                     let function = frame
-                        .closure
-                        .borrow()
-                        .function
-                        .borrow()
-                        .chunk
                         .read_constant(function_index)
                         .get_function_object()
                         .expect("Closure operation should store index to function")
                         .clone();
-                    let closure = Value::new_closure_object(function);
+                    let closure = Value::new_closure_object(function, &mut self.garbage_collector);
                     // Handle closure upvalues
                     let upvalues_count = closure
                         .get_closure_object()
@@ -431,24 +861,19 @@ impl VirtualMachine {
                         .borrow()
                         .upvalues_count;
                     for _ in 0..upvalues_count {
-                        let instruction = frame
-                            .closure
-                            .borrow_mut()
-                            .function
-                            .borrow_mut()
-                            .chunk
-                            .read_operation_code(frame.instruction_pointer)
-                            .map_err(VirtualMachineError::InvalidInstructionFormat)?;
-                        frame.instruction_pointer +=
-                            OperationCode::get_instruction_bytes_length(&instruction);
+                        let instruction = frame.read_operation_code()?;
 
                         match instruction {
-                            OperationCode::LocalUpvalue(upvalue_index) => closure
-                                .get_closure_object()
-                                .unwrap()
-                                .borrow_mut()
-                                .upvalues
-                                .push(self.capture_upvalue(frame.stack_start + upvalue_index)),
+                            OperationCode::LocalUpvalue(upvalue_index) => {
+                                closure
+                                    .get_closure_object()
+                                    .unwrap()
+                                    .borrow_mut()
+                                    .upvalues
+                                    .push(self.capture_upvalue(
+                                        frame.stack_start + upvalue_index as usize,
+                                    ))
+                            }
                             OperationCode::NonLocalUpvalue(upvalue_index) => closure
                                 .get_closure_object()
                                 .unwrap()
@@ -460,6 +885,12 @@ impl VirtualMachine {
                             _ => return Err(VirtualMachineError::NotEnoughUpvaluesInClosure),
                         }
                     }
+                    let upvalues_bytes = closure
+                        .get_closure_object()
+                        .unwrap()
+                        .borrow()
+                        .get_allocated_bytes();
+                    self.garbage_collector.track_growth(upvalues_bytes);
                     self.stack_push(closure);
                 }
                 OperationCode::LocalUpvalue(_) => {
@@ -516,89 +947,243 @@ impl VirtualMachine {
                     self.stack_pop()?;
                 }
                 OperationCode::Class(class_name_index) => {
-                    let name = frame
-                        .closure
-                        .borrow()
-                        .function
-                        .borrow()
-                        .chunk
-                        .read_constant(class_name_index);
+                    let name = frame.read_constant(class_name_index);
                     let name_string_object = name
                         .get_string_object()
                         .map_err(|_| VirtualMachineError::InvalidVariableNameType)?;
-                    let new_class_object =
-                        Value::new_class_object(name_string_object.borrow().get_value());
+                    let new_class_object = Value::new_class_object(
+                        name_string_object.borrow().get_value(),
+                        &mut self.garbage_collector,
+                    );
                     self.stack_push(new_class_object);
                 }
+                OperationCode::Trait(trait_name_index) => {
+                    let name = frame.read_constant(trait_name_index);
+                    let name_string_object = name
+                        .get_string_object()
+                        .map_err(|_| VirtualMachineError::InvalidVariableNameType)?;
+                    let new_trait_object = Value::new_class_object(
+                        name_string_object.borrow().get_value(),
+                        &mut self.garbage_collector,
+                    );
+                    new_trait_object
+                        .get_class_object()
+                        .unwrap()
+                        .borrow_mut()
+                        .is_trait = true;
+                    self.stack_push(new_trait_object);
+                }
+                OperationCode::Mixin => {
+                    // Class and trait are already on the stack
+                    let mixed_trait = self.stack_pop()?;
+                    let is_trait = mixed_trait
+                        .get_class_object()
+                        .is_ok_and(|mixed_trait| mixed_trait.borrow().is_trait);
+                    if !is_trait {
+                        self.runtime_error_message("Can only mix in traits.", frame);
+                        return Err(VirtualMachineError::InvalidVariableType);
+                    }
+                    let class = self
+                        .stack_peek(0)?
+                        .get_class_object()
+                        .map_err(|_| VirtualMachineError::InvalidVariableType)?
+                        .clone();
+                    let result = class
+                        .borrow_mut()
+                        .mix_in_trait(&mixed_trait.get_class_object().unwrap());
+                    if let Err(conflict) = result {
+                        self.runtime_error_message(
+                            &format!(
+                                "Traits '{}' and '{}' both define '{}', class must define it itself.",
+                                conflict.first_trait, conflict.second_trait, conflict.member
+                            ),
+                            frame,
+                        );
+                        return Err(VirtualMachineError::TraitConflict);
+                    }
+                }
+                OperationCode::Yield => {
+                    let value = self.stack_pop()?;
+                    self.yield_coroutine(value, frame);
+                }
+                OperationCode::Resume => {
+                    let value = self.stack_pop()?;
+                    let coroutine = self.stack_pop()?;
+                    let Ok(coroutine) = coroutine.get_coroutine_object() else {
+                        self.runtime_error_message("Can only resume coroutines.", frame);
+                        return Err(VirtualMachineError::InvalidVariableType);
+                    };
+                    self.resume_coroutine(coroutine.clone(), value, frame)?;
+                }
                 OperationCode::GetProperty(property_name_index) => {
+                    if self.stack_peek(0)?.is_module_object() {
+                        let property_name = frame.read_constant(property_name_index);
+                        let property_name_string = property_name
+                            .get_string_object()
+                            .expect("Property name should only be represented as string object");
+                        let export = self.read_module_export(&property_name_string, 0, frame)?;
+                        // Remove module from stack
+                        self.stack_pop()?;
+                        self.stack_push(export);
+                        continue;
+                    }
+                    if self.stack_peek(0)?.is_class_object() {
+                        let property_name = frame.read_constant(property_name_index);
+                        let property_name_string = property_name
+                            .get_string_object()
+                            .expect("Property name should only be represented as string object");
+                        let field = self.read_static_field(&property_name_string, 0, frame)?;
+                        // Remove class from stack
+                        self.stack_pop()?;
+                        self.stack_push(field);
+                        continue;
+                    }
+                    if let Ok(coroutine) = self.stack_peek(0)?.get_coroutine_object() {
+                        let coroutine = coroutine.clone();
+                        let property_name = frame.read_constant(property_name_index);
+                        let property_name_string = property_name
+                            .get_string_object()
+                            .expect("Property name should only be represented as string object");
+                        let property =
+                            self.read_coroutine_property(&coroutine, &property_name_string, frame)?;
+                        // Remove coroutine from stack
+                        self.stack_pop()?;
+                        self.stack_push(property);
+                        continue;
+                    }
+                    let is_list = self.stack_peek(0)?.is_list_object();
+                    if is_list || self.stack_peek(0)?.is_map_object() {
+                        let property_name = frame.read_constant(property_name_index);
+                        let property_name_string = property_name
+                            .get_string_object()
+                            .expect("Property name should only be represented as string object");
+                        return Err(self.report_builtin_property_read(
+                            &property_name_string,
+                            is_list,
+                            frame,
+                        ));
+                    }
                     if !self.stack_peek(0)?.is_instance_object() {
-                        self.runtime_error_message("Only instances can have properties.", &frame);
+                        self.runtime_error_message("Only instances can have properties.", frame);
                         return Err(VirtualMachineError::PropertyOutsideInstance);
                     }
                     let instance = self.stack_peek(0)?.get_instance_object().unwrap().clone();
 
-                    let property_name = frame
-                        .closure
-                        .borrow()
-                        .function
-                        .borrow()
-                        .chunk
-                        .read_constant(property_name_index);
+                    let property_name = frame.read_constant(property_name_index);
                     let property_name_string = property_name
                         .get_string_object()
                         .expect("Field name should only be represented as string object");
 
-                    let borrowed_instance = instance.borrow();
-                    let get_field_result = borrowed_instance.fields.get(property_name_string);
+                    let inline_cache = frame.get_inline_cache();
+                    let field =
+                        Self::find_field(&instance.borrow(), &property_name_string, inline_cache);
 
                     // Field take precedence over methods, so we check for them first
-                    match get_field_result {
-                        Ok(property_value) => {
+                    match field {
+                        Some(property_value) => {
                             // Remove instance from stack
                             self.stack_pop()?;
-                            self.stack_push(property_value.clone());
+                            self.stack_push(property_value);
                         }
-                        Err(_) => {
-                            // We couldn't find field, so we check if such method exists
-                            let bind_method_result = self.find_and_bind_method(
-                                &borrowed_instance.class,
-                                property_name_string,
-                            );
-                            if bind_method_result.is_err() {
-                                self.runtime_error_message(
-                                    &format!(
-                                        "Undefined property {}.",
-                                        property_name_string.borrow().get_value()
-                                    ),
-                                    &frame,
-                                );
-                                return Err(VirtualMachineError::UndefinedProperty);
+                        None => {
+                            // We couldn't find field, so we check if such getter or method exists
+                            let class = instance.borrow().class.clone();
+                            let getter =
+                                Self::find_accessor(&class.borrow().getters, &property_name_string);
+                            if let Some(getter) = getter {
+                                // Instance is already on the stack top, where getter expects "this"
+                                let frames_count = self.frames.len();
+                                self.handle_function_call(getter, 0, Some(frame))?;
+                                if self.frames.len() > frames_count {
+                                    self.swap_call_frames_top(frame);
+                                }
+                                continue;
+                            }
+                            match Self::find_method(&class, &property_name_string, inline_cache) {
+                                Some(method) => self.bind_method(method)?,
+                                None => {
+                                    self.runtime_error_message(
+                                        &format!(
+                                            "Undefined property {}.",
+                                            property_name_string.borrow().get_value()
+                                        ),
+                                        frame,
+                                    );
+                                    return Err(VirtualMachineError::UndefinedProperty);
+                                }
                             }
                         }
                     }
                 }
                 OperationCode::SetProperty(property_name_index) => {
+                    if self.stack_peek(1)?.is_module_object() {
+                        self.runtime_error_message("Module exports are read-only.", frame);
+                        return Err(VirtualMachineError::ConstantAssignment);
+                    }
+                    if let Ok(class) = self.stack_peek(1)?.get_class_object() {
+                        let class = class.clone();
+                        let field_name = frame.read_constant(property_name_index);
+                        let field_name_string = field_name
+                            .get_string_object()
+                            .expect("Field name should only be represented as string object");
+                        let field_value = self.stack_pop()?;
+                        self.set_static_field(&class, &field_name_string, field_value.clone());
+                        // Replace class with the assigned value
+                        self.stack_pop()?;
+                        self.stack_push(field_value);
+                        continue;
+                    }
                     if !self.stack_peek(1)?.is_instance_object() {
-                        self.runtime_error_message("Only instances can have properties.", &frame);
+                        self.runtime_error_message("Only instances can have properties.", frame);
                         return Err(VirtualMachineError::PropertyOutsideInstance);
                     }
                     let instance = self.stack_peek(1)?.get_instance_object().unwrap().clone();
 
-                    let field_name = frame
-                        .closure
-                        .borrow()
-                        .function
-                        .borrow()
-                        .chunk
-                        .read_constant(property_name_index);
+                    let field_name = frame.read_constant(property_name_index);
                     let field_name_string = field_name
                         .get_string_object()
                         .expect("Field name should only be represented as string object");
 
-                    instance
-                        .borrow_mut()
-                        .fields
-                        .insert(field_name_string.clone(), self.stack_peek(0)?.clone());
+                    let class = instance.borrow().class.clone();
+                    let setter = Self::find_accessor(&class.borrow().setters, &field_name_string);
+                    if let Some(setter) = setter {
+                        // On the stack we already have [instance field_value], just like setter expects
+                        self.handle_function_call(setter, 1, Some(frame))?;
+                        self.swap_call_frames_top(frame);
+                        continue;
+                    }
+                    // Field with the same name as the getter would never be read
+                    if class.borrow().getters.get(&field_name_string).is_ok() {
+                        self.runtime_error_message(
+                            &format!(
+                                "Can't set property {} which only has a getter.",
+                                field_name_string.borrow().get_value()
+                            ),
+                            frame,
+                        );
+                        return Err(VirtualMachineError::UndefinedProperty);
+                    }
+
+                    let field_value = self.stack_peek(0)?.clone();
+                    let inline_cache = frame.get_inline_cache();
+                    let fields = &mut instance.borrow_mut().fields;
+                    // Replacing existing field at the cached index doesn't need any lookup
+                    let cached_field_index = inline_cache.borrow().get_field_index();
+                    let is_replaced = match cached_field_index {
+                        Some(index) => {
+                            fields.replace_at(index, &field_name_string, field_value.clone())
+                        }
+                        None => false,
+                    };
+                    if !is_replaced {
+                        let bytes_before = fields.get_allocated_bytes();
+                        fields.insert(field_name_string.clone(), field_value);
+                        self.garbage_collector
+                            .track_growth(fields.get_allocated_bytes() - bytes_before);
+                        inline_cache
+                            .borrow_mut()
+                            .set_field_index(fields.find_index(&field_name_string));
+                    }
 
                     // On the stack we have: [instance field_value]
                     // we want to have: [field_value];
@@ -607,67 +1192,91 @@ impl VirtualMachine {
                     self.stack_push(field_value);
                 }
                 OperationCode::Method(method_name_index) => {
-                    let method_name = frame
-                        .closure
-                        .borrow()
-                        .function
-                        .borrow()
-                        .chunk
-                        .read_constant(method_name_index);
+                    let method_name = frame.read_constant(method_name_index);
                     let method_name_string = method_name
                         .get_string_object()
                         .expect("Method name should only be represented as string object");
-                    self.define_method(method_name_string)?;
+                    self.define_method(&method_name_string)?;
+                }
+                OperationCode::Getter(property_name_index) => {
+                    let property_name = frame.read_constant(property_name_index);
+                    let property_name_string = property_name
+                        .get_string_object()
+                        .expect("Property name should only be represented as string object");
+                    self.define_property_accessor(&property_name_string, true)?;
+                }
+                OperationCode::Setter(property_name_index) => {
+                    let property_name = frame.read_constant(property_name_index);
+                    let property_name_string = property_name
+                        .get_string_object()
+                        .expect("Property name should only be represented as string object");
+                    self.define_property_accessor(&property_name_string, false)?;
+                }
+                OperationCode::StaticField(field_name_index) => {
+                    let field_name = frame.read_constant(field_name_index);
+                    let field_name_string = field_name
+                        .get_string_object()
+                        .expect("Field name should only be represented as string object");
+                    // On the stack we have: [class field_value]
+                    let field_value = self.stack_pop()?;
+                    let class = self
+                        .stack_peek(0)?
+                        .get_class_object()
+                        .map_err(|_| VirtualMachineError::HandlingMethodWithoutClass)?
+                        .clone();
+                    self.set_static_field(&class, &field_name_string, field_value);
                 }
                 OperationCode::InvokeProperty(property_name_index, arguments_count) => {
-                    let property_name = frame
-                        .closure
-                        .borrow()
-                        .function
-                        .borrow()
-                        .chunk
-                        .read_constant(property_name_index);
+                    let property_name = frame.read_constant(property_name_index);
                     let property_name_string = property_name
                         .get_string_object()
                         .expect("Property name should only be represented as string object");
-                    if let Err(e) =
-                        self.invoke_property(property_name_string, arguments_count, &frame)
-                    {
-                        self.runtime_error_message("Only instances have properties.", &frame);
-                        return Err(e);
+                    // Lists and maps don't have fields or classes, they only have built-in methods, which never need new frame
+                    let receiver = self.stack_peek(arguments_count as usize)?;
+                    if receiver.is_list_object() {
+                        self.invoke_list_method(&property_name_string, arguments_count, frame)?;
+                        continue;
+                    }
+                    if receiver.is_map_object() {
+                        self.invoke_map_method(&property_name_string, arguments_count, frame)?;
+                        continue;
+                    }
+                    let is_module = receiver.is_module_object();
+                    let is_class = receiver.is_class_object();
+                    let frames_count = self.frames.len();
+                    if is_module {
+                        self.invoke_module_export(&property_name_string, arguments_count, frame)?;
+                    } else if is_class {
+                        self.invoke_static_field(&property_name_string, arguments_count, frame)?;
+                    } else {
+                        self.invoke_property(&property_name_string, arguments_count, frame)?;
+                    }
+                    // Field holding native function or class without initializer is called without new frame
+                    if self.frames.len() > frames_count {
+                        self.swap_call_frames_top(frame);
                     }
-                    frame = self.swap_call_frames_top(frame);
                 }
                 OperationCode::Inherit => {
                     // Base class and current class are already on the stack
                     let base_class = self.stack_peek(1)?;
                     let current_class = self.stack_peek(0)?;
                     match base_class.get_class_object() {
-                        Ok(base_class) => {
+                        Ok(base_class) if !base_class.borrow().is_trait => {
                             let current_class = current_class
                                 .get_class_object()
                                 .map_err(|_| VirtualMachineError::InvalidVariableType)?;
-                            Table::insert_all_from(
-                                &base_class.borrow().methods,
-                                &mut current_class.borrow_mut().methods,
-                            );
+                            current_class.borrow_mut().inherit(&base_class);
                             // Remove current class from stack
                             self.stack_pop()?;
                         }
-                        Err(_) => {
-                            self.runtime_error_message("Base class must be a class.", &frame);
+                        _ => {
+                            self.runtime_error_message("Base class must be a class.", frame);
                             return Err(VirtualMachineError::InvalidVariableType);
                         }
                     }
                 }
                 OperationCode::GetSuper(method_name_index) => {
-                    let method_name = frame
-                        .closure
-                        .borrow()
-                        .function
-                        .borrow()
-                        .chunk
-                        .read_constant(method_name_index);
+                    let method_name = frame.read_constant(method_name_index);
                     let method_name_string = method_name
                         .get_string_object()
                         .expect("Method name should only be represented as string object");
@@ -675,16 +1284,35 @@ impl VirtualMachine {
                     let base_class = base_class_value
                         .get_class_object()
                         .map_err(|_| VirtualMachineError::InvalidVariableType)?;
-                    self.find_and_bind_method(base_class, method_name_string)?;
+                    let getter =
+                        Self::find_accessor(&base_class.borrow().getters, &method_name_string);
+                    match Self::find_method(
+                        &base_class,
+                        &method_name_string,
+                        frame.get_inline_cache(),
+                    ) {
+                        Some(method) => self.bind_method(method)?,
+                        None if getter.is_some() => {
+                            let frames_count = self.frames.len();
+                            self.handle_function_call(getter.unwrap(), 0, Some(frame))?;
+                            if self.frames.len() > frames_count {
+                                self.swap_call_frames_top(frame);
+                            }
+                        }
+                        None => {
+                            self.runtime_error_message(
+                                &format!(
+                                    "Undefined property {}.",
+                                    method_name_string.borrow().get_value()
+                                ),
+                                frame,
+                            );
+                            return Err(VirtualMachineError::UndefinedProperty);
+                        }
+                    }
                 }
                 OperationCode::InvokeSuperMethod(method_name_index, arguments_count) => {
-                    let method_name = frame
-                        .closure
-                        .borrow()
-                        .function
-                        .borrow()
-                        .chunk
-                        .read_constant(method_name_index);
+                    let method_name = frame.read_constant(method_name_index);
                     let method_name_string = method_name
                         .get_string_object()
                         .expect("Method name should only be represented as string object");
@@ -694,25 +1322,154 @@ impl VirtualMachine {
                         .get_class_object()
                         .map_err(|_| VirtualMachineError::InvalidVariableType)?;
 
+                    let frames_count = self.frames.len();
                     self.invoke_property_from_class(
-                        base_class,
-                        method_name_string,
+                        &base_class,
+                        &method_name_string,
                         arguments_count,
-                        &frame,
+                        frame,
                     )?;
 
-                    frame = self.swap_call_frames_top(frame);
+                    if self.frames.len() > frames_count {
+                        self.swap_call_frames_top(frame);
+                    }
                 }
-            }
-        }
-    }
-
-    fn read_binary_operation_arguments(
-        &mut self,
-    ) -> Result<BinaryOperationArguments, VirtualMachineError> {
-        let rhs = self.stack_pop()?;
-        let lhs = self.stack_pop()?;
-        Ok(BinaryOperationArguments { lhs, rhs })
+                OperationCode::BuildList(items_count) => {
+                    let items = self
+                        .stack
+                        .split_off(self.stack.len() - items_count as usize);
+                    let list = Value::new_list_object(items, &mut self.garbage_collector);
+                    self.stack_push(list);
+                }
+                OperationCode::GetIndex => {
+                    if self.call_operator_method(OverloadedOperator::Index, 1, frame)? {
+                        continue;
+                    }
+                    let index = self.stack_peek(0)?.clone();
+                    let indexed = self.stack_peek(1)?.clone();
+                    let item = if let Ok(list) = indexed.get_list_object() {
+                        let length = list.borrow().items.len();
+                        let index = self.read_list_index(&index, length, false, frame)?;
+                        list.borrow().items[index].clone()
+                    } else if let Ok(map) = indexed.get_map_object() {
+                        self.read_map_value(&map, &index, frame)?
+                    } else {
+                        self.runtime_error_message("Only lists and maps can be indexed.", frame);
+                        return Err(VirtualMachineError::InvalidVariableType);
+                    };
+                    // On the stack we have: [list/map index/key]
+                    // we want to have: [item]
+                    self.stack_pop()?;
+                    self.stack_pop()?;
+                    self.stack_push(item);
+                }
+                OperationCode::SetIndex => {
+                    let item = self.stack_peek(0)?.clone();
+                    let index = self.stack_peek(1)?.clone();
+                    let indexed = self.stack_peek(2)?.clone();
+                    if let Ok(list) = indexed.get_list_object() {
+                        let length = list.borrow().items.len();
+                        let index = self.read_list_index(&index, length, false, frame)?;
+                        list.borrow_mut().items[index] = item;
+                    } else if let Ok(map) = indexed.get_map_object() {
+                        self.check_map_key(&index, frame)?;
+                        let entries = &mut map.borrow_mut().entries;
+                        let bytes_before = entries.get_allocated_bytes();
+                        entries.insert(index, item);
+                        self.garbage_collector
+                            .track_growth(entries.get_allocated_bytes() - bytes_before);
+                    } else {
+                        self.runtime_error_message("Only lists and maps can be indexed.", frame);
+                        return Err(VirtualMachineError::InvalidVariableType);
+                    }
+                    // On the stack we have: [list/map index/key item]
+                    // we want to have: [item]
+                    let item = self.stack_pop()?;
+                    self.stack_pop()?;
+                    self.stack_pop()?;
+                    self.stack_push(item);
+                }
+                OperationCode::Stringify => {
+                    if !self.stack_peek(0)?.is_string_object() {
+                        let value = self.stack_peek(0)?.clone();
+                        let string = self.stringify_value(&value, frame)?;
+                        self.stack_pop()?;
+                        let string = Value::new_string_object(
+                            &string,
+                            &mut self.strings,
+                            &mut self.garbage_collector,
+                        );
+                        self.stack_push(string);
+                    }
+                }
+                OperationCode::BuildMap(entries_count) => {
+                    let keys_and_values = self
+                        .stack
+                        .split_off(self.stack.len() - 2 * entries_count as usize);
+                    let mut entries = Table::new();
+                    for key_and_value in keys_and_values.chunks_exact(2) {
+                        self.check_map_key(&key_and_value[0], frame)?;
+                        entries.insert(key_and_value[0].clone(), key_and_value[1].clone());
+                    }
+                    let map = Value::new_map_object(entries, &mut self.garbage_collector);
+                    self.stack_push(map);
+                }
+                OperationCode::PushHandler(handler_start) => {
+                    frame.handlers.push(ExceptionHandler {
+                        handler_start: handler_start as usize,
+                        stack_height: self.stack.len(),
+                    })
+                }
+                OperationCode::PopHandler => {
+                    frame
+                        .handlers
+                        .pop()
+                        .expect("Handler should be registered before it's removed");
+                }
+                OperationCode::Import(path_index) => {
+                    let path = frame.read_constant(path_index);
+                    let path = path
+                        .get_string_object()
+                        .expect("Module path should only be represented as string object");
+                    let frames_count = self.frames.len();
+                    self.import_module(path.borrow().get_value(), frame)?;
+                    // Module that wasn't imported yet is executed in a new frame
+                    if self.frames.len() > frames_count {
+                        self.swap_call_frames_top(frame);
+                    }
+                }
+                OperationCode::Throw => {
+                    let value = self.stack_pop()?;
+                    // Instance gets the stack trace of the place it's thrown from for the first time
+                    if let Ok(instance) = value.get_instance_object() {
+                        let stack_name = Value::new_string_object(
+                            ERROR_STACK_FIELD,
+                            &mut self.strings,
+                            &mut self.garbage_collector,
+                        );
+                        let has_stack_trace = instance
+                            .borrow()
+                            .fields
+                            .get(&stack_name.get_string_object().unwrap())
+                            .is_ok();
+                        if !has_stack_trace {
+                            let stack_trace = self.get_stack_trace(frame);
+                            self.set_string_field(&instance, ERROR_STACK_FIELD, &stack_trace);
+                        }
+                    }
+                    self.thrown_value = Some(value);
+                    return Err(VirtualMachineError::UncaughtException);
+                }
+            }
+        }
+    }
+
+    fn read_binary_operation_arguments(
+        &mut self,
+    ) -> Result<BinaryOperationArguments, VirtualMachineError> {
+        let rhs = self.stack_pop()?;
+        let lhs = self.stack_pop()?;
+        Ok(BinaryOperationArguments { lhs, rhs })
     }
 
     fn stack_push(&mut self, value: Value) {
@@ -731,34 +1488,200 @@ impl VirtualMachine {
         Ok(&self.stack[index])
     }
 
+    /// Throw the instance of `Error` class with provided message and stack trace of the current instruction.
+    /// It must be followed by returning the error, so that the stack is unwound.
     fn runtime_error_message(&mut self, message: &str, frame: &CallFrame) {
-        eprintln!("{}", message);
-
-        // Print current function
-        let inner_most_closure = frame.closure.borrow();
-        let inner_most_function = inner_most_closure.function.borrow();
-        let inner_most_line = inner_most_function
-            .chunk
-            .read_line(frame.instruction_pointer - 1);
+        let error = Value::new_instance_object(&self.error_class, &mut self.garbage_collector);
+        let instance = error.get_instance_object().unwrap().clone();
+        self.set_string_field(&instance, ERROR_MESSAGE_FIELD, message);
+        let stack_trace = self.get_stack_trace(frame);
+        self.set_string_field(&instance, ERROR_STACK_FIELD, &stack_trace);
+        self.thrown_value = Some(error);
+    }
+
+    fn get_stack_trace(&self, frame: &CallFrame) -> String {
+        let mut stack_trace = vec![];
+
+        // Current function
+        let inner_most_function = frame.function.borrow();
+        // Error can be raised before the first instruction of the function is executed (e.g. by the garbage collector)
+        let inner_most_chunk = &inner_most_function.chunk;
+        let inner_most_span = inner_most_chunk.read_span(
+            inner_most_chunk.get_instruction_offset(frame.instruction_pointer.saturating_sub(1)),
+        );
         let inner_most_name = inner_most_function.name.borrow();
-        eprintln!(
-            "[line {}] in {}",
-            inner_most_line,
+        stack_trace.push(format!(
+            "[{}] in {}",
+            self.modules[frame.module_index].format_line(format!(
+                "{}:{}",
+                inner_most_span.line, inner_most_span.column
+            )),
             inner_most_name.get_value()
-        );
+        ));
 
-        // Print the call stack
+        // The call stack
         for frame in self.frames.iter().rev() {
             // -1 becuase the current instruction_pointer points to the next instruction to be executed
             let last_executed_instruction = frame.instruction_pointer - 1;
-            let current_closure = frame.closure.borrow();
-            let current_function = current_closure.function.borrow();
-            let current_line = current_function.chunk.read_line(last_executed_instruction);
+            let current_function = frame.function.borrow();
+            let current_chunk = &current_function.chunk;
+            let current_span = current_chunk
+                .read_span(current_chunk.get_instruction_offset(last_executed_instruction));
             let current_name = current_function.name.borrow();
-            eprintln!("[line {}] in {}", current_line, current_name.get_value());
+            stack_trace.push(format!(
+                "[{}] in {}",
+                self.modules[frame.module_index]
+                    .format_line(format!("{}:{}", current_span.line, current_span.column)),
+                current_name.get_value()
+            ));
+        }
+
+        Self::collapse_repeated_lines(stack_trace).join("\n")
+    }
+
+    /// Deep recursion would fill the stack trace with thousands of the same lines, so consecutive repeats of the line
+    /// are replaced with the number of them
+    fn collapse_repeated_lines(lines: Vec<String>) -> Vec<String> {
+        let mut collapsed_lines: Vec<String> = vec![];
+        let mut repeats_count = 0;
+        let flush_repeats =
+            |collapsed_lines: &mut Vec<String>, repeats_count: usize| match repeats_count {
+                0 => {}
+                1 => collapsed_lines.push(collapsed_lines.last().unwrap().clone()),
+                _ => collapsed_lines.push(format!("... repeated {} more times", repeats_count)),
+            };
+        for line in lines {
+            if collapsed_lines.last() == Some(&line) {
+                repeats_count += 1;
+                continue;
+            }
+            flush_repeats(&mut collapsed_lines, repeats_count);
+            repeats_count = 0;
+            collapsed_lines.push(line);
+        }
+        flush_repeats(&mut collapsed_lines, repeats_count);
+        collapsed_lines
+    }
+
+    fn set_string_field(
+        &mut self,
+        instance: &Rc<RefCell<InstanceObject>>,
+        name: &str,
+        value: &str,
+    ) {
+        let name = Value::new_string_object(name, &mut self.strings, &mut self.garbage_collector);
+        let value = Value::new_string_object(value, &mut self.strings, &mut self.garbage_collector);
+        let fields = &mut instance.borrow_mut().fields;
+        let bytes_before = fields.get_allocated_bytes();
+        fields.insert(name.get_string_object().unwrap().clone(), value);
+        self.garbage_collector
+            .track_growth(fields.get_allocated_bytes() - bytes_before);
+    }
+
+    /// Unwind call frames until the one with exception handler is found and jump to that handler.
+    /// If no frame has a handler, the thrown value is reported and false is returned.
+    fn catch_thrown_value(&mut self, thrown_value: Value, frame: &mut CallFrame) -> bool {
+        let has_handler = !frame.handlers.is_empty()
+            || self.frames.iter().any(|frame| !frame.handlers.is_empty());
+        if !has_handler {
+            self.report_uncaught_value(&thrown_value, frame);
+            self.reset();
+            return false;
         }
 
-        self.reset();
+        loop {
+            if let Some(handler) = frame.handlers.pop() {
+                // Modules whose scripts were unwound are never loaded, so importing them again executes them again
+                while self
+                    .loading_modules
+                    .last()
+                    .is_some_and(|module| module.frames_count >= self.frames.len())
+                {
+                    self.loading_modules.pop();
+                }
+                self.finish_unwound_coroutines();
+                self.close_upvalue(handler.stack_height);
+                self.stack.truncate(handler.stack_height);
+                self.stack_push(thrown_value);
+                frame.instruction_pointer = handler.handler_start;
+                return true;
+            }
+            // Leave the function the same way as returning from it does
+            self.close_upvalue(frame.stack_start);
+            self.stack.truncate(frame.stack_start);
+            *frame = self
+                .frames
+                .pop()
+                .expect("Frame with the handler should be found");
+        }
+    }
+
+    // Errors (and other instances with message) are reported with their message and stack trace captured when they
+    // were thrown, other values don't have them, so they are reported as they are with the current stack trace
+    fn report_uncaught_value(&mut self, value: &Value, frame: &CallFrame) {
+        let message_name = Value::new_string_object(
+            ERROR_MESSAGE_FIELD,
+            &mut self.strings,
+            &mut self.garbage_collector,
+        );
+        let stack_name = Value::new_string_object(
+            ERROR_STACK_FIELD,
+            &mut self.strings,
+            &mut self.garbage_collector,
+        );
+        let (message, stack_trace) = match value.get_instance_object() {
+            Ok(instance) => {
+                let fields = &instance.borrow().fields;
+                (
+                    fields
+                        .get(&message_name.get_string_object().unwrap())
+                        .ok()
+                        .map(Value::to_string),
+                    fields
+                        .get(&stack_name.get_string_object().unwrap())
+                        .ok()
+                        .map(Value::to_string),
+                )
+            }
+            Err(_) => (None, None),
+        };
+        eprintln!(
+            "{}",
+            message.unwrap_or_else(|| format!("Uncaught exception: {}", value))
+        );
+        eprintln!(
+            "{}",
+            stack_trace.unwrap_or_else(|| self.get_stack_trace(frame))
+        );
+    }
+
+    // Roots are: values on the stack, closures of every call frame (including the one being executed, which isn't
+    // stored in `frames`), global variables, open upvalues and running coroutines. Strings (including `init_string`) don't have to be marked,
+    // as they cannot reference other objects and are freed by reference counting.
+    fn collect_garbage(&mut self, current_frame: &CallFrame) {
+        for value in self.stack.iter() {
+            self.garbage_collector.mark_value(value);
+        }
+        self.garbage_collector.mark_object(&current_frame.closure);
+        for frame in self.frames.iter() {
+            self.garbage_collector.mark_object(&frame.closure);
+        }
+        for module in self.modules.iter() {
+            for value in module.globals.iter().flatten() {
+                self.garbage_collector.mark_value(value);
+            }
+        }
+        for (_, value) in self.builtins.iter() {
+            self.garbage_collector.mark_value(value);
+        }
+        for upvalue in self.open_upvalues.iter() {
+            self.garbage_collector.mark_object(&upvalue.0);
+        }
+        for running in self.running_coroutines.iter() {
+            self.garbage_collector.mark_object(&running.coroutine);
+        }
+        self.garbage_collector.mark_object(&self.error_class);
+        self.garbage_collector.collect(&mut self.strings);
     }
 
     fn add_numbers(&mut self, lhs: &Value, rhs: &Value) -> Result<Value, VirtualMachineError> {
@@ -772,19 +1695,19 @@ impl VirtualMachine {
     }
 
     fn add_strings(&mut self, lhs: &Value, rhs: &Value) -> Result<Value, VirtualMachineError> {
-        let lhs: &StringObject = &lhs
+        let lhs = lhs
             .get_string_object()
-            .map_err(|_| VirtualMachineError::InvalidVariableType)?
-            .borrow();
-        let rhs: &StringObject = &rhs
+            .map_err(|_| VirtualMachineError::InvalidVariableType)?;
+        let rhs = rhs
             .get_string_object()
-            .map_err(|_| VirtualMachineError::InvalidVariableType)?
-            .borrow();
+            .map_err(|_| VirtualMachineError::InvalidVariableType)?;
 
         let mut content = String::new();
-        content.push_str(lhs.get_value());
-        content.push_str(rhs.get_value());
-        Ok(Value::new_string_object(&content, &mut self.strings))
+        content.push_str(lhs.borrow().get_value());
+        content.push_str(rhs.borrow().get_value());
+        let result =
+            Value::new_string_object(&content, &mut self.strings, &mut self.garbage_collector);
+        Ok(result)
     }
 
     fn substract_numbers(
@@ -826,6 +1749,76 @@ impl VirtualMachine {
         Ok(Value::new_number(lhs / rhs))
     }
 
+    /// Pop both operands, which must be numbers, and push the result of the operation on them
+    fn apply_number_operation(
+        &mut self,
+        operation: fn(f64, f64) -> Result<f64, VirtualMachineError>,
+        frame: &CallFrame,
+    ) -> Result<(), VirtualMachineError> {
+        let args = self.read_binary_operation_arguments()?;
+        let (Ok(lhs), Ok(rhs)) = (args.lhs.get_number(), args.rhs.get_number()) else {
+            self.runtime_error_message("Both operands must be numbers", frame);
+            return Err(VirtualMachineError::InvalidVariableType);
+        };
+        match operation(lhs, rhs) {
+            Ok(result) => {
+                self.stack_push(Value::new_number(result));
+                Ok(())
+            }
+            Err(VirtualMachineError::DivideByZero) => {
+                self.runtime_error_message("You cannot divide by 0", frame);
+                Err(VirtualMachineError::DivideByZero)
+            }
+            Err(VirtualMachineError::NonIntegerOperand) => {
+                self.runtime_error_message("Both operands must be integers", frame);
+                Err(VirtualMachineError::NonIntegerOperand)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // Result has the same sign as the divisor, so that it's consistent with integer division (which rounds down)
+    fn modulo_numbers(lhs: f64, rhs: f64) -> Result<f64, VirtualMachineError> {
+        if rhs == 0.0 {
+            return Err(VirtualMachineError::DivideByZero);
+        }
+        let remainder = lhs % rhs;
+        if remainder != 0.0 && (remainder < 0.0) != (rhs < 0.0) {
+            return Ok(remainder + rhs);
+        }
+        Ok(remainder)
+    }
+
+    // Integer operators don't truncate fractions (nor infinities or NaN), such operands are reported instead
+    fn get_integer_operands(lhs: f64, rhs: f64) -> Result<(i64, i64), VirtualMachineError> {
+        if lhs.fract() != 0.0 || rhs.fract() != 0.0 {
+            return Err(VirtualMachineError::NonIntegerOperand);
+        }
+        Ok((lhs as i64, rhs as i64))
+    }
+
+    fn integer_divide_numbers(lhs: f64, rhs: f64) -> Result<f64, VirtualMachineError> {
+        Self::get_integer_operands(lhs, rhs)?;
+        if rhs == 0.0 {
+            return Err(VirtualMachineError::DivideByZero);
+        }
+        Ok((lhs / rhs).floor())
+    }
+
+    // Shifting by negative number or by more than 63 bits shifts out every bit
+    fn shift_left_numbers(lhs: f64, rhs: f64) -> Result<f64, VirtualMachineError> {
+        let (lhs, rhs) = Self::get_integer_operands(lhs, rhs)?;
+        let shifted = u32::try_from(rhs).ok().and_then(|rhs| lhs.checked_shl(rhs));
+        Ok(shifted.unwrap_or(0) as f64)
+    }
+
+    fn shift_right_numbers(lhs: f64, rhs: f64) -> Result<f64, VirtualMachineError> {
+        let (lhs, rhs) = Self::get_integer_operands(lhs, rhs)?;
+        let shifted = u32::try_from(rhs).ok().and_then(|rhs| lhs.checked_shr(rhs));
+        // Right shift is arithmetic, so negative numbers end up as -1
+        Ok(shifted.unwrap_or(if lhs < 0 { -1 } else { 0 }) as f64)
+    }
+
     fn compare_greater(&self, lhs: &Value, rhs: &Value) -> Result<bool, VirtualMachineError> {
         let lhs = lhs
             .get_number()
@@ -846,30 +1839,12 @@ impl VirtualMachine {
         Ok(lhs < rhs)
     }
 
-    fn should_swap_frames(&self, callee: &Value) -> bool {
-        match callee.get_type() {
-            ValueType::NativeFunction => false,
-            ValueType::ClassObject => {
-                // We should only swap frames if we wanna call initializer
-                callee
-                    .get_class_object()
-                    .unwrap()
-                    .borrow()
-                    .methods
-                    .get(&self.init_string)
-                    .is_ok()
-            }
-            _ => true,
-        }
-    }
-
     // Logic here is that in current_frame we store currently executed frame
     // When we call new function, we want to put current frame back on stack, and return the call frame of the new function
     // We know that at the point of calling this, the frame is already on the stack (or at least should be)
-    fn swap_call_frames_top(&mut self, current_frame: CallFrame) -> CallFrame {
+    fn swap_call_frames_top(&mut self, current_frame: &mut CallFrame) {
         let top = self.frames.pop().unwrap();
-        self.frames.push(current_frame);
-        top
+        self.frames.push(std::mem::replace(current_frame, top));
     }
 
     fn handle_call_value(
@@ -894,9 +1869,13 @@ impl VirtualMachine {
                 );
                 Ok(())
             }
+            ValueType::ClassObject if callee.get_class_object().unwrap().borrow().is_trait => {
+                self.runtime_error_message("Can't instantiate trait.", frame);
+                Err(VirtualMachineError::CallOnNotCallable)
+            }
             ValueType::ClassObject => {
                 self.handle_class_initializer_call(
-                    callee.get_class_object().unwrap(),
+                    &callee.get_class_object().unwrap(),
                     arguments_count,
                     Some(frame),
                 )?;
@@ -920,7 +1899,10 @@ impl VirtualMachine {
                 self.handle_function_call(raw_closure, arguments_count, Some(frame))?;
                 Ok(())
             }
-            _ => Err(VirtualMachineError::CallOnNotCallable),
+            _ => {
+                self.runtime_error_message("Can only call functions and classes.", frame);
+                Err(VirtualMachineError::CallOnNotCallable)
+            }
         }
     }
 
@@ -941,14 +1923,24 @@ impl VirtualMachine {
             );
             return Err(VirtualMachineError::InvalidArgumentsCount);
         }
-        let call_frame = CallFrame {
+        if closure.borrow().function.borrow().is_generator {
+            self.create_coroutine(closure, arguments_count);
+            return Ok(());
+        }
+        // Currently executed frame isn't stored in `frames`, so it must be counted separately
+        if let Some(frame) = frame {
+            if self.frames.len() + 2 > self.max_call_depth {
+                self.runtime_error_message("Stack overflow", frame);
+                return Err(VirtualMachineError::StackOverflow);
+            }
+        }
+        let call_frame = CallFrame::new(
             closure,
-            instruction_pointer: 0,
             // We do it so that for frame it seems stack start at functions's position,
             // as we have: <function <arg1> <arg2> ... <argN> <STACK_TOP>
             // so from stack top we must substract (n + 1)
-            stack_start: self.stack.len() as u8 - (arguments_count + 1),
-        };
+            self.stack.len() - (arguments_count as usize + 1),
+        );
         self.frames.push(call_frame);
         Ok(())
     }
@@ -976,7 +1968,7 @@ impl VirtualMachine {
         arguments_count: u8,
         frame: Option<&CallFrame>,
     ) -> Result<(), VirtualMachineError> {
-        let new_instance = Value::new_instance_object(class);
+        let new_instance = Value::new_instance_object(class, &mut self.garbage_collector);
         let top = self.stack.len();
         self.stack[top - arguments_count as usize - 1] = new_instance;
         match class.borrow().methods.get(&self.init_string) {
@@ -1020,36 +2012,252 @@ impl VirtualMachine {
         Ok(())
     }
 
+    fn define_property_accessor(
+        &mut self,
+        name: &Rc<RefCell<StringObject>>,
+        is_getter: bool,
+    ) -> Result<(), VirtualMachineError> {
+        let accessor = self.stack_peek(0)?.clone();
+        let class = self
+            .stack_peek(1)?
+            .get_class_object()
+            .map_err(|_| VirtualMachineError::HandlingMethodWithoutClass)?
+            .clone();
+        let result = match is_getter {
+            true => class.borrow_mut().add_getter(name.clone(), accessor),
+            false => class.borrow_mut().add_setter(name.clone(), accessor),
+        };
+        result.map_err(|_| VirtualMachineError::InvalidVariableType)?;
+        // Remove closure
+        self.stack_pop()?;
+        Ok(())
+    }
+
     // It only makes sense to use this function before program starts executing
     fn define_native_function(&mut self, name: &str, native_function: NativeFunction) {
         // We are pushing and popping of the stack because of GC
-        let function_name = Value::new_string_object(name, &mut self.strings);
+        let function_name =
+            Value::new_string_object(name, &mut self.strings, &mut self.garbage_collector);
         self.stack_push(function_name);
         let function = Value::new_native_function(native_function);
         self.stack_push(function);
-        self.globals.insert(
-            self.stack_peek(1)
-                .unwrap()
-                .get_string_object()
-                .unwrap()
-                .clone(),
-            self.stack_peek(0).unwrap().clone(),
-        );
+        let function_name = self
+            .stack_peek(1)
+            .unwrap()
+            .get_string_object()
+            .unwrap()
+            .clone();
+        let function = self.stack_peek(0).unwrap().clone();
+        self.modules[MAIN_MODULE_INDEX].define_global(&function_name, function);
         // Removing temporary values
         self.stack_pop().unwrap();
         self.stack_pop().unwrap();
     }
 
-    fn capture_upvalue(&mut self, index: u8) -> Rc<RefCell<UpvalueObject>> {
-        let new_upvalue = Rc::new(RefCell::new(UpvalueObject {
-            stack_index: Some(index as usize),
-            variable: None,
-        }));
+    // It only makes sense to use this function before program starts executing
+    fn define_prelude(&mut self) {
+        if !matches!(self.interpret(PRELUDE), InterpretResult::Ok) {
+            panic!("Prelude should always be executed successfully");
+        }
+        let error_class_name = Value::new_string_object(
+            ERROR_CLASS_NAME,
+            &mut self.strings,
+            &mut self.garbage_collector,
+        );
+        let main_module = &mut self.modules[MAIN_MODULE_INDEX];
+        let global_slot = main_module
+            .global_slots
+            .resolve(&error_class_name.get_string_object().unwrap());
+        self.error_class = main_module.globals[global_slot as usize]
+            .as_ref()
+            .and_then(|error_class| {
+                error_class
+                    .get_class_object()
+                    .ok()
+                    .map(|class| class.clone())
+            })
+            .expect("Prelude should define error class");
+    }
+
+    // Everything defined before the user code is executed is also available in the imported modules.
+    // It only makes sense to use this function before program starts executing
+    fn save_builtins(&mut self) {
+        let main_module = &self.modules[MAIN_MODULE_INDEX];
+        self.builtins = main_module
+            .globals
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, value)| {
+                let name = main_module.global_slots.get_name(slot as u32).clone();
+                value.clone().map(|value| (name, value))
+            })
+            .collect();
+    }
+
+    /// Push imported module on the stack. If the module wasn't imported yet, its script is called instead and the
+    /// module is pushed once the script returns.
+    fn import_module(&mut self, path: &str, frame: &CallFrame) -> Result<(), VirtualMachineError> {
+        let Some(resolved_path) = module::resolve_module_path(
+            path,
+            &self.modules[frame.module_index].directory,
+            &self.module_search_paths,
+        ) else {
+            self.runtime_error_message(&format!("Can't find module '{}'.", path), frame);
+            return Err(VirtualMachineError::ImportFailure);
+        };
+        // The same file can be imported using different paths, but it's always the same module
+        let canonical_path = fs::canonicalize(&resolved_path).unwrap_or(resolved_path.clone());
+        let name = resolved_path.display().to_string();
+
+        if let Some(module) = self.imported_modules.get(&canonical_path) {
+            self.stack_push(Value::from(module.clone()));
+            return Ok(());
+        }
+        if let Some(cycle_start) = self
+            .loading_modules
+            .iter()
+            .position(|module| module.path == canonical_path)
+        {
+            let cycle = self.loading_modules[cycle_start..]
+                .iter()
+                .map(|module| module.object.borrow().name.borrow().get_value().to_owned())
+                .chain(iter::once(name))
+                .collect::<Vec<String>>()
+                .join(" -> ");
+            self.runtime_error_message(&format!("Import cycle detected: {}.", cycle), frame);
+            return Err(VirtualMachineError::ImportFailure);
+        }
+
+        let Ok(source) = fs::read_to_string(&resolved_path) else {
+            self.runtime_error_message(&format!("Can't read module '{}'.", name), frame);
+            return Err(VirtualMachineError::ImportFailure);
+        };
+        let module_index = self.modules.len();
+        let directory = resolved_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let mut module = Module::new(Some(name.clone()), directory);
+        for (builtin_name, builtin_value) in self.builtins.iter() {
+            module.define_global(builtin_name, builtin_value.clone());
+        }
+        self.modules.push(module);
+
+        let mut compiler = Compiler::new(&source, FunctionType::Script, module_index, Some(&name));
+        let module = &mut self.modules[module_index];
+        let compile_result = compiler.compile(
+            &mut self.strings,
+            &mut self.garbage_collector,
+            &mut module.global_slots,
+        );
+        module.resize_globals();
+        let Ok(function) = compile_result else {
+            // Nothing was compiled as a part of the module, so it can be safely removed
+            self.modules.pop();
+            self.runtime_error_message(&format!("Can't compile module '{}'.", name), frame);
+            return Err(VirtualMachineError::ImportFailure);
+        };
+
+        self.track_function(&function);
+        let closure = ClosureObject::new_rc(function);
+        self.garbage_collector.track(&closure);
+        self.stack_push(Value::from(closure.clone()));
+        let frames_count = self.frames.len();
+        self.handle_function_call(closure, 0, Some(frame))?;
+        self.loading_modules.push(LoadingModule {
+            path: canonical_path,
+            object: ModuleObject::new_rc(&name, module_index),
+            frames_count,
+        });
+        Ok(())
+    }
+
+    /// Value exported by the module that is on the stack at provided distance from the top
+    fn read_module_export(
+        &mut self,
+        name: &Rc<RefCell<StringObject>>,
+        distance: usize,
+        frame: &CallFrame,
+    ) -> Result<Value, VirtualMachineError> {
+        let module = self
+            .stack_peek(distance)?
+            .get_module_object()
+            .unwrap()
+            .clone();
+        let module = module.borrow();
+        match self.modules[module.module_index].get_export(name) {
+            Some(value) => Ok(value),
+            None => {
+                self.runtime_error_message(
+                    &format!(
+                        "Undefined export '{}' in module '{}'.",
+                        name.borrow().get_value(),
+                        module.name.borrow().get_value()
+                    ),
+                    frame,
+                );
+                Err(VirtualMachineError::UndefinedProperty)
+            }
+        }
+    }
+
+    /// Static field of the class that is on the stack at provided distance from the top
+    fn read_static_field(
+        &mut self,
+        name: &Rc<RefCell<StringObject>>,
+        distance: usize,
+        frame: &CallFrame,
+    ) -> Result<Value, VirtualMachineError> {
+        let class = self
+            .stack_peek(distance)?
+            .get_class_object()
+            .unwrap()
+            .clone();
+        let field = class.borrow().get_static_field(name);
+        match field {
+            Some(value) => Ok(value),
+            None => {
+                self.runtime_error_message(
+                    &format!("Undefined property {}.", name.borrow().get_value()),
+                    frame,
+                );
+                Err(VirtualMachineError::UndefinedProperty)
+            }
+        }
+    }
+
+    fn set_static_field(
+        &mut self,
+        class: &Rc<RefCell<ClassObject>>,
+        name: &Rc<RefCell<StringObject>>,
+        value: Value,
+    ) {
+        let static_fields = &mut class.borrow_mut().static_fields;
+        let bytes_before = static_fields.get_allocated_bytes();
+        static_fields.insert(name.clone(), value);
+        self.garbage_collector
+            .track_growth(static_fields.get_allocated_bytes() - bytes_before);
+    }
+
+    // Functions are created by the compiler, which doesn't have access to the garbage collector,
+    // so the whole tree of functions is tracked after the compilation is finished
+    fn track_function(&mut self, function: &Rc<RefCell<FunctionObject>>) {
+        self.garbage_collector.track(function);
+        for constant in function.borrow().chunk.iter_constants() {
+            if let Ok(nested_function) = constant.get_function_object() {
+                self.track_function(&nested_function);
+            }
+        }
+    }
+
+    fn capture_upvalue(&mut self, index: usize) -> Rc<RefCell<UpvalueObject>> {
+        let new_upvalue = UpvalueObject::new_rc(index);
         let wrapper = UpvalueObjectBTreeWrapper(new_upvalue.clone());
         match self.open_upvalues.get(&wrapper) {
             Some(already_exisiting) => already_exisiting.0.clone(),
             None => {
                 self.open_upvalues.insert(wrapper);
+                self.garbage_collector.track(&new_upvalue);
                 new_upvalue
             }
         }
@@ -1077,27 +2285,403 @@ impl VirtualMachine {
         }
     }
 
-    // If it find method with such name in the class it put it onto stack
-    fn find_and_bind_method(
-        &mut self,
+    /// Look up the field in the instance, using the field index remembered in the inline cache as a hint
+    fn find_field(
+        instance: &InstanceObject,
+        name: &Rc<RefCell<StringObject>>,
+        inline_cache: &RefCell<InlineCache>,
+    ) -> Option<Value> {
+        let cached_field_index = inline_cache.borrow().get_field_index();
+        if let Some(field) =
+            cached_field_index.and_then(|index| instance.fields.get_at(index, name))
+        {
+            return Some(field.clone());
+        }
+        let index = instance.fields.find_index(name)?;
+        inline_cache.borrow_mut().set_field_index(Some(index));
+        instance.fields.get_at(index, name).cloned()
+    }
+
+    /// Look up the method in the class, skipping the lookup if the method is already in the inline cache
+    fn find_method(
         class: &Rc<RefCell<ClassObject>>,
-        method_name: &Rc<RefCell<StringObject>>,
+        name: &Rc<RefCell<StringObject>>,
+        inline_cache: &RefCell<InlineCache>,
+    ) -> Option<Rc<RefCell<ClosureObject>>> {
+        let cached_method = inline_cache.borrow().get_method(class);
+        if cached_method.is_some() {
+            return cached_method;
+        }
+        let method = class
+            .borrow()
+            .methods
+            .get(name)
+            .ok()?
+            .get_closure_object()
+            .ok()?
+            .clone();
+        inline_cache.borrow_mut().set_method(class, &method);
+        Some(method)
+    }
+
+    // Replace the instance on the top of the stack with the method bound to it
+    fn bind_method(
+        &mut self,
+        method: Rc<RefCell<ClosureObject>>,
+    ) -> Result<(), VirtualMachineError> {
+        let instance = self.stack_peek(0)?.get_instance_object().unwrap().clone();
+        let bound_method =
+            Value::new_bound_method_object(instance, method, &mut self.garbage_collector);
+        self.stack_pop()?;
+        self.stack_push(bound_method);
+        Ok(())
+    }
+
+    /// Method overloading the operator, if the value is an instance whose class defines it
+    fn find_operator_method(
+        &self,
+        value: &Value,
+        operator: OverloadedOperator,
+    ) -> Option<Rc<RefCell<ClosureObject>>> {
+        let class = value.get_instance_object().ok()?.borrow().class.clone();
+        let class = class.borrow();
+        let method = class
+            .methods
+            .get(&self.operator_method_names[operator as usize])
+            .ok()?;
+        Some(method.get_closure_object().ok()?.clone())
+    }
+
+    /// Call the method overloading the operator if the operand at the provided distance from the stack top defines it.
+    /// Operands are already placed on the stack the same way as receiver and arguments of the method call.
+    /// If the left operand of the binary operator doesn't overload it, the reflected operator of the right operand is tried.
+    /// Returns false if the operator isn't overloaded, so it should be applied as usual.
+    fn call_operator_method(
+        &mut self,
+        operator: OverloadedOperator,
+        arguments_count: u8,
+        frame: &mut CallFrame,
+    ) -> Result<bool, VirtualMachineError> {
+        let receiver = self.stack_peek(arguments_count as usize)?;
+        let method = match self.find_operator_method(receiver, operator) {
+            Some(method) => method,
+            None => {
+                let reflected = match operator.get_reflected() {
+                    Some(reflected) if arguments_count == 1 => reflected,
+                    _ => return Ok(false),
+                };
+                let Some(method) = self.find_operator_method(self.stack_peek(0)?, reflected) else {
+                    return Ok(false);
+                };
+                // Right operand becomes the receiver and the left one the argument
+                let stack_top = self.stack.len() - 1;
+                self.stack.swap(stack_top, stack_top - 1);
+                method
+            }
+        };
+        let frames_count = self.frames.len();
+        self.handle_function_call(method, arguments_count, Some(frame))?;
+        if self.frames.len() > frames_count {
+            self.swap_call_frames_top(frame);
+            frame.returns_bool = matches!(operator, OverloadedOperator::Equal);
+        }
+        Ok(true)
+    }
+
+    /// Convert value to string the same way as displaying it does, but using `__str` method of instances which define it
+    fn stringify_value(
+        &mut self,
+        value: &Value,
+        frame: &mut CallFrame,
+    ) -> Result<String, VirtualMachineError> {
+        self.stringify_nested_value(value, &mut vec![], frame)
+    }
+
+    fn stringify_nested_value(
+        &mut self,
+        value: &Value,
+        containers: &mut Vec<Value>,
+        frame: &mut CallFrame,
+    ) -> Result<String, VirtualMachineError> {
+        if let Some(method) = self.find_operator_method(value, OverloadedOperator::Str) {
+            self.stack_push(value.clone());
+            let string = self.call_value_and_wait(Value::from(method), 0, frame)?;
+            return match string.get_string_object() {
+                Ok(string) => Ok(string.borrow().get_value().to_string()),
+                Err(_) => {
+                    self.runtime_error_message("__str must return a string.", frame);
+                    Err(VirtualMachineError::InvalidVariableType)
+                }
+            };
+        }
+        let (items, opening, closing) = if let Ok(list) = value.get_list_object() {
+            (list.borrow().items.clone(), "[", "]")
+        } else if let Ok(map) = value.get_map_object() {
+            let entries = map
+                .borrow()
+                .entries
+                .iter()
+                .flat_map(|(key, value)| [key.clone(), value.clone()])
+                .collect();
+            (entries, "{", "}")
+        } else {
+            return Ok(value.to_string());
+        };
+        // Container that (directly or not) contains itself is converted only once, same as when displaying it
+        if containers
+            .iter()
+            .any(|container| Value::are_values_equal(container, value))
+        {
+            return Ok(format!("{}...{}", opening, closing));
+        }
+        containers.push(value.clone());
+        // Items are put on the stack, so that they are still reachable if `__str` removes them from the container
+        let items_start = self.stack.len();
+        self.stack.extend(items);
+        let mut strings = vec![];
+        for index in items_start..self.stack.len() {
+            let item = self.stack[index].clone();
+            strings.push(self.stringify_nested_value(&item, containers, frame)?);
+        }
+        self.stack.truncate(items_start);
+        containers.pop();
+
+        let separated_items = match value.is_map_object() {
+            true => strings
+                .chunks(2)
+                .map(|entry| format!("{}: {}", entry[0], entry[1]))
+                .collect::<Vec<_>>()
+                .join(", "),
+            false => strings.join(", "),
+        };
+        Ok(format!("{}{}{}", opening, separated_items, closing))
+    }
+
+    /// Look up the property getter or setter in the table of class accessors
+    fn find_accessor(
+        accessors: &Table,
+        name: &Rc<RefCell<StringObject>>,
+    ) -> Option<Rc<RefCell<ClosureObject>>> {
+        let accessor = accessors.get(name).ok()?;
+        Some(
+            accessor
+                .get_closure_object()
+                .expect("Accessors should only be closures")
+                .clone(),
+        )
+    }
+
+    /// Call the value that is on the stack below its arguments and execute it until it returns, so that its result can be
+    /// used right away by the instruction being executed. Thrown values that aren't caught inside the call are left
+    /// for the caller to handle.
+    fn call_value_and_wait(
+        &mut self,
+        callee: Value,
+        arguments_count: u8,
+        frame: &mut CallFrame,
+    ) -> Result<Value, VirtualMachineError> {
+        if self.nested_calls.len() >= Self::MAX_NESTED_CALLS_DEPTH {
+            self.runtime_error_message("Stack overflow", frame);
+            return Err(VirtualMachineError::StackOverflow);
+        }
+        let frames_count = self.frames.len();
+        self.handle_call_value(callee, arguments_count, frame)?;
+        // Natives and classes without initializer don't need new frame, so they have already finished
+        if self.frames.len() > frames_count {
+            self.swap_call_frames_top(frame);
+            self.nested_calls.push(frames_count);
+            let result = self.run_nested_call(frames_count, frame);
+            self.nested_calls.pop();
+            result?;
+        }
+        self.stack_pop()
+    }
+
+    fn run_nested_call(
+        &mut self,
+        frames_count: usize,
+        frame: &mut CallFrame,
+    ) -> Result<(), VirtualMachineError> {
+        loop {
+            let error = match self.execute_instructions(frame) {
+                Ok(_) => return Ok(()),
+                Err(error) => error,
+            };
+            let Some(thrown_value) = self.thrown_value.take() else {
+                return Err(error);
+            };
+            // Frames above the caller belong to the nested call
+            let has_handler = !frame.handlers.is_empty()
+                || self.frames[frames_count + 1..]
+                    .iter()
+                    .any(|frame| !frame.handlers.is_empty());
+            if has_handler {
+                self.catch_thrown_value(thrown_value, frame);
+                continue;
+            }
+            // Leave every frame of the nested call, so that the caller can look for the handler
+            while self.frames.len() > frames_count {
+                self.close_upvalue(frame.stack_start);
+                self.stack.truncate(frame.stack_start);
+                *frame = self.frames.pop().expect("Caller frame should be found");
+            }
+            self.finish_unwound_coroutines();
+            self.thrown_value = Some(thrown_value);
+            return Err(error);
+        }
+    }
+
+    /// Generator function isn't executed when it's called - its closure and arguments become the stack segment of the
+    /// new coroutine, which is the result of the call
+    fn create_coroutine(&mut self, closure: Rc<RefCell<ClosureObject>>, arguments_count: u8) {
+        let stack = self
+            .stack
+            .split_off(self.stack.len() - (arguments_count as usize + 1));
+        let frame = CallFrame::new(closure.clone(), 0);
+        let coroutine =
+            Value::new_coroutine_object(closure, stack, frame, &mut self.garbage_collector);
+        self.stack_push(coroutine);
+    }
+
+    /// Continue the suspended coroutine - its stack segment is placed on top of the stack and its frame becomes the
+    /// current one, while the resuming frame waits below it for the coroutine to yield or return
+    fn resume_coroutine(
+        &mut self,
+        coroutine: Rc<RefCell<CoroutineObject>>,
+        value: Value,
+        frame: &mut CallFrame,
     ) -> Result<(), VirtualMachineError> {
-        let borrowed_class = class.borrow();
-        let method_lookup_result = borrowed_class.methods.get(method_name);
-        match method_lookup_result {
-            Ok(method) => {
-                let instance = self.stack_peek(0)?.get_instance_object().unwrap();
-                let bound_method = Value::new_bound_method_object(
-                    instance.clone(),
-                    method.get_closure_object().unwrap().clone(),
+        match coroutine.borrow().state {
+            CoroutineState::Suspended => (),
+            CoroutineState::Running => {
+                self.runtime_error_message("Can't resume running coroutine.", frame);
+                return Err(VirtualMachineError::InvalidCoroutineState);
+            }
+            CoroutineState::Finished => {
+                self.runtime_error_message("Can't resume finished coroutine.", frame);
+                return Err(VirtualMachineError::InvalidCoroutineState);
+            }
+        }
+        if self.frames.len() + 2 > self.max_call_depth {
+            self.runtime_error_message("Stack overflow", frame);
+            return Err(VirtualMachineError::StackOverflow);
+        }
+
+        let stack_start = self.stack.len();
+        let mut coroutine_borrow = coroutine.borrow_mut();
+        coroutine_borrow.state = CoroutineState::Running;
+        let mut coroutine_frame = coroutine_borrow
+            .frame
+            .take()
+            .expect("Suspended coroutine should have its frame");
+        coroutine_frame.move_to_stack_start(stack_start);
+        self.stack.append(&mut coroutine_borrow.stack);
+        self.reopen_upvalues(mem::take(&mut coroutine_borrow.upvalues), stack_start);
+        drop(coroutine_borrow);
+        // Value is the result of the `yield` the coroutine is suspended at, there is none before the first resume
+        if coroutine_frame.instruction_pointer > 0 {
+            self.stack_push(value);
+        }
+
+        self.running_coroutines.push(RunningCoroutine {
+            coroutine,
+            frames_count: self.frames.len(),
+        });
+        self.frames.push(mem::replace(frame, coroutine_frame));
+        Ok(())
+    }
+
+    /// Suspend the coroutine whose generator function is executed by the current frame, the yielded value is the
+    /// result of the `resume` executed by the frame below
+    fn yield_coroutine(&mut self, value: Value, frame: &mut CallFrame) {
+        let running = self
+            .running_coroutines
+            .pop()
+            .expect("Only generator functions can yield and they are executed only by coroutines");
+        let stack_start = frame.stack_start;
+        let upvalues = self.suspend_upvalues(stack_start);
+        let stack = self.stack.split_off(stack_start);
+        let mut coroutine_frame = mem::replace(
+            frame,
+            self.frames.pop().expect("Resuming frame should be found"),
+        );
+        coroutine_frame.move_to_stack_start(0);
+
+        let mut coroutine = running.coroutine.borrow_mut();
+        let bytes_before = coroutine.get_allocated_bytes();
+        coroutine.state = CoroutineState::Suspended;
+        coroutine.stack = stack;
+        coroutine.frame = Some(coroutine_frame);
+        coroutine.upvalues = upvalues;
+        self.garbage_collector
+            .track_growth(coroutine.get_allocated_bytes().saturating_sub(bytes_before));
+        drop(coroutine);
+        self.stack_push(value);
+    }
+
+    fn read_coroutine_property(
+        &mut self,
+        coroutine: &Rc<RefCell<CoroutineObject>>,
+        name: &Rc<RefCell<StringObject>>,
+        frame: &CallFrame,
+    ) -> Result<Value, VirtualMachineError> {
+        match name.borrow().get_value() {
+            "done" => Ok(Value::new_bool(
+                coroutine.borrow().state == CoroutineState::Finished,
+            )),
+            property_name => {
+                self.runtime_error_message(
+                    &format!("Undefined property {}.", property_name),
+                    frame,
                 );
-                // Replace instance with the bounded method on the stack
-                self.stack_pop()?;
-                self.stack_push(bound_method);
-                Ok(())
+                Err(VirtualMachineError::UndefinedProperty)
             }
-            Err(_) => Err(VirtualMachineError::UndefinedProperty),
+        }
+    }
+
+    /// Mark coroutines whose frames were removed while unwinding the stack as finished
+    fn finish_unwound_coroutines(&mut self) {
+        while self
+            .running_coroutines
+            .last()
+            .is_some_and(|running| running.frames_count >= self.frames.len())
+        {
+            let running = self.running_coroutines.pop().unwrap();
+            running.coroutine.borrow_mut().state = CoroutineState::Finished;
+        }
+    }
+
+    /// Close every upvalue of variables from the stack segment of the coroutine being suspended, remembering their
+    /// indices relative to the segment. While the coroutine is suspended, only closures can change these variables.
+    fn suspend_upvalues(&mut self, stack_start: usize) -> Vec<(usize, Rc<RefCell<UpvalueObject>>)> {
+        let upvalues: Vec<(usize, Rc<RefCell<UpvalueObject>>)> = self
+            .open_upvalues
+            .iter()
+            .filter_map(|u| {
+                let index = u.0.borrow().stack_index?;
+                (index >= stack_start).then(|| (index - stack_start, u.0.clone()))
+            })
+            .collect();
+        self.close_upvalue(stack_start);
+        upvalues
+    }
+
+    /// Reopen upvalues of the resumed coroutine, values closed while it was suspended are moved back to its stack
+    fn reopen_upvalues(
+        &mut self,
+        upvalues: Vec<(usize, Rc<RefCell<UpvalueObject>>)>,
+        stack_start: usize,
+    ) {
+        for (index, upvalue) in upvalues {
+            let variable = upvalue
+                .borrow_mut()
+                .variable
+                .take()
+                .expect("Upvalue of suspended coroutine should be closed");
+            self.stack[stack_start + index] = variable.borrow().clone();
+            upvalue.borrow_mut().stack_index = Some(stack_start + index);
+            self.open_upvalues
+                .insert(UpvalueObjectBTreeWrapper(upvalue));
         }
     }
 
@@ -1105,27 +2689,357 @@ impl VirtualMachine {
         &mut self,
         name: &Rc<RefCell<StringObject>>,
         arguments_count: u8,
-        frame: &CallFrame,
+        frame: &mut CallFrame,
     ) -> Result<(), VirtualMachineError> {
-        let instance = self
+        let instance = match self
             .stack_peek(arguments_count as usize)?
             .get_instance_object()
-            .map_err(|_| VirtualMachineError::PropertyOutsideInstance)?
-            .clone();
+        {
+            Ok(instance) => instance.clone(),
+            Err(_) => {
+                self.runtime_error_message("Only instances have properties.", frame);
+                return Err(VirtualMachineError::PropertyOutsideInstance);
+            }
+        };
 
-        let result = match instance.borrow().fields.get(name) {
+        let field = Self::find_field(&instance.borrow(), name, frame.get_inline_cache());
+        match field {
             // Check if there is field with such name
-            Ok(field) => {
+            Some(field) => {
                 let top = self.stack.len();
                 self.stack[top - arguments_count as usize - 1] = field.clone();
-                self.handle_call_value(field.clone(), arguments_count, frame)
+                self.handle_call_value(field, arguments_count, frame)
             }
-            Err(_) => {
+            None => {
                 let class = instance.borrow().class.clone();
-                self.invoke_property_from_class(&class, name, arguments_count, frame)
+                let getter = Self::find_accessor(&class.borrow().getters, name);
+                match getter {
+                    Some(getter) => {
+                        // Getter gets its own copy of the instance, as the arguments are above the original one
+                        let instance = self.stack_peek(arguments_count as usize)?.clone();
+                        self.stack_push(instance);
+                        let property = self.call_value_and_wait(Value::from(getter), 0, frame)?;
+                        let top = self.stack.len();
+                        self.stack[top - arguments_count as usize - 1] = property.clone();
+                        self.handle_call_value(property, arguments_count, frame)
+                    }
+                    None => self.invoke_property_from_class(&class, name, arguments_count, frame),
+                }
             }
+        }
+    }
+
+    fn invoke_module_export(
+        &mut self,
+        name: &Rc<RefCell<StringObject>>,
+        arguments_count: u8,
+        frame: &CallFrame,
+    ) -> Result<(), VirtualMachineError> {
+        let export = self.read_module_export(name, arguments_count as usize, frame)?;
+        let top = self.stack.len();
+        self.stack[top - arguments_count as usize - 1] = export.clone();
+        self.handle_call_value(export, arguments_count, frame)
+    }
+
+    fn invoke_static_field(
+        &mut self,
+        name: &Rc<RefCell<StringObject>>,
+        arguments_count: u8,
+        frame: &CallFrame,
+    ) -> Result<(), VirtualMachineError> {
+        let field = self.read_static_field(name, arguments_count as usize, frame)?;
+        let top = self.stack.len();
+        self.stack[top - arguments_count as usize - 1] = field.clone();
+        self.handle_call_value(field, arguments_count, frame)
+    }
+
+    /// Convert value to the index of list item, `allow_end` permits index equal to the list length
+    /// (e.g. for inserting at the end of the list)
+    fn read_list_index(
+        &mut self,
+        index: &Value,
+        length: usize,
+        allow_end: bool,
+        frame: &CallFrame,
+    ) -> Result<usize, VirtualMachineError> {
+        let index = match index.get_number() {
+            Ok(index) if index.fract() == 0.0 => index,
+            _ => {
+                self.runtime_error_message("List index must be an integer.", frame);
+                return Err(VirtualMachineError::InvalidVariableType);
+            }
+        };
+        let end = if allow_end { length + 1 } else { length };
+        if index < 0.0 || index >= end as f64 {
+            self.runtime_error_message(
+                &format!(
+                    "List index {} out of range for list of length {}.",
+                    index, length
+                ),
+                frame,
+            );
+            return Err(VirtualMachineError::IndexOutOfBounds);
+        }
+        Ok(index as usize)
+    }
+
+    fn check_map_key(&mut self, key: &Value, frame: &CallFrame) -> Result<(), VirtualMachineError> {
+        if !key.is_hashable() {
+            self.runtime_error_message("Map key must be a number, boolean, nil or string.", frame);
+            return Err(VirtualMachineError::InvalidMapKey);
+        }
+        // NaN isn't equal to anything (including itself), so the entry stored under it could never be found
+        if key.get_number().is_ok_and(f64::is_nan) {
+            self.runtime_error_message("Map key can't be NaN.", frame);
+            return Err(VirtualMachineError::InvalidMapKey);
+        }
+        Ok(())
+    }
+
+    fn read_map_value(
+        &mut self,
+        map: &Rc<RefCell<MapObject>>,
+        key: &Value,
+        frame: &CallFrame,
+    ) -> Result<Value, VirtualMachineError> {
+        self.check_map_key(key, frame)?;
+        let value = map.borrow().entries.get(key).ok().cloned();
+        match value {
+            Some(value) => Ok(value),
+            None => {
+                self.runtime_error_message(&format!("Undefined key {}.", key), frame);
+                Err(VirtualMachineError::UndefinedMapKey)
+            }
+        }
+    }
+
+    /// Check the number of arguments passed to built-in method, which accepts from `min` to `max` arguments
+    fn check_builtin_arguments_count(
+        &mut self,
+        arguments_count: u8,
+        (min, max): (usize, usize),
+        frame: &CallFrame,
+    ) -> Result<(), VirtualMachineError> {
+        if (arguments_count as usize) < min || arguments_count as usize > max {
+            let expected = if min == max {
+                min.to_string()
+            } else {
+                format!("{} or {}", min, max)
+            };
+            self.runtime_error_message(
+                &format!(
+                    "Expected {} arguments, but got {}",
+                    expected, arguments_count
+                ),
+                frame,
+            );
+            return Err(VirtualMachineError::InvalidArgumentsCount);
+        }
+        Ok(())
+    }
+
+    /// Minimal and maximal number of arguments of the built-in list method, none if there is no such method
+    fn get_list_method_arguments_range(name: &str) -> Option<(usize, usize)> {
+        match name {
+            "length" | "pop" | "sort" => Some((0, 0)),
+            "push" | "remove" => Some((1, 1)),
+            "insert" => Some((2, 2)),
+            "slice" => Some((1, 2)),
+            _ => None,
+        }
+    }
+
+    fn get_map_method_arguments_range(name: &str) -> Option<(usize, usize)> {
+        match name {
+            "length" | "keys" | "values" => Some((0, 0)),
+            "has" | "remove" => Some((1, 1)),
+            _ => None,
+        }
+    }
+
+    /// Lists and maps only have built-in methods, which aren't values on their own, so they can't be read without calling them
+    fn report_builtin_property_read(
+        &mut self,
+        name: &Rc<RefCell<StringObject>>,
+        is_list: bool,
+        frame: &CallFrame,
+    ) -> VirtualMachineError {
+        let name = name.borrow().get_value().to_string();
+        let (kind, is_method) = match is_list {
+            true => (
+                "List",
+                Self::get_list_method_arguments_range(&name).is_some(),
+            ),
+            false => ("Map", Self::get_map_method_arguments_range(&name).is_some()),
+        };
+        if !is_method {
+            self.runtime_error_message(&format!("Undefined property {}.", name), frame);
+            return VirtualMachineError::UndefinedProperty;
+        }
+        self.runtime_error_message(
+            &format!("{} method '{}' must be called directly.", kind, name),
+            frame,
+        );
+        VirtualMachineError::PropertyOutsideInstance
+    }
+
+    // Built-in list methods are called directly, the list and arguments on the stack are replaced with the result
+    fn invoke_list_method(
+        &mut self,
+        name: &Rc<RefCell<StringObject>>,
+        arguments_count: u8,
+        frame: &CallFrame,
+    ) -> Result<(), VirtualMachineError> {
+        let arguments_start = self.stack.len() - arguments_count as usize;
+        let list = self.stack[arguments_start - 1]
+            .get_list_object()
+            .unwrap()
+            .clone();
+        let arguments = self.stack[arguments_start..].to_vec();
+
+        let method_name = name.borrow().get_value().to_string();
+        let Some(arguments_range) = Self::get_list_method_arguments_range(&method_name) else {
+            self.runtime_error_message(&format!("Undefined property {}.", method_name), frame);
+            return Err(VirtualMachineError::UndefinedProperty);
+        };
+        self.check_builtin_arguments_count(arguments_count, arguments_range, frame)?;
+
+        let length = list.borrow().items.len();
+        let bytes_before = list.borrow().get_allocated_bytes();
+        let result = match method_name.as_str() {
+            "length" => Value::new_number(length as f64),
+            "push" => {
+                list.borrow_mut().items.push(arguments[0].clone());
+                Value::new_nil()
+            }
+            "pop" => match list.borrow_mut().items.pop() {
+                Some(item) => item,
+                None => {
+                    self.runtime_error_message("Can't pop from an empty list.", frame);
+                    return Err(VirtualMachineError::IndexOutOfBounds);
+                }
+            },
+            "insert" => {
+                let index = self.read_list_index(&arguments[0], length, true, frame)?;
+                list.borrow_mut().items.insert(index, arguments[1].clone());
+                Value::new_nil()
+            }
+            "remove" => {
+                let index = self.read_list_index(&arguments[0], length, false, frame)?;
+                list.borrow_mut().items.remove(index)
+            }
+            "slice" => {
+                let start = self.read_list_index(&arguments[0], length, true, frame)?;
+                let end = match arguments.get(1) {
+                    Some(end) => self.read_list_index(end, length, true, frame)?,
+                    None => length,
+                };
+                // Empty slice is returned when the end is before the start
+                let items = list.borrow().items[start..end.max(start)].to_vec();
+                Value::new_list_object(items, &mut self.garbage_collector)
+            }
+            "sort" => {
+                if let Err(e) = Self::sort_list(&mut list.borrow_mut()) {
+                    self.runtime_error_message(
+                        "Only lists of numbers or lists of strings can be sorted.",
+                        frame,
+                    );
+                    return Err(e);
+                }
+                Value::new_nil()
+            }
+            _ => unreachable!("Every list method has its arguments count checked above"),
         };
-        result
+        let bytes_after = list.borrow().get_allocated_bytes();
+        self.garbage_collector
+            .track_growth(bytes_after.saturating_sub(bytes_before));
+
+        self.stack.truncate(arguments_start - 1);
+        self.stack_push(result);
+        Ok(())
+    }
+
+    // Built-in map methods are called the same way as list methods
+    fn invoke_map_method(
+        &mut self,
+        name: &Rc<RefCell<StringObject>>,
+        arguments_count: u8,
+        frame: &CallFrame,
+    ) -> Result<(), VirtualMachineError> {
+        let arguments_start = self.stack.len() - arguments_count as usize;
+        let map = self.stack[arguments_start - 1]
+            .get_map_object()
+            .unwrap()
+            .clone();
+        let arguments = self.stack[arguments_start..].to_vec();
+
+        let method_name = name.borrow().get_value().to_string();
+        let Some(arguments_range) = Self::get_map_method_arguments_range(&method_name) else {
+            self.runtime_error_message(&format!("Undefined property {}.", method_name), frame);
+            return Err(VirtualMachineError::UndefinedProperty);
+        };
+        self.check_builtin_arguments_count(arguments_count, arguments_range, frame)?;
+
+        let result = match method_name.as_str() {
+            "length" => Value::new_number(map.borrow().entries.iter().count() as f64),
+            // Keys and values are returned in the same order, so that they can be iterated together
+            "keys" => {
+                let keys = map
+                    .borrow()
+                    .entries
+                    .iter()
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                Value::new_list_object(keys, &mut self.garbage_collector)
+            }
+            "values" => {
+                let values = map
+                    .borrow()
+                    .entries
+                    .iter()
+                    .map(|(_, value)| value.clone())
+                    .collect();
+                Value::new_list_object(values, &mut self.garbage_collector)
+            }
+            "has" => {
+                self.check_map_key(&arguments[0], frame)?;
+                Value::new_bool(map.borrow().entries.get(&arguments[0]).is_ok())
+            }
+            "remove" => {
+                let value = self.read_map_value(&map, &arguments[0], frame)?;
+                map.borrow_mut()
+                    .entries
+                    .remove(&arguments[0])
+                    .expect("Key was just found in the map");
+                value
+            }
+            _ => unreachable!("Every map method has its arguments count checked above"),
+        };
+
+        self.stack.truncate(arguments_start - 1);
+        self.stack_push(result);
+        Ok(())
+    }
+
+    fn sort_list(list: &mut ListObject) -> Result<(), VirtualMachineError> {
+        if list.items.iter().all(Value::is_number) {
+            list.items.sort_by(|lhs, rhs| {
+                lhs.get_number()
+                    .unwrap()
+                    .total_cmp(&rhs.get_number().unwrap())
+            });
+            return Ok(());
+        }
+        if list.items.iter().all(Value::is_string_object) {
+            list.items.sort_by(|lhs, rhs| {
+                let lhs = lhs.get_string_object().unwrap();
+                let rhs = rhs.get_string_object().unwrap();
+                let ordering = lhs.borrow().get_value().cmp(rhs.borrow().get_value());
+                ordering
+            });
+            return Ok(());
+        }
+        Err(VirtualMachineError::InvalidVariableType)
     }
 
     fn invoke_property_from_class(
@@ -1135,13 +3049,9 @@ impl VirtualMachine {
         arguments_count: u8,
         frame: &CallFrame,
     ) -> Result<(), VirtualMachineError> {
-        match class.borrow().methods.get(name) {
-            Ok(method) => self.handle_function_call(
-                method.get_closure_object().unwrap().clone(),
-                arguments_count,
-                Some(frame),
-            ),
-            Err(_) => {
+        match Self::find_method(class, name, frame.get_inline_cache()) {
+            Some(method) => self.handle_function_call(method, arguments_count, Some(frame)),
+            None => {
                 self.runtime_error_message(
                     &format!("Undefined property {}.", name.borrow().get_value()),
                     frame,